@@ -22,7 +22,9 @@ import {
   useMantineColorScheme,
 } from '@mantine/core';
 import { Notifications, notifications } from '@mantine/notifications';
-import { IconSun, IconMoon, IconPower } from '@tabler/icons-react';
+import { IconSun, IconMoon, IconPower, IconSettings } from '@tabler/icons-react';
+import { PluginSettingsForm } from './plugins/PluginSettingsForm';
+import { errorMessage, isErrorPayload } from './plugins/errors';
 import '@mantine/core/styles.css';
 import '@mantine/notifications/styles.css';
 
@@ -31,6 +33,11 @@ interface PluginInfo {
   version: string;
 }
 
+interface PluginLoadFailure {
+  path: string;
+  reason: string;
+}
+
 interface CompressionOptions {
   mode: 'Binary' | 'Text';
   split_size: number | null;
@@ -54,6 +61,7 @@ function App() {
   const [existingFiles, setExistingFiles] = useState<string[]>([]);
   const [showOverwriteDialog, setShowOverwriteDialog] = useState(false);
   const [errorMessage, setErrorMessage] = useState<string | null>(null);
+  const [showPluginSettings, setShowPluginSettings] = useState(false);
 
   useEffect(() => {
     async function loadPlugins() {
@@ -61,6 +69,16 @@ function App() {
         const pluginList = await invoke<PluginInfo[]>('list_plugins');
         console.log('Loaded plugins:', pluginList);
         setPlugins(pluginList);
+
+        const failures = await invoke<PluginLoadFailure[]>('get_plugin_load_failures');
+        failures.forEach((failure) => {
+          notifications.show({
+            title: 'Plugin not loaded',
+            message: `${failure.path}: ${failure.reason}`,
+            color: 'orange',
+            autoClose: false
+          });
+        });
       } catch (e) {
         console.error('Failed to load plugins:', e);
         setErrorMessage(`Failed to load plugins: ${e}`);
@@ -130,7 +148,7 @@ function App() {
         console.error('Decompression error:', e);
         notifications.show({
           title: 'Error',
-          message: `Decompression failed: ${e}`,
+          message: `Decompression failed: ${errorMessage(e)}`,
           color: 'red'
         });
       }
@@ -156,7 +174,7 @@ function App() {
       console.error('Decompression error:', e);
       notifications.show({
         title: 'Error',
-        message: `Decompression failed: ${e}`,
+        message: `Decompression failed: ${errorMessage(e)}`,
         color: 'red'
       });
     }
@@ -221,9 +239,7 @@ function App() {
       });
     } catch (e) {
       console.error('Compression error:', e);
-      const errorMsg = String(e);
-      
-      if (errorMsg.includes('Archive already exists')) {
+      if (isErrorPayload(e) && e.code === 'already_exists') {
         notifications.show({
           title: 'Warning',
           message: 'An archive with this name already exists. Please remove it first or choose a different output location.',
@@ -232,7 +248,7 @@ function App() {
       } else {
         notifications.show({
           title: 'Error',
-          message: `Compression failed: ${e}`,
+          message: `Compression failed: ${errorMessage(e)}`,
           color: 'red'
         });
       }
@@ -277,13 +293,25 @@ function App() {
             )}
 
             {/* Plugin Selection */}
-            <Select
-              label="Select Compression Format"
-              placeholder="Choose a format"
-              value={selectedPlugin}
-              onChange={setSelectedPlugin}
-              data={plugins.map(p => ({ value: p.name, label: p.name }))}
-            />
+            <Group align="flex-end">
+              <Select
+                label="Select Compression Format"
+                placeholder="Choose a format"
+                value={selectedPlugin}
+                onChange={setSelectedPlugin}
+                data={plugins.map(p => ({ value: p.name, label: p.name }))}
+                style={{ flex: 1 }}
+              />
+              <ActionIcon
+                variant="default"
+                size={36}
+                onClick={() => setShowPluginSettings(true)}
+                disabled={!selectedPlugin}
+                title="Plugin settings"
+              >
+                <IconSettings size={16} />
+              </ActionIcon>
+            </Group>
 
             <Divider />
 
@@ -368,6 +396,24 @@ function App() {
         </Group>
       </Modal>
 
+      {/* Plugin Settings Dialog */}
+      <Modal
+        opened={showPluginSettings && !!selectedPlugin}
+        onClose={() => setShowPluginSettings(false)}
+        title={`${selectedPlugin} settings`}
+      >
+        {selectedPlugin && (
+          <PluginSettingsForm
+            plugin={selectedPlugin}
+            onSaved={() => notifications.show({
+              title: 'Saved',
+              message: `Settings of ${selectedPlugin} saved`,
+              color: 'green'
+            })}
+          />
+        )}
+      </Modal>
+
       {/* Error Dialog */}
       <Modal
         opened={!!errorMessage}
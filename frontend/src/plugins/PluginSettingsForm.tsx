@@ -0,0 +1,148 @@
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/tauri';
+import { open } from '@tauri-apps/api/dialog';
+import { Button, Group, NumberInput, Select, Stack, Switch, Text, TextInput } from '@mantine/core';
+import { errorMessage } from './errors';
+import type { PluginSettingsView, SettingDescriptor, SettingValue } from './types';
+
+interface SettingFieldProps {
+  setting: SettingDescriptor;
+  value: SettingValue;
+  onChange: (value: SettingValue) => void;
+}
+
+// Renders the control matching the setting's `type`, so any plugin's schema can be edited
+const SettingField: React.FC<SettingFieldProps> = ({ setting, value, onChange }) => {
+  const common = { label: setting.label, description: setting.description || undefined };
+
+  switch (setting.type) {
+    case 'boolean':
+      return (
+        <Switch
+          {...common}
+          checked={Boolean(value)}
+          onChange={(event) => onChange(event.currentTarget.checked)}
+        />
+      );
+    case 'integer':
+    case 'number':
+      return (
+        <NumberInput
+          {...common}
+          value={Number(value)}
+          min={setting.min ?? undefined}
+          max={setting.max ?? undefined}
+          allowDecimal={setting.type === 'number'}
+          onChange={(next) => typeof next === 'number' && onChange(next)}
+        />
+      );
+    case 'choice':
+      return (
+        <Select
+          {...common}
+          value={String(value)}
+          data={setting.options}
+          allowDeselect={false}
+          onChange={(next) => next !== null && onChange(next)}
+        />
+      );
+    case 'path':
+      return (
+        <Group align="flex-end">
+          <TextInput
+            {...common}
+            style={{ flex: 1 }}
+            value={String(value)}
+            onChange={(event) => onChange(event.currentTarget.value)}
+          />
+          <Button
+            variant="default"
+            onClick={async () => {
+              const selected = await open({ multiple: false });
+              if (typeof selected === 'string') {
+                onChange(selected);
+              }
+            }}
+          >
+            Browse
+          </Button>
+        </Group>
+      );
+    default:
+      return (
+        <TextInput
+          {...common}
+          value={String(value)}
+          onChange={(event) => onChange(event.currentTarget.value)}
+        />
+      );
+  }
+};
+
+interface PluginSettingsFormProps {
+  plugin: string;
+  onSaved?: () => void;
+}
+
+export const PluginSettingsForm: React.FC<PluginSettingsFormProps> = ({ plugin, onSaved }) => {
+  const [view, setView] = useState<PluginSettingsView | null>(null);
+  const [values, setValues] = useState<Record<string, SettingValue>>({});
+  const [error, setError] = useState<string | null>(null);
+  const [saving, setSaving] = useState(false);
+
+  useEffect(() => {
+    setView(null);
+    setError(null);
+    invoke<PluginSettingsView>('get_plugin_settings', { name: plugin })
+      .then((loaded) => {
+        setView(loaded);
+        setValues(loaded.values);
+      })
+      .catch((e) => setError(errorMessage(e)));
+  }, [plugin]);
+
+  const handleSave = async () => {
+    setSaving(true);
+    setError(null);
+    try {
+      const saved = await invoke<PluginSettingsView>('set_plugin_settings', { name: plugin, values });
+      setView(saved);
+      setValues(saved.values);
+      onSaved?.();
+    } catch (e) {
+      setError(errorMessage(e));
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  if (!view) {
+    return error ? <Text c="red" size="sm">{error}</Text> : <Text size="sm">Loading settings...</Text>;
+  }
+
+  if (!view.schema.settings.length) {
+    return <Text size="sm" c="dimmed">This plugin has no settings.</Text>;
+  }
+
+  return (
+    <Stack>
+      {view.schema.settings.map((setting) => (
+        <SettingField
+          key={setting.key}
+          setting={setting}
+          value={values[setting.key] ?? setting.default}
+          onChange={(value) => setValues((prev) => ({ ...prev, [setting.key]: value }))}
+        />
+      ))}
+      {error && <Text c="red" size="sm">{error}</Text>}
+      <Group justify="flex-end">
+        <Button variant="default" onClick={() => setValues(view.values)} disabled={saving}>
+          Reset
+        </Button>
+        <Button onClick={handleSave} loading={saving}>
+          Save
+        </Button>
+      </Group>
+    </Stack>
+  );
+};
@@ -0,0 +1,32 @@
+// Mirrors `ErrorCode` in plugin_api/error.rs
+export type ErrorCode =
+  | 'not_implemented'
+  | 'not_found'
+  | 'already_exists'
+  | 'invalid_input'
+  | 'io'
+  | 'corrupt_archive'
+  | 'wrong_password'
+  | 'unsupported_feature'
+  | 'path_traversal'
+  | 'limit_exceeded'
+  | 'disk_full'
+  | 'cancelled'
+  | 'panicked'
+  | 'other';
+
+// What plugin commands reject with
+export interface ErrorPayload {
+  code: ErrorCode;
+  message: string;
+  path?: string;
+  plugin?: string;
+}
+
+export function isErrorPayload(error: unknown): error is ErrorPayload {
+  return typeof error === 'object' && error !== null && 'code' in error && 'message' in error;
+}
+
+export function errorMessage(error: unknown): string {
+  return isErrorPayload(error) ? error.message : String(error);
+}
@@ -20,3 +20,25 @@ export interface CompressionOptions {
   mode: 'Binary' | 'Preserve';
   splitSize?: number;
 }
+
+export type SettingKind =
+  | { type: 'boolean' }
+  | { type: 'integer'; min?: number | null; max?: number | null }
+  | { type: 'number'; min?: number | null; max?: number | null }
+  | { type: 'string' }
+  | { type: 'path' }
+  | { type: 'choice'; options: string[] };
+
+export type SettingValue = boolean | number | string;
+
+export type SettingDescriptor = SettingKind & {
+  key: string;
+  label: string;
+  description: string;
+  default: SettingValue;
+};
+
+export interface PluginSettingsView {
+  schema: { settings: SettingDescriptor[] };
+  values: Record<string, SettingValue>;
+}
@@ -0,0 +1,297 @@
+// Stable C ABI between the core and dynamically loaded plugin libraries.
+//
+// Rust trait objects have no stable layout, so a library never hands out a
+// `Box<dyn Plugin>`. Instead `_plugin_create` returns a `PluginHandle`: an
+// opaque instance pointer plus a table of `extern "C"` functions. Structured
+// arguments cross the boundary as JSON, and every buffer is freed by the
+// library that allocated it.
+
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use crate::base::{panic_message, Plugin, PluginError};
+use crate::compression::CompressionPlugin;
+use crate::extract::ExtractionLimits;
+use crate::settings::PluginSettings;
+use crate::types::CompressionOptions;
+
+// Bump whenever the layout of `PluginHandle` or `PluginVTable` changes
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"_plugin_abi_version";
+pub const CREATE_SYMBOL: &[u8] = b"_plugin_create";
+
+pub const ABI_OK: i32 = 0;
+// The error buffer holds the `PluginError`, see `PluginError::encode`
+pub const ABI_ERROR: i32 = 1;
+pub const ABI_UNSUPPORTED: i32 = 2;
+// The error buffer holds the panic message
+pub const ABI_PANICKED: i32 = 3;
+
+pub const CAP_COMPRESSION: u32 = 1;
+
+pub type AbiVersionFn = unsafe extern "C" fn() -> u32;
+pub type CreateFn = unsafe extern "C" fn() -> PluginHandle;
+
+// A byte buffer owned by the library that allocated it
+#[repr(C)]
+pub struct FfiBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl FfiBuffer {
+    pub fn empty() -> Self {
+        Self {
+            ptr: std::ptr::null_mut(),
+            len: 0,
+            cap: 0,
+        }
+    }
+
+    pub fn from_string(value: String) -> Self {
+        let mut bytes = std::mem::ManuallyDrop::new(value.into_bytes());
+        Self {
+            ptr: bytes.as_mut_ptr(),
+            len: bytes.len(),
+            cap: bytes.capacity(),
+        }
+    }
+
+    /// # Safety
+    /// The buffer must be empty or point to `len` initialised bytes.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+
+    // Only valid in the library that created the buffer
+    unsafe fn into_vec(self) -> Vec<u8> {
+        if self.ptr.is_null() {
+            Vec::new()
+        } else {
+            Vec::from_raw_parts(self.ptr, self.len, self.cap)
+        }
+    }
+}
+
+// A borrowed byte slice passed from the core into a plugin call
+#[repr(C)]
+pub struct FfiStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl FfiStr {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    /// # Safety
+    /// The slice must still be alive, which holds for the duration of the call it was passed to.
+    pub unsafe fn as_bytes(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(self.ptr, self.len)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginVTable {
+    pub metadata: unsafe extern "C" fn(*mut c_void) -> FfiBuffer,
+    // JSON `SettingsSchema`
+    pub settings_schema: unsafe extern "C" fn(*mut c_void) -> FfiBuffer,
+    // Takes the JSON `PluginSettings` object
+    pub initialize: unsafe extern "C" fn(*mut c_void, FfiStr, *mut FfiBuffer) -> i32,
+    pub cleanup: unsafe extern "C" fn(*mut c_void, *mut FfiBuffer) -> i32,
+    pub compress: unsafe extern "C" fn(*mut c_void, FfiStr, *mut FfiBuffer) -> i32,
+    pub decompress: unsafe extern "C" fn(*mut c_void, FfiStr, *mut FfiBuffer) -> i32,
+    pub free_buffer: unsafe extern "C" fn(FfiBuffer),
+    pub destroy: unsafe extern "C" fn(*mut c_void),
+}
+
+#[repr(C)]
+pub struct PluginHandle {
+    pub abi_version: u32,
+    pub capabilities: u32,
+    pub instance: *mut c_void,
+    pub vtable: PluginVTable,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompressRequest {
+    pub input_files: Vec<PathBuf>,
+    pub output_file: PathBuf,
+    pub options: CompressionOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DecompressRequest {
+    pub archive_file: PathBuf,
+    pub output_dir: PathBuf,
+    pub overwrite: bool,
+    // Missing from hosts built before limits were passed on
+    #[serde(default)]
+    pub limits: ExtractionLimits,
+}
+
+// Unwinding across `extern "C"` aborts the process, so every call is caught here
+fn guarded<F>(error_out: *mut FfiBuffer, call: F) -> i32
+where
+    F: FnOnce() -> Result<(), PluginError>,
+{
+    let (status, message) = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => return ABI_OK,
+        Ok(Err(e)) => (ABI_ERROR, e.encode()),
+        Err(payload) => (ABI_PANICKED, panic_message(payload.as_ref())),
+    };
+    if !error_out.is_null() {
+        unsafe { error_out.write(FfiBuffer::from_string(message)) };
+    }
+    status
+}
+
+fn parse_request<T: for<'de> Deserialize<'de>>(request: &FfiStr) -> Result<T, PluginError> {
+    serde_json::from_slice(unsafe { request.as_bytes() })
+        .map_err(|e| PluginError::InvalidInput(format!("Malformed plugin request: {}", e)))
+}
+
+unsafe extern "C" fn metadata_shim<P: Plugin>(instance: *mut c_void) -> FfiBuffer {
+    let plugin = &*(instance as *const P);
+    match catch_unwind(AssertUnwindSafe(|| serde_json::to_string(&plugin.metadata()))) {
+        Ok(Ok(json)) => FfiBuffer::from_string(json),
+        _ => FfiBuffer::empty(),
+    }
+}
+
+unsafe extern "C" fn settings_schema_shim<P: Plugin>(instance: *mut c_void) -> FfiBuffer {
+    let plugin = &*(instance as *const P);
+    match catch_unwind(AssertUnwindSafe(|| serde_json::to_string(&plugin.settings_schema()))) {
+        Ok(Ok(json)) => FfiBuffer::from_string(json),
+        _ => FfiBuffer::empty(),
+    }
+}
+
+unsafe extern "C" fn initialize_shim<P: Plugin>(
+    instance: *mut c_void,
+    settings: FfiStr,
+    error_out: *mut FfiBuffer,
+) -> i32 {
+    let plugin = &mut *(instance as *mut P);
+    guarded(error_out, || {
+        let settings: PluginSettings = parse_request(&settings)?;
+        plugin.initialize(&settings)
+    })
+}
+
+unsafe extern "C" fn cleanup_shim<P: Plugin>(instance: *mut c_void, error_out: *mut FfiBuffer) -> i32 {
+    let plugin = &mut *(instance as *mut P);
+    guarded(error_out, || plugin.cleanup())
+}
+
+unsafe extern "C" fn compress_shim<P: CompressionPlugin>(
+    instance: *mut c_void,
+    request: FfiStr,
+    error_out: *mut FfiBuffer,
+) -> i32 {
+    let plugin = &*(instance as *const P);
+    guarded(error_out, || {
+        let request: CompressRequest = parse_request(&request)?;
+        plugin.compress(&request.input_files, &request.output_file, &request.options)
+    })
+}
+
+unsafe extern "C" fn decompress_shim<P: CompressionPlugin>(
+    instance: *mut c_void,
+    request: FfiStr,
+    error_out: *mut FfiBuffer,
+) -> i32 {
+    let plugin = &*(instance as *const P);
+    guarded(error_out, || {
+        let request: DecompressRequest = parse_request(&request)?;
+        plugin.decompress(&request.archive_file, &request.output_dir, request.overwrite, request.limits)
+    })
+}
+
+unsafe extern "C" fn unsupported_shim(_instance: *mut c_void, _request: FfiStr, _error_out: *mut FfiBuffer) -> i32 {
+    ABI_UNSUPPORTED
+}
+
+unsafe extern "C" fn free_buffer_shim(buffer: FfiBuffer) {
+    drop(buffer.into_vec());
+}
+
+unsafe extern "C" fn destroy_shim<P: Plugin>(instance: *mut c_void) {
+    drop(Box::from_raw(instance as *mut P));
+}
+
+pub fn export_plugin<P: Plugin>(plugin: P) -> PluginHandle {
+    PluginHandle {
+        abi_version: PLUGIN_ABI_VERSION,
+        capabilities: 0,
+        instance: Box::into_raw(Box::new(plugin)) as *mut c_void,
+        vtable: PluginVTable {
+            metadata: metadata_shim::<P>,
+            settings_schema: settings_schema_shim::<P>,
+            initialize: initialize_shim::<P>,
+            cleanup: cleanup_shim::<P>,
+            compress: unsupported_shim,
+            decompress: unsupported_shim,
+            free_buffer: free_buffer_shim,
+            destroy: destroy_shim::<P>,
+        },
+    }
+}
+
+pub fn export_compression_plugin<P: CompressionPlugin>(plugin: P) -> PluginHandle {
+    let mut handle = export_plugin(plugin);
+    handle.capabilities |= CAP_COMPRESSION;
+    handle.vtable.compress = compress_shim::<P>;
+    handle.vtable.decompress = decompress_shim::<P>;
+    handle
+}
+
+// Exports `_plugin_abi_version` and `_plugin_create` for a plugin that only implements `Plugin`
+#[macro_export]
+macro_rules! declare_plugin {
+    ($plugin_type:ty, $constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn _plugin_abi_version() -> u32 {
+            $crate::abi::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn _plugin_create() -> $crate::abi::PluginHandle {
+            let plugin: $plugin_type = $constructor();
+            $crate::abi::export_plugin(plugin)
+        }
+    };
+}
+
+// Same as `declare_plugin!`, additionally exposing the `CompressionPlugin` calls
+#[macro_export]
+macro_rules! declare_compression_plugin {
+    ($plugin_type:ty, $constructor:expr) => {
+        #[no_mangle]
+        pub extern "C" fn _plugin_abi_version() -> u32 {
+            $crate::abi::PLUGIN_ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn _plugin_create() -> $crate::abi::PluginHandle {
+            let plugin: $plugin_type = $constructor();
+            $crate::abi::export_compression_plugin(plugin)
+        }
+    };
+}
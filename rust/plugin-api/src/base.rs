@@ -0,0 +1,50 @@
+use std::any::Any;
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use crate::compression::{AsyncCompressionPlugin, CompressionPlugin};
+pub use crate::error::{panic_message, PluginError};
+use crate::settings::{PluginSettings, SettingsSchema};
+use crate::transfer::TransferPlugin;
+use crate::types::{PluginType, PluginMetadata};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    pub plugin_type: PluginType,
+}
+
+pub trait Plugin: Send + Sync + Any {
+    fn get_config(&self) -> &PluginConfig;
+    fn metadata(&self) -> PluginMetadata;
+
+    // Settings the plugin accepts; the core persists them and checks values against it
+    fn settings_schema(&self) -> SettingsSchema {
+        SettingsSchema::default()
+    }
+
+    // `settings` has a valid value for every key in `settings_schema`
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError>;
+    fn cleanup(&mut self) -> Result<(), PluginError>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    // Capability accessors. `as_any` only reaches the concrete type, so a `dyn Plugin` cannot
+    // be downcast to another trait object; plugins that implement one return `Some(self)`.
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        None
+    }
+
+    fn as_async_compression(&self) -> Option<&dyn AsyncCompressionPlugin> {
+        None
+    }
+
+    fn as_transfer(&self) -> Option<&dyn TransferPlugin> {
+        None
+    }
+}
+
+pub trait PluginFactory: Send + Sync {
+    fn create(&self) -> Result<Box<dyn Plugin>, PluginError>;
+}
@@ -0,0 +1,220 @@
+use super::base::{Plugin, PluginError};
+use super::extract::ExtractionLimits;
+use super::types::CompressionOptions;
+use async_trait::async_trait;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+
+// Plugins return `UnsupportedFeature` for kinds their format cannot store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink { target: String },
+}
+
+// Formats that cannot store a field leave it out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntryMetadata {
+    pub modified: Option<SystemTime>,
+    // Unix permission bits, e.g. 0o644
+    pub mode: Option<u32>,
+}
+
+// An entry handed out by `read_entries`, its data is passed alongside
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub name: String,
+    pub kind: EntryKind,
+    pub metadata: EntryMetadata,
+}
+
+// One entry handed to `compress_stream`
+pub struct StreamEntry<'a> {
+    // Path inside the archive, `/`-separated
+    pub name: String,
+    pub kind: EntryKind,
+    pub metadata: EntryMetadata,
+    // Empty for directories and symlinks
+    pub reader: Box<dyn Read + Send + 'a>,
+}
+
+impl<'a> StreamEntry<'a> {
+    pub fn new(name: impl Into<String>, reader: impl Read + Send + 'a) -> Self {
+        Self {
+            name: name.into(),
+            kind: EntryKind::File,
+            metadata: EntryMetadata::default(),
+            reader: Box::new(reader),
+        }
+    }
+
+    pub fn directory(name: impl Into<String>) -> Self {
+        Self {
+            kind: EntryKind::Directory,
+            ..Self::new(name, io::empty())
+        }
+    }
+
+    pub fn symlink(name: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            kind: EntryKind::Symlink { target: target.into() },
+            ..Self::new(name, io::empty())
+        }
+    }
+
+    pub fn from_info(info: EntryInfo, reader: impl Read + Send + 'a) -> Self {
+        Self {
+            name: info.name,
+            kind: info.kind,
+            metadata: info.metadata,
+            reader: Box::new(reader),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: EntryMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+// Output of `compress_seekable`, anything that can both write and seek, such as a file
+pub trait WriteSeek: Write + Seek {}
+
+impl<T: Write + Seek + ?Sized> WriteSeek for T {}
+
+pub trait CompressionPlugin: Plugin {
+    fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError>;
+
+    // Extraction stops with `LimitExceeded` once it goes past `limits`
+    fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError>;
+
+    // Stream variants, for archives that go to or come from something other than a file,
+    // such as a connection. Entries are pulled one at a time, so they can be opened lazily.
+    // Formats that write their index with seeks or keep it at the end, like zip and 7z, may
+    // hold the whole archive in memory here; prefer `compress_seekable` for files.
+    fn compress_stream(
+        &self,
+        _entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        _output: &mut dyn Write,
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        Err(PluginError::NotImplemented)
+    }
+
+    // `compress_stream` for outputs that can seek, which formats written with seeks use to
+    // write the archive straight to `output`
+    fn compress_seekable(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        mut output: &mut dyn WriteSeek,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        self.compress_stream(entries, &mut output, options)
+    }
+
+    fn decompress_stream(
+        &self,
+        _input: &mut dyn Read,
+        _output_dir: &Path,
+        _overwrite: bool,
+        _limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        Err(PluginError::NotImplemented)
+    }
+
+    // Hands every entry of `archive_file` to `visit` in archive order, with a reader over its
+    // data; directories and symlinks come with an empty one. Stops at the first error from
+    // `visit`. Entry names are `/`-separated, without a trailing `/` for directories.
+    fn read_entries(
+        &self,
+        _archive_file: &Path,
+        _visit: &mut dyn FnMut(EntryInfo, &mut dyn Read) -> Result<(), PluginError>,
+    ) -> Result<(), PluginError> {
+        Err(PluginError::NotImplemented)
+    }
+
+    // Changes an existing archive in place. `entries` are added, replacing any entry of the
+    // same name; names in `remove` are deleted along with everything below them, and a name
+    // that matches nothing is `NotFound`. The archive is left as it was if the update fails.
+    fn update(
+        &self,
+        _archive_file: &Path,
+        _entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        _remove: &[String],
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        Err(PluginError::NotImplemented)
+    }
+}
+
+// Whether `entry` goes away when `removed` is deleted: the entry itself, or one below it
+// when `removed` is a directory
+pub fn is_removed(entry: &str, removed: &str) -> bool {
+    let removed = removed.trim_end_matches('/');
+    let entry = entry.trim_end_matches('/');
+    entry == removed || entry.strip_prefix(removed).is_some_and(|rest| rest.starts_with('/'))
+}
+
+// A temp file next to `path` to write a new version of it in, see `persist_replacement`.
+// Unlike a plain `NamedTempFile` it is not private, but gets what a new file would.
+pub fn replacement_file(path: &Path) -> Result<NamedTempFile, PluginError> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // Narrowed by the umask
+        builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    builder.tempfile_in(dir).map_err(|e| PluginError::io(dir, e))
+}
+
+// Moves a finished `replacement_file` over `path`, keeping the permissions of the file
+// it replaces
+pub fn persist_replacement(temp: NamedTempFile, path: &Path) -> Result<(), PluginError> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => temp
+            .as_file()
+            .set_permissions(metadata.permissions())
+            .map_err(|e| PluginError::io(path, e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(PluginError::io(path, e)),
+    }
+    temp.persist(path).map_err(|e| PluginError::io(path, e.error))?;
+    Ok(())
+}
+
+// For plugins whose work is naturally async, e.g. waiting on a remote service. They are
+// awaited on the core's runtime, so they must not block it; plain `CompressionPlugin`s are
+// moved to the blocking pool by `plugins::AsyncCompression` instead.
+#[async_trait]
+pub trait AsyncCompressionPlugin: Plugin {
+    async fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError>;
+
+    async fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError>;
+}
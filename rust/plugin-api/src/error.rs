@@ -0,0 +1,447 @@
+use std::any::Any;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+// Stable identifiers the frontend and other plugins can match on, unlike the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotImplemented,
+    NotFound,
+    AlreadyExists,
+    InvalidInput,
+    Io,
+    CorruptArchive,
+    WrongPassword,
+    UnsupportedFeature,
+    PathTraversal,
+    LimitExceeded,
+    DiskFull,
+    Cancelled,
+    Panicked,
+    Other,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NotImplemented => "not_implemented",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::AlreadyExists => "already_exists",
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::Io => "io",
+            ErrorCode::CorruptArchive => "corrupt_archive",
+            ErrorCode::WrongPassword => "wrong_password",
+            ErrorCode::UnsupportedFeature => "unsupported_feature",
+            ErrorCode::PathTraversal => "path_traversal",
+            ErrorCode::LimitExceeded => "limit_exceeded",
+            ErrorCode::DiskFull => "disk_full",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Panicked => "panicked",
+            ErrorCode::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// The messages only describe the error itself; the underlying cause is reachable through
+// `source()`, so print the whole chain (`{:#}` on anyhow, or `ErrorPayload`) when reporting.
+#[derive(Debug, thiserror::Error)]
+pub enum PluginError {
+    #[error("Operation not implemented")]
+    NotImplemented,
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("I/O error{}", on(path))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Archive{} is corrupt", named(path))]
+    CorruptArchive {
+        path: Option<PathBuf>,
+        #[source]
+        source: Option<BoxError>,
+    },
+    #[error("Wrong or missing password{}", on(path))]
+    WrongPassword { path: Option<PathBuf> },
+    #[error("Unsupported: {0}")]
+    UnsupportedFeature(String),
+    #[error("Archive entry '{entry}' would be extracted outside the output directory")]
+    PathTraversal { entry: String },
+    // An extraction limit was reached, the archive may be a decompression bomb
+    #[error("Extraction stopped: {0}")]
+    LimitExceeded(String),
+    #[error("Not enough disk space{}", on(path))]
+    DiskFull {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Operation cancelled")]
+    Cancelled,
+    // The plugin panicked during a call; it stays disabled until it is reinitialized
+    #[error("Plugin '{plugin}' panicked: {message}")]
+    Panicked { plugin: String, message: String },
+    #[error("{message}")]
+    Other {
+        message: String,
+        #[source]
+        source: Option<BoxError>,
+    },
+}
+
+fn on(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|path| format!(" on {}", path.display())).unwrap_or_default()
+}
+
+fn named(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|path| format!(" {}", path.display())).unwrap_or_default()
+}
+
+impl PluginError {
+    pub fn other(message: impl Into<String>) -> Self {
+        PluginError::Other { message: message.into(), source: None }
+    }
+
+    pub fn with_source(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        PluginError::Other { message: message.into(), source: Some(source.into()) }
+    }
+
+    // Running out of space is worth telling apart from other write failures
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        let path = Some(path.as_ref().to_path_buf());
+        if is_disk_full(&source) {
+            PluginError::DiskFull { path, source }
+        } else {
+            PluginError::Io { path, source }
+        }
+    }
+
+    pub fn corrupt(path: impl AsRef<Path>, source: impl Into<BoxError>) -> Self {
+        PluginError::CorruptArchive {
+            path: Some(path.as_ref().to_path_buf()),
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn from_panic(plugin: &str, payload: Box<dyn Any + Send>) -> Self {
+        PluginError::Panicked {
+            plugin: plugin.to_string(),
+            message: panic_message(payload.as_ref()),
+        }
+    }
+
+    // Errors reported across the plugin boundary are encoded with `encode` when the plugin
+    // was built against this crate; anything else is kept as plain text
+    pub fn decode(message: String) -> Self {
+        serde_json::from_str(&message).unwrap_or_else(|_| PluginError::other(message))
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap_or_else(|_| self.report())
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            PluginError::NotImplemented => ErrorCode::NotImplemented,
+            PluginError::NotFound(_) => ErrorCode::NotFound,
+            PluginError::AlreadyExists(_) => ErrorCode::AlreadyExists,
+            PluginError::InvalidInput(_) => ErrorCode::InvalidInput,
+            PluginError::Io { .. } => ErrorCode::Io,
+            PluginError::CorruptArchive { .. } => ErrorCode::CorruptArchive,
+            PluginError::WrongPassword { .. } => ErrorCode::WrongPassword,
+            PluginError::UnsupportedFeature(_) => ErrorCode::UnsupportedFeature,
+            PluginError::PathTraversal { .. } => ErrorCode::PathTraversal,
+            PluginError::LimitExceeded(_) => ErrorCode::LimitExceeded,
+            PluginError::DiskFull { .. } => ErrorCode::DiskFull,
+            PluginError::Cancelled => ErrorCode::Cancelled,
+            PluginError::Panicked { .. } => ErrorCode::Panicked,
+            PluginError::Other { .. } => ErrorCode::Other,
+        }
+    }
+
+    // The file the error is about, if it is about one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            PluginError::Io { path, .. }
+            | PluginError::CorruptArchive { path, .. }
+            | PluginError::WrongPassword { path }
+            | PluginError::DiskFull { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    // The message followed by every cause, like anyhow's `{:#}`
+    pub fn report(&self) -> String {
+        report(self)
+    }
+}
+
+fn report(error: &dyn StdError) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+fn is_disk_full(error: &io::Error) -> bool {
+    if error.kind() == io::ErrorKind::StorageFull {
+        return true;
+    }
+    // ENOSPC, and ERROR_HANDLE_DISK_FULL / ERROR_DISK_FULL on Windows
+    match error.raw_os_error() {
+        Some(code) if cfg!(windows) => code == 39 || code == 112,
+        Some(code) => code == 28,
+        None => false,
+    }
+}
+
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic payload")
+    }
+}
+
+impl From<io::Error> for PluginError {
+    fn from(error: io::Error) -> Self {
+        if is_disk_full(&error) {
+            PluginError::DiskFull { path: None, source: error }
+        } else {
+            PluginError::Io { path: None, source: error }
+        }
+    }
+}
+
+// Keeps a `PluginError` that was only passed through anyhow
+impl From<anyhow::Error> for PluginError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<PluginError>() {
+            Ok(error) => error,
+            Err(error) => PluginError::other(format!("{:#}", error)),
+        }
+    }
+}
+
+// A cause that crossed a process or plugin boundary, where only its text survives
+#[derive(Debug)]
+struct RemoteError(String);
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for RemoteError {}
+
+// How a `PluginError` travels to plugin hosts and back: causes are flattened to their text
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+enum WireError {
+    NotImplemented,
+    NotFound { detail: String },
+    AlreadyExists { detail: String },
+    InvalidInput { detail: String },
+    Io { path: Option<PathBuf>, cause: String },
+    CorruptArchive { path: Option<PathBuf>, cause: Option<String> },
+    WrongPassword { path: Option<PathBuf> },
+    UnsupportedFeature { detail: String },
+    PathTraversal { entry: String },
+    LimitExceeded { detail: String },
+    DiskFull { path: Option<PathBuf>, cause: String },
+    Cancelled,
+    Panicked { plugin: String, message: String },
+    Other { message: String, cause: Option<String> },
+}
+
+impl From<&PluginError> for WireError {
+    fn from(error: &PluginError) -> Self {
+        match error {
+            PluginError::NotImplemented => WireError::NotImplemented,
+            PluginError::NotFound(detail) => WireError::NotFound { detail: detail.clone() },
+            PluginError::AlreadyExists(detail) => WireError::AlreadyExists { detail: detail.clone() },
+            PluginError::InvalidInput(detail) => WireError::InvalidInput { detail: detail.clone() },
+            PluginError::Io { path, source } => WireError::Io { path: path.clone(), cause: report(source) },
+            PluginError::CorruptArchive { path, source } => WireError::CorruptArchive {
+                path: path.clone(),
+                cause: source.as_deref().map(|source| report(source)),
+            },
+            PluginError::WrongPassword { path } => WireError::WrongPassword { path: path.clone() },
+            PluginError::UnsupportedFeature(detail) => WireError::UnsupportedFeature { detail: detail.clone() },
+            PluginError::PathTraversal { entry } => WireError::PathTraversal { entry: entry.clone() },
+            PluginError::LimitExceeded(detail) => WireError::LimitExceeded { detail: detail.clone() },
+            PluginError::DiskFull { path, source } => WireError::DiskFull { path: path.clone(), cause: report(source) },
+            PluginError::Cancelled => WireError::Cancelled,
+            PluginError::Panicked { plugin, message } => WireError::Panicked {
+                plugin: plugin.clone(),
+                message: message.clone(),
+            },
+            PluginError::Other { message, source } => WireError::Other {
+                message: message.clone(),
+                cause: source.as_deref().map(|source| report(source)),
+            },
+        }
+    }
+}
+
+impl From<WireError> for PluginError {
+    fn from(error: WireError) -> Self {
+        let remote = |cause: String| -> BoxError { Box::new(RemoteError(cause)) };
+        match error {
+            WireError::NotImplemented => PluginError::NotImplemented,
+            WireError::NotFound { detail } => PluginError::NotFound(detail),
+            WireError::AlreadyExists { detail } => PluginError::AlreadyExists(detail),
+            WireError::InvalidInput { detail } => PluginError::InvalidInput(detail),
+            WireError::Io { path, cause } => PluginError::Io { path, source: io::Error::other(cause) },
+            WireError::CorruptArchive { path, cause } => PluginError::CorruptArchive { path, source: cause.map(remote) },
+            WireError::WrongPassword { path } => PluginError::WrongPassword { path },
+            WireError::UnsupportedFeature { detail } => PluginError::UnsupportedFeature(detail),
+            WireError::PathTraversal { entry } => PluginError::PathTraversal { entry },
+            WireError::LimitExceeded { detail } => PluginError::LimitExceeded(detail),
+            WireError::DiskFull { path, cause } => PluginError::DiskFull { path, source: io::Error::other(cause) },
+            WireError::Cancelled => PluginError::Cancelled,
+            WireError::Panicked { plugin, message } => PluginError::Panicked { plugin, message },
+            WireError::Other { message, cause } => PluginError::Other { message, source: cause.map(remote) },
+        }
+    }
+}
+
+impl Serialize for PluginError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        WireError::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PluginError {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        WireError::deserialize(deserializer).map(PluginError::from)
+    }
+}
+
+// What commands hand to the frontend when they fail
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorPayload {
+    pub code: ErrorCode,
+    // The error and all of its causes, ready to show
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+}
+
+impl ErrorPayload {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into(), path: None, plugin: None }
+    }
+}
+
+impl From<&PluginError> for ErrorPayload {
+    fn from(error: &PluginError) -> Self {
+        Self {
+            code: error.code(),
+            message: error.report(),
+            path: error.path().map(Path::to_path_buf),
+            plugin: match error {
+                PluginError::Panicked { plugin, .. } => Some(plugin.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl From<PluginError> for ErrorPayload {
+    fn from(error: PluginError) -> Self {
+        Self::from(&error)
+    }
+}
+
+// The code comes from the first `PluginError` in the chain, the message from all of it
+impl From<anyhow::Error> for ErrorPayload {
+    fn from(error: anyhow::Error) -> Self {
+        let message = format!("{:#}", error);
+        match error.chain().find_map(|cause| cause.downcast_ref::<PluginError>()) {
+            Some(plugin_error) => Self { message, ..Self::from(plugin_error) },
+            None => Self::new(ErrorCode::Other, message),
+        }
+    }
+}
+
+impl fmt::Display for ErrorPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_codes_and_source_chain() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let err = PluginError::io("/tmp/input.txt", missing);
+        assert_eq!(err.code(), ErrorCode::Io);
+        assert_eq!(err.code().as_str(), "io");
+        assert_eq!(err.to_string(), "I/O error on /tmp/input.txt");
+        assert_eq!(err.source().unwrap().to_string(), "no such file");
+        assert_eq!(err.report(), "I/O error on /tmp/input.txt: no such file");
+
+        let full = PluginError::io("/tmp/out.zip", io::Error::from(io::ErrorKind::StorageFull));
+        assert_eq!(full.code(), ErrorCode::DiskFull);
+        assert_eq!(full.path(), Some(Path::new("/tmp/out.zip")));
+
+        let err = anyhow::Error::from(PluginError::WrongPassword { path: Some("a.7z".into()) })
+            .context("Failed to extract a.7z");
+        let payload = ErrorPayload::from(err);
+        assert_eq!(payload.code, ErrorCode::WrongPassword);
+        assert_eq!(payload.message, "Failed to extract a.7z: Wrong or missing password on a.7z");
+        assert_eq!(payload.path, Some(PathBuf::from("a.7z")));
+
+        let plain: Result<(), _> = Err(io::Error::other("boom"));
+        let payload = ErrorPayload::from(plain.context("Failed").unwrap_err());
+        assert_eq!(payload.code, ErrorCode::Other);
+        assert_eq!(serde_json::to_value(&payload).unwrap()["code"], "other");
+    }
+
+    #[test]
+    fn test_errors_survive_the_plugin_boundary() {
+        let err = PluginError::corrupt("broken.zip", io::Error::other("invalid central directory"));
+        let decoded = PluginError::decode(err.encode());
+        assert_eq!(decoded.code(), ErrorCode::CorruptArchive);
+        assert_eq!(decoded.report(), "Archive broken.zip is corrupt: invalid central directory");
+
+        let decoded = PluginError::decode(PluginError::PathTraversal { entry: "../x".into() }.encode());
+        assert!(matches!(decoded, PluginError::PathTraversal { entry } if entry == "../x"));
+
+        // Plugins built before error codes report plain text
+        let decoded = PluginError::decode("it broke".to_string());
+        assert_eq!(decoded.code(), ErrorCode::Other);
+        assert_eq!(decoded.to_string(), "it broke");
+    }
+}
@@ -0,0 +1,552 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use tempfile::TempDir;
+use super::compression::{CompressionPlugin, EntryInfo, EntryKind};
+use super::error::PluginError;
+
+// The ratio limit only applies past this many bytes, as small repetitive files compress
+// very well without being bombs
+const RATIO_GRACE: u64 = 16 * 1024 * 1024;
+
+// Bounds on what one extraction may write; `None` turns a limit off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractionLimits {
+    // Bytes written over all entries
+    pub max_total_size: Option<u64>,
+    pub max_entries: Option<u64>,
+    // Bytes written per byte of archive
+    pub max_ratio: Option<u64>,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: Some(32 * 1024 * 1024 * 1024),
+            max_entries: Some(100_000),
+            max_ratio: Some(200),
+        }
+    }
+}
+
+impl ExtractionLimits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_total_size: None,
+            max_entries: None,
+            max_ratio: None,
+        }
+    }
+}
+
+// Everything an extractor writes goes through here. Entries must land inside the output
+// directory, without passing through a symlink, and symlinks must point inside it too;
+// the limits are checked as data is written, so a bomb stops before it fills the disk.
+pub struct ExtractionGuard {
+    root: PathBuf,
+    overwrite: bool,
+    limits: ExtractionLimits,
+    // Read errors are reported against it
+    archive: Option<PathBuf>,
+    // What the ratio limit is measured against, unknown until set
+    compressed_size: Option<u64>,
+    entries: u64,
+    written: u64,
+    // Directories that earlier symlink targets climb out of with `..`, relative to the root
+    climbed: HashSet<PathBuf>,
+}
+
+impl ExtractionGuard {
+    // Creates `output_dir` if it does not exist yet
+    pub fn new(output_dir: &Path, overwrite: bool, limits: ExtractionLimits) -> Result<Self, PluginError> {
+        fs::create_dir_all(output_dir).map_err(|e| PluginError::io(output_dir, e))?;
+        let root = output_dir.canonicalize().map_err(|e| PluginError::io(output_dir, e))?;
+        Ok(Self {
+            root,
+            overwrite,
+            limits,
+            archive: None,
+            compressed_size: None,
+            entries: 0,
+            written: 0,
+            climbed: HashSet::new(),
+        })
+    }
+
+    pub fn for_archive(mut self, archive_file: &Path) -> Self {
+        self.compressed_size = fs::metadata(archive_file).ok().map(|metadata| metadata.len());
+        self.archive = Some(archive_file.to_path_buf());
+        self
+    }
+
+    // For streamed archives, how much has been read so far
+    pub fn set_compressed_size(&mut self, size: u64) {
+        self.compressed_size = Some(size);
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn extract(&mut self, info: EntryInfo, data: &mut dyn Read) -> Result<(), PluginError> {
+        match info.kind {
+            EntryKind::File => self.write_file(&info.name, data),
+            EntryKind::Directory => self.create_dir(&info.name),
+            EntryKind::Symlink { target } => self.create_symlink(&info.name, &target),
+        }
+        .map(|_| ())
+    }
+
+    // Counts the entry and returns where it goes
+    pub fn entry_path(&mut self, name: &str) -> Result<PathBuf, PluginError> {
+        self.entries += 1;
+        if let Some(max) = self.limits.max_entries {
+            if self.entries > max {
+                return Err(PluginError::LimitExceeded(format!("the archive has more than {} entries", max)));
+            }
+        }
+        let relative = relative_path(name)?;
+        // An earlier entry may have been a symlink pointing elsewhere; never write through one
+        let mut path = self.root.clone();
+        for component in relative.components() {
+            path.push(component);
+            match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(PluginError::PathTraversal { entry: name.to_string() })
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        Ok(self.root.join(relative))
+    }
+
+    pub fn create_dir(&mut self, name: &str) -> Result<PathBuf, PluginError> {
+        let path = self.entry_path(name)?;
+        fs::create_dir_all(&path).map_err(|e| PluginError::io(&path, e))?;
+        Ok(path)
+    }
+
+    // A file that fails partway, a limit included, is removed again
+    pub fn write_file(&mut self, name: &str, data: &mut dyn Read) -> Result<PathBuf, PluginError> {
+        let (path, mut file) = self.create_file(name)?;
+        let copied = self.copy(data, &mut file, &path);
+        if copied.is_err() {
+            drop(file);
+            let _ = fs::remove_file(&path);
+        }
+        copied.map(|()| path)
+    }
+
+    // For extractors that write the data themselves, which they must `count` before writing
+    pub fn create_file(&mut self, name: &str) -> Result<(PathBuf, File), PluginError> {
+        let path = self.file_path(name)?;
+        let file = File::create(&path).map_err(|e| PluginError::io(&path, e))?;
+        Ok((path, file))
+    }
+
+    pub fn count(&mut self, len: u64) -> Result<(), PluginError> {
+        self.written += len;
+        if let Some(max) = self.limits.max_total_size {
+            if self.written > max {
+                return Err(PluginError::LimitExceeded(format!("more than {} bytes would be extracted", max)));
+            }
+        }
+        if let (Some(max), Some(compressed)) = (self.limits.max_ratio, self.compressed_size) {
+            if self.written > RATIO_GRACE && self.written > max.saturating_mul(compressed.max(1)) {
+                return Err(PluginError::LimitExceeded(format!("the archive expands more than {}-fold", max)));
+            }
+        }
+        Ok(())
+    }
+
+    fn file_path(&mut self, name: &str) -> Result<PathBuf, PluginError> {
+        let path = self.entry_path(name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| PluginError::io(parent, e))?;
+        }
+        if path.exists() && !self.overwrite {
+            return Err(PluginError::AlreadyExists(path.display().to_string()));
+        }
+        Ok(path)
+    }
+
+    pub fn create_symlink(&mut self, name: &str, target: &str) -> Result<PathBuf, PluginError> {
+        let traversal = || PluginError::PathTraversal { entry: name.to_string() };
+        let path = self.entry_path(name)?;
+        let relative = path.strip_prefix(&self.root).unwrap_or(Path::new("")).to_path_buf();
+        // An earlier target's `..` would lead up from wherever this link points instead
+        if self.climbed.iter().any(|climbed| climbed.starts_with(&relative)) {
+            return Err(traversal());
+        }
+        // Resolved from the link's own directory, the target has to stay inside as well. `..`
+        // is only taken lexically where what it leaves is no symlink.
+        let mut resolved = PathBuf::new();
+        let mut climbed = Vec::new();
+        let link_dir = relative.parent().unwrap_or(Path::new(""));
+        for component in link_dir.components().chain(Path::new(target).components()) {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                Component::ParentDir if resolved.parent().is_some() => {
+                    let left = self.root.join(&resolved);
+                    if fs::symlink_metadata(&left).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+                        return Err(traversal());
+                    }
+                    climbed.push(resolved.clone());
+                    resolved.pop();
+                }
+                _ => return Err(traversal()),
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| PluginError::io(parent, e))?;
+        }
+        if fs::symlink_metadata(&path).is_ok() {
+            if !self.overwrite {
+                return Err(PluginError::AlreadyExists(path.display().to_string()));
+            }
+            fs::remove_file(&path).map_err(|e| PluginError::io(&path, e))?;
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(target, &path).map_err(|e| PluginError::io(&path, e))?;
+            self.climbed.extend(climbed);
+            Ok(path)
+        }
+        #[cfg(not(unix))]
+        Err(PluginError::UnsupportedFeature(format!("extracting the symlink '{}'", name)))
+    }
+
+    fn copy(&mut self, data: &mut dyn Read, file: &mut File, path: &Path) -> Result<(), PluginError> {
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = match data.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.read_error(e)),
+            };
+            self.count(read as u64)?;
+            file.write_all(&buf[..read]).map_err(|e| PluginError::io(path, e))?;
+        }
+    }
+
+    // Moves what `dir` holds into place, `prefix` being its name in the output
+    fn move_tree(&mut self, dir: &Path, prefix: &str) -> Result<(), PluginError> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|e| PluginError::io(dir, e))?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let from = entry.path();
+            let name = entry
+                .file_name()
+                .to_str()
+                .map(|name| format!("{}{}", prefix, name))
+                .ok_or_else(|| PluginError::InvalidInput(format!("{} is not valid UTF-8", from.display())))?;
+            let file_type = entry.file_type().map_err(|e| PluginError::io(&from, e))?;
+            if file_type.is_symlink() {
+                let target = fs::read_link(&from).map_err(|e| PluginError::io(&from, e))?;
+                let target = target
+                    .to_str()
+                    .ok_or_else(|| PluginError::InvalidInput(format!("{} is not valid UTF-8", target.display())))?;
+                self.create_symlink(&name, target)?;
+            } else if file_type.is_dir() {
+                self.create_dir(&name)?;
+                self.move_tree(&from, &format!("{}/", name))?;
+            } else if file_type.is_file() {
+                let path = self.file_path(&name)?;
+                let len = entry.metadata().map_err(|e| PluginError::io(&from, e))?.len();
+                self.count(len)?;
+                fs::rename(&from, &path).map_err(|e| PluginError::io(&path, e))?;
+            } else {
+                return Err(PluginError::UnsupportedFeature(format!("extracting the special file '{}'", name)));
+            }
+        }
+        Ok(())
+    }
+
+    // Damaged entries fail to read with InvalidData, e.g. on a CRC mismatch, with Other from
+    // some decoders, or with an early end
+    fn read_error(&self, error: io::Error) -> PluginError {
+        match (error.kind(), &self.archive) {
+            (io::ErrorKind::InvalidData | io::ErrorKind::Other | io::ErrorKind::UnexpectedEof, archive) => {
+                PluginError::CorruptArchive {
+                    path: archive.clone(),
+                    source: Some(error.into()),
+                }
+            }
+            (_, Some(archive)) => PluginError::io(archive, error),
+            (_, None) => error.into(),
+        }
+    }
+}
+
+// Either separator is accepted; names that are absolute or climb out are refused
+fn relative_path(name: &str) -> Result<PathBuf, PluginError> {
+    let traversal = || PluginError::PathTraversal { entry: name.to_string() };
+    if name.starts_with(['/', '\\']) {
+        return Err(traversal());
+    }
+    let mut relative = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return Err(traversal()),
+            // A drive or stream name on Windows
+            part if cfg!(windows) && part.contains(':') => return Err(traversal()),
+            part => relative.push(part),
+        }
+    }
+    Ok(relative)
+}
+
+// Extracts everything `plugin.read_entries` hands out, through a guard
+pub fn extract_entries(
+    plugin: &dyn CompressionPlugin,
+    archive_file: &Path,
+    output_dir: &Path,
+    overwrite: bool,
+    limits: ExtractionLimits,
+) -> Result<(), PluginError> {
+    let mut guard = ExtractionGuard::new(output_dir, overwrite, limits)?.for_archive(archive_file);
+    plugin.read_entries(archive_file, &mut |info, data| guard.extract(info, data))
+}
+
+// A private directory inside the output directory for plugins that write their output
+// themselves. `finish` moves what they wrote into place through an `ExtractionGuard`, so
+// their entries are checked like everyone else's.
+pub struct Staging {
+    dir: TempDir,
+    output_dir: PathBuf,
+}
+
+impl Staging {
+    pub fn new(output_dir: &Path) -> Result<Self, PluginError> {
+        fs::create_dir_all(output_dir).map_err(|e| PluginError::io(output_dir, e))?;
+        let dir = tempfile::Builder::new()
+            .prefix(".extracting")
+            .tempdir_in(output_dir)
+            .map_err(|e| PluginError::io(output_dir, e))?;
+        let staging = Self {
+            dir,
+            output_dir: output_dir.to_path_buf(),
+        };
+        fs::create_dir(staging.path()).map_err(|e| PluginError::io(staging.path(), e))?;
+        Ok(staging)
+    }
+
+    // Where the plugin extracts to; one level down, so a plugin that follows an entry's `..`
+    // still writes inside the private directory
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join("out")
+    }
+
+    pub fn finish(self, archive_file: &Path, overwrite: bool, limits: ExtractionLimits) -> Result<(), PluginError> {
+        let mut guard = ExtractionGuard::new(&self.output_dir, overwrite, limits)?.for_archive(archive_file);
+        guard.move_tree(&self.path(), "")
+    }
+}
+
+// For callers that take any compression plugin: plugins that cannot hand out their entries
+// extract into a `Staging` directory instead
+pub fn extract_archive(
+    plugin: &dyn CompressionPlugin,
+    archive_file: &Path,
+    output_dir: &Path,
+    overwrite: bool,
+    limits: ExtractionLimits,
+) -> Result<(), PluginError> {
+    match extract_entries(plugin, archive_file, output_dir, overwrite, limits) {
+        Err(PluginError::NotImplemented) => {
+            let staging = Staging::new(output_dir)?;
+            plugin.decompress(archive_file, &staging.path(), true, limits)?;
+            staging.finish(archive_file, overwrite, limits)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+    use tempfile::tempdir;
+
+    fn file(name: &str) -> EntryInfo {
+        EntryInfo {
+            name: name.to_string(),
+            kind: EntryKind::File,
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_paths_stay_inside_the_output_dir() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut guard = ExtractionGuard::new(&output, true, ExtractionLimits::default()).unwrap();
+
+        for name in ["../evil.txt", "a/../../evil.txt", "/etc/evil", "\\evil.txt", "a\\..\\..\\evil.txt"] {
+            let err = guard.extract(file(name), &mut &b"evil"[..]).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::PathTraversal, "{}", name);
+        }
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        guard.extract(file("./docs//a.txt"), &mut &b"fine"[..]).unwrap();
+        assert_eq!(fs::read_to_string(output.join("docs").join("a.txt")).unwrap(), "fine");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_escape() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("out");
+        let mut guard = ExtractionGuard::new(&output, true, ExtractionLimits::default()).unwrap();
+        let link = |target: &str| EntryInfo {
+            name: "link".to_string(),
+            kind: EntryKind::Symlink { target: target.to_string() },
+            metadata: Default::default(),
+        };
+
+        for target in ["..", "/etc", "docs/../../.."] {
+            let err = guard.extract(link(target), &mut io::empty()).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::PathTraversal, "{}", target);
+        }
+
+        // A link planted by hand, or by another tool, is not written through either
+        std::os::unix::fs::symlink(dir.path(), output.join("planted")).unwrap();
+        let err = guard.extract(file("planted/evil.txt"), &mut &b"evil"[..]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::PathTraversal);
+        assert!(!dir.path().join("evil.txt").exists());
+
+        guard.extract(link("docs/readme.txt"), &mut io::empty()).unwrap();
+        assert_eq!(fs::read_link(output.join("link")).unwrap(), Path::new("docs/readme.txt"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chained_symlinks_cannot_escape() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("out");
+        let link = |name: &str, target: &str| EntryInfo {
+            name: name.to_string(),
+            kind: EntryKind::Symlink { target: target.to_string() },
+            metadata: Default::default(),
+        };
+
+        // `x/l/..` is the output dir when read lexically, but `..` of the link's target when followed
+        let mut guard = ExtractionGuard::new(&output, true, ExtractionLimits::default()).unwrap();
+        guard.extract(link("x/l", ".."), &mut io::empty()).unwrap();
+        let err = guard.extract(link("esc", "x/l/.."), &mut io::empty()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::PathTraversal);
+        assert!(fs::symlink_metadata(output.join("esc")).is_err());
+
+        // The other way round the second link turns the first one into an escape
+        let output = dir.path().join("reversed");
+        let mut guard = ExtractionGuard::new(&output, true, ExtractionLimits::default()).unwrap();
+        guard.extract(link("esc", "x/l/.."), &mut io::empty()).unwrap();
+        for (name, target) in [("x/l", ".."), ("x", ".")] {
+            let err = guard.extract(link(name, target), &mut io::empty()).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::PathTraversal, "{}", name);
+        }
+
+        // Links that only pass through other links are fine
+        guard.extract(link("current", "v2"), &mut io::empty()).unwrap();
+        guard.extract(link("bin", "current/bin"), &mut io::empty()).unwrap();
+    }
+
+    #[test]
+    fn test_staged_output_goes_through_the_guard() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("out");
+        fs::create_dir(&output).unwrap();
+        fs::write(output.join("kept.txt"), "kept").unwrap();
+        let archive = dir.path().join("archive");
+        fs::write(&archive, "archive").unwrap();
+
+        let staging = Staging::new(&output).unwrap();
+        fs::create_dir_all(staging.path().join("docs")).unwrap();
+        fs::write(staging.path().join("docs").join("a.txt"), "a").unwrap();
+        fs::write(staging.path().join("kept.txt"), "replaced").unwrap();
+        // What a plugin following `../evil.txt` writes stays in the private directory
+        fs::write(staging.path().join("..").join("evil.txt"), "evil").unwrap();
+        let err = staging.finish(&archive, false, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::AlreadyExists);
+        assert_eq!(fs::read_to_string(output.join("kept.txt")).unwrap(), "kept");
+
+        let staging = Staging::new(&output).unwrap();
+        fs::create_dir_all(staging.path().join("docs")).unwrap();
+        fs::write(staging.path().join("docs").join("a.txt"), "a").unwrap();
+        fs::write(staging.path().join("..").join("evil.txt"), "evil").unwrap();
+        staging.finish(&archive, true, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read_to_string(output.join("docs").join("a.txt")).unwrap(), "a");
+        let mut names: Vec<_> = fs::read_dir(&output).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        names.sort();
+        assert_eq!(names, ["docs", "kept.txt"]);
+
+        let limits = ExtractionLimits {
+            max_total_size: Some(10),
+            ..ExtractionLimits::unlimited()
+        };
+        let staging = Staging::new(&output).unwrap();
+        fs::write(staging.path().join("large.bin"), [0u8; 100]).unwrap();
+        let err = staging.finish(&archive, true, limits).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+        assert!(!output.join("large.bin").exists());
+
+        #[cfg(unix)]
+        {
+            let staging = Staging::new(&output).unwrap();
+            std::os::unix::fs::symlink("../..", staging.path().join("up")).unwrap();
+            let err = staging.finish(&archive, true, ExtractionLimits::default()).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::PathTraversal);
+        }
+    }
+
+    #[test]
+    fn test_limits_stop_extraction() {
+        let dir = tempdir().unwrap();
+        let limits = ExtractionLimits {
+            max_total_size: Some(1000),
+            max_entries: Some(3),
+            max_ratio: None,
+        };
+
+        let mut guard = ExtractionGuard::new(&dir.path().join("size"), true, limits).unwrap();
+        guard.extract(file("small.bin"), &mut &[0u8; 600][..]).unwrap();
+        let err = guard.extract(file("large.bin"), &mut &[0u8; 600][..]).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+        assert!(!dir.path().join("size").join("large.bin").exists());
+
+        let mut guard = ExtractionGuard::new(&dir.path().join("count"), true, limits).unwrap();
+        for i in 0..3 {
+            guard.extract(file(&format!("{}.txt", i)), &mut io::empty()).unwrap();
+        }
+        let err = guard.extract(file("3.txt"), &mut io::empty()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+
+        // 32 MiB out of a 1 KiB archive
+        let limits = ExtractionLimits {
+            max_ratio: Some(100),
+            ..ExtractionLimits::unlimited()
+        };
+        let mut guard = ExtractionGuard::new(&dir.path().join("ratio"), true, limits).unwrap();
+        guard.set_compressed_size(1024);
+        let err = guard.extract(file("zeros.bin"), &mut io::repeat(0).take(32 << 20)).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+
+        let err = ExtractionGuard::new(&dir.path().join("exists"), false, limits)
+            .and_then(|mut guard| {
+                guard.extract(file("a.txt"), &mut &b"a"[..])?;
+                guard.extract(file("a.txt"), &mut &b"b"[..])
+            })
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::AlreadyExists);
+    }
+}
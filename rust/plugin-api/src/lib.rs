@@ -0,0 +1,35 @@
+// The plugin API on its own, so plugin libraries can build against it without pulling in
+// the app and its dependencies. The app re-exports it as `smart_transfer::plugin_api`.
+
+pub mod base;
+pub mod error;
+pub mod types;
+pub mod compression;
+pub mod extract;
+pub mod transfer;
+pub mod settings;
+pub mod platform;
+pub mod abi;
+
+// Re-export commonly used types
+pub use base::{Plugin, PluginFactory};
+pub use error::{ErrorCode, ErrorPayload, PluginError};
+pub use types::{
+    Capability,
+    PluginFeature,
+    PluginMetadata,
+    CompressionOptions,
+    PlatformSupport,
+    PluginType,
+    CompressionMode,
+};
+pub use compression::{AsyncCompressionPlugin, CompressionPlugin, EntryInfo, EntryKind, EntryMetadata, StreamEntry, WriteSeek};
+pub use extract::{ExtractionGuard, ExtractionLimits};
+pub use settings::{PluginSettings, SettingDescriptor, SettingKind, SettingsSchema};
+pub use transfer::{TransferCapabilities, TransferManifest, TransferOptions, TransferPlugin, TransferSession};
+pub use platform::{
+    PlatformPaths,
+    get_plugin_dir,
+    get_log_dir,
+    get_config_dir,
+};
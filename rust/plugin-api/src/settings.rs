@@ -0,0 +1,174 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::base::PluginError;
+
+// Setting values by key, as persisted and as handed to `Plugin::initialize`
+pub type PluginSettings = serde_json::Map<String, Value>;
+
+// What a single setting accepts. Serialized with a `type` tag so the frontend can pick a
+// form control per setting without knowing the plugin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SettingKind {
+    Boolean,
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+    },
+    String,
+    // A file system path, rendered with a file picker
+    Path,
+    Choice { options: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingDescriptor {
+    pub key: String,
+    pub label: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(flatten)]
+    pub kind: SettingKind,
+    pub default: Value,
+}
+
+impl SettingDescriptor {
+    fn check(&self, value: &Value) -> Result<(), String> {
+        let mismatch = || format!("expected {}, got {}", self.kind.type_name(), value);
+        match &self.kind {
+            SettingKind::Boolean => value.as_bool().map(|_| ()).ok_or_else(mismatch),
+            SettingKind::Integer { min, max } => check_range(value.as_i64().ok_or_else(mismatch)?, min, max),
+            SettingKind::Number { min, max } => check_range(value.as_f64().ok_or_else(mismatch)?, min, max),
+            SettingKind::String | SettingKind::Path => value.as_str().map(|_| ()).ok_or_else(mismatch),
+            SettingKind::Choice { options } => {
+                let choice = value.as_str().ok_or_else(mismatch)?;
+                if options.iter().any(|option| option == choice) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' is not one of {}", choice, options.join(", ")))
+                }
+            }
+        }
+    }
+}
+
+impl SettingKind {
+    fn type_name(&self) -> &'static str {
+        match self {
+            SettingKind::Boolean => "a boolean",
+            SettingKind::Integer { .. } => "an integer",
+            SettingKind::Number { .. } => "a number",
+            SettingKind::String => "a string",
+            SettingKind::Path => "a path",
+            SettingKind::Choice { .. } => "one of the listed options",
+        }
+    }
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(n: T, min: &Option<T>, max: &Option<T>) -> Result<(), String> {
+    let below = min.as_ref().is_some_and(|min| n < *min);
+    let above = max.as_ref().is_some_and(|max| n > *max);
+    if !below && !above {
+        return Ok(());
+    }
+    Err(match (min, max) {
+        (Some(min), Some(max)) => format!("{} is out of range {}..={}", n, min, max),
+        (Some(min), None) => format!("{} is below the minimum of {}", n, min),
+        (None, Some(max)) => format!("{} is above the maximum of {}", n, max),
+        (None, None) => return Ok(()),
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SettingsSchema {
+    pub settings: Vec<SettingDescriptor>,
+}
+
+impl SettingsSchema {
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    pub fn defaults(&self) -> PluginSettings {
+        self.settings
+            .iter()
+            .map(|setting| (setting.key.clone(), setting.default.clone()))
+            .collect()
+    }
+
+    // Checks `values` against the schema and fills in defaults for missing keys. Unknown keys
+    // are refused so a typo does not silently fall back to the default.
+    pub fn resolve(&self, values: &PluginSettings) -> Result<PluginSettings, PluginError> {
+        for key in values.keys() {
+            if !self.settings.iter().any(|setting| setting.key == *key) {
+                return Err(PluginError::InvalidInput(format!("Unknown setting '{}'", key)));
+            }
+        }
+
+        let mut resolved = PluginSettings::new();
+        for setting in &self.settings {
+            let value = values.get(&setting.key).unwrap_or(&setting.default);
+            setting
+                .check(value)
+                .map_err(|e| PluginError::InvalidInput(format!("Setting '{}': {}", setting.key, e)))?;
+            resolved.insert(setting.key.clone(), value.clone());
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> SettingsSchema {
+        serde_json::from_value(json!({
+            "settings": [
+                { "key": "level", "label": "Level", "type": "integer", "min": 1, "max": 9, "default": 6 },
+                { "key": "dictionary", "label": "Dictionary", "type": "path", "default": "" },
+                { "key": "method", "label": "Method", "type": "choice", "options": ["deflate", "store"], "default": "deflate" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn settings(value: Value) -> PluginSettings {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_resolve_fills_defaults() {
+        let resolved = schema().resolve(&settings(json!({ "level": 9 }))).unwrap();
+        assert_eq!(resolved["level"], json!(9));
+        assert_eq!(resolved["method"], json!("deflate"));
+        assert_eq!(resolved, schema().resolve(&resolved).unwrap());
+        assert_eq!(schema().resolve(&PluginSettings::new()).unwrap(), schema().defaults());
+    }
+
+    #[test]
+    fn test_resolve_rejects_invalid_values() {
+        let schema = schema();
+        let err = schema.resolve(&settings(json!({ "level": 12 }))).unwrap_err();
+        assert!(err.to_string().contains("Setting 'level': 12 is out of range 1..=9"));
+        assert!(schema.resolve(&settings(json!({ "level": "fast" }))).is_err());
+        assert!(schema.resolve(&settings(json!({ "method": "lzma" }))).is_err());
+        let err = schema.resolve(&settings(json!({ "threads": 4 }))).unwrap_err();
+        assert!(err.to_string().contains("Unknown setting 'threads'"));
+    }
+
+    #[test]
+    fn test_schema_serialization() {
+        let json = serde_json::to_value(schema()).unwrap();
+        assert_eq!(json["settings"][0]["type"], json!("integer"));
+        assert_eq!(json["settings"][2]["options"], json!(["deflate", "store"]));
+    }
+}
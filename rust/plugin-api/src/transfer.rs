@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use super::base::{Plugin, PluginError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferOptions {
+    // Number of parallel TCP data streams; more streams help on high-latency links
+    pub streams: usize,
+    pub chunk_size: usize,
+}
+
+impl Default for TransferOptions {
+    fn default() -> Self {
+        Self {
+            streams: 1,
+            chunk_size: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferCapabilities {
+    // An interrupted transfer can pick up where it stopped
+    pub resumable: bool,
+    // Data is encrypted on the wire
+    pub encrypted: bool,
+    // Data is spread over several connections, see `TransferOptions::streams`
+    pub parallel_streams: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferFile {
+    // Path relative to the receiver's output directory
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub files: Vec<TransferFile>,
+}
+
+impl TransferManifest {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+// One outgoing transfer. The core calls `send_manifest`, then `send_data`, then `finish`.
+#[async_trait]
+pub trait TransferSession: Send {
+    // Announces the files; fails if the receiver refuses them
+    async fn send_manifest(&mut self, manifest: &TransferManifest) -> Result<(), PluginError>;
+
+    // Streams the contents of every file; `sources[i]` holds `manifest.files[i]`
+    async fn send_data(&mut self, sources: &[PathBuf]) -> Result<(), PluginError>;
+
+    // Waits until the receiver confirms that everything arrived
+    async fn finish(self: Box<Self>) -> Result<(), PluginError>;
+}
+
+#[async_trait]
+pub trait TransferPlugin: Plugin {
+    // Target schemes routed to this plugin, e.g. `tcp` for `tcp://host:port`
+    fn schemes(&self) -> Vec<String>;
+
+    fn capabilities(&self) -> TransferCapabilities;
+
+    // `address` is the target without its scheme
+    async fn connect(
+        &self,
+        address: &str,
+        options: &TransferOptions,
+    ) -> Result<Box<dyn TransferSession>, PluginError>;
+
+    // Accepts a single transfer on `address` and writes it below `output_dir`
+    async fn receive(&self, address: &str, output_dir: &Path) -> Result<(), PluginError>;
+}
@@ -0,0 +1,166 @@
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginMetadata {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    pub description: String,
+    pub plugin_type: PluginType,
+    pub platform_support: PlatformSupport,
+    // Semver requirement on the core, as declared in the plugin's `plugin.toml`
+    #[serde(default)]
+    pub core_version: Option<String>,
+    // File extensions the plugin handles, without the leading dot, e.g. `zip` or `tar.gz`
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    // Declared on top of `extensions`, which are provided implicitly
+    #[serde(default)]
+    pub provides: Vec<Capability>,
+    // Capabilities other plugins must provide before this one can be loaded
+    #[serde(default)]
+    pub requires: Vec<Capability>,
+}
+
+impl PluginMetadata {
+    pub fn has_capability(&self, capability: &Capability) -> bool {
+        let extension = match capability {
+            Capability::Extension(ext) => self
+                .extensions
+                .iter()
+                .any(|own| own.trim_start_matches('.').eq_ignore_ascii_case(ext)),
+            _ => false,
+        };
+        extension || self.provides.contains(capability)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginFeature {
+    Encryption,
+    Split,
+    Streaming,
+}
+
+impl PluginFeature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluginFeature::Encryption => "encryption",
+            PluginFeature::Split => "split",
+            PluginFeature::Streaming => "streaming",
+        }
+    }
+}
+
+impl FromStr for PluginFeature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "encryption" => Ok(PluginFeature::Encryption),
+            "split" => Ok(PluginFeature::Split),
+            "streaming" => Ok(PluginFeature::Streaming),
+            other => Err(format!("Unknown feature '{}'", other)),
+        }
+    }
+}
+
+// Something a plugin can do, written `format:tar`, `extension:tar.zst` or
+// `feature:encryption` in manifests and queries
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Capability {
+    // A container or codec, independent of file naming, e.g. `tar` or `zstd`
+    Format(String),
+    // Lower case and without the leading dot
+    Extension(String),
+    Feature(PluginFeature),
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Capability '{}' must be written kind:value", s))?;
+        let value = value.trim().trim_start_matches('.').to_lowercase();
+        if value.is_empty() {
+            return Err(format!("Capability '{}' has no value", s));
+        }
+        match kind.trim() {
+            "format" => Ok(Capability::Format(value)),
+            "extension" => Ok(Capability::Extension(value)),
+            "feature" => value.parse().map(Capability::Feature),
+            other => Err(format!("Unknown capability kind '{}'", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for Capability {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Format(format) => write!(f, "format:{}", format),
+            Capability::Extension(ext) => write!(f, "extension:{}", ext),
+            Capability::Feature(feature) => write!(f, "feature:{}", feature.name()),
+        }
+    }
+}
+
+impl From<Capability> for String {
+    fn from(capability: Capability) -> Self {
+        capability.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PluginType {
+    Compression,
+    Transfer,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlatformSupport {
+    pub windows: bool,
+    pub linux: bool,
+    pub macos: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CompressionMode {
+    Fast,
+    Normal,
+    Best,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompressionOptions {
+    pub mode: CompressionMode,
+    pub password: Option<String>,
+    pub split_size: Option<u64>,
+    pub extra_args: HashMap<String, String>,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        Self {
+            mode: CompressionMode::Normal,
+            password: None,
+            split_size: None,
+            extra_args: HashMap::new(),
+        }
+    }
+}
@@ -1,16 +1,19 @@
-use anyhow::Result;
-use async_trait::async_trait;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use smart_transfer_plugin_api::{
-    base::{Plugin, PluginConfig, PluginType},
-    compression::{CompressionPlugin, CompressionOptions},
-    types::PluginMetadata,
-    declare_plugin,
+    base::{Plugin, PluginConfig, PluginError},
+    compression::CompressionPlugin,
+    extract::{ExtractionGuard, ExtractionLimits},
+    settings::{PluginSettings, SettingDescriptor, SettingKind, SettingsSchema},
+    types::{Capability, CompressionOptions, PlatformSupport, PluginMetadata, PluginType},
 };
 
-#[derive(Clone)]
+// Writes the inputs as a JSON map of file name to contents, enough to exercise the plugin ABI
 pub struct ExamplePlugin {
     config: PluginConfig,
     metadata: PluginMetadata,
+    pretty: bool,
 }
 
 impl ExamplePlugin {
@@ -28,56 +31,111 @@ impl ExamplePlugin {
                 version: env!("CARGO_PKG_VERSION").to_string(),
                 author: "Your Name".to_string(),
                 plugin_type: PluginType::Compression,
+                platform_support: PlatformSupport {
+                    windows: true,
+                    linux: true,
+                    macos: true,
+                },
+                core_version: None,
+                extensions: vec!["example".to_string()],
+                provides: vec![Capability::Format("example".to_string())],
+                requires: Vec::new(),
             },
+            pretty: false,
         }
     }
 }
 
+impl Default for ExamplePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Plugin for ExamplePlugin {
     fn get_config(&self) -> &PluginConfig {
         &self.config
     }
 
-    fn metadata(&self) -> &PluginMetadata {
-        &self.metadata
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn settings_schema(&self) -> SettingsSchema {
+        SettingsSchema {
+            settings: vec![SettingDescriptor {
+                key: "pretty".to_string(),
+                label: "Pretty-print archives".to_string(),
+                description: "Indent the JSON written by compress".to_string(),
+                kind: SettingKind::Boolean,
+                default: serde_json::Value::Bool(false),
+            }],
+        }
     }
 
-    fn initialize(&mut self) -> Result<()> {
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError> {
+        self.pretty = settings.get("pretty").and_then(|v| v.as_bool()).unwrap_or(false);
         Ok(())
     }
 
-    fn shutdown(&mut self) -> Result<()> {
+    fn cleanup(&mut self) -> Result<(), PluginError> {
         Ok(())
     }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        Some(self)
+    }
 }
 
-#[async_trait]
 impl CompressionPlugin for ExamplePlugin {
-    async fn compress(
+    fn compress(
         &self,
-        _files: Vec<std::path::PathBuf>,
-        _output_path: std::path::PathBuf,
-        _options: CompressionOptions,
-    ) -> Result<()> {
+        input_files: &[PathBuf],
+        output_file: &Path,
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let mut entries = HashMap::new();
+        for file in input_files {
+            let name = file
+                .file_name()
+                .ok_or_else(|| PluginError::InvalidInput(format!("{} has no file name", file.display())))?;
+            entries.insert(name.to_string_lossy().into_owned(), fs::read_to_string(file)?);
+        }
+        let json = if self.pretty {
+            serde_json::to_vec_pretty(&entries)
+        } else {
+            serde_json::to_vec(&entries)
+        }
+        .map_err(|e| PluginError::with_source("Failed to encode the archive", e))?;
+        fs::write(output_file, json)?;
         Ok(())
     }
 
-    async fn decompress(
+    fn decompress(
         &self,
-        _archive_path: std::path::PathBuf,
-        _output_dir: std::path::PathBuf,
-        _options: CompressionOptions,
-    ) -> Result<()> {
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        let data = fs::read(archive_file).map_err(|e| PluginError::io(archive_file, e))?;
+        let entries: HashMap<String, String> =
+            serde_json::from_slice(&data).map_err(|e| PluginError::corrupt(archive_file, e))?;
+        // Names come from the archive, so they go through the guard like the core's own extractors
+        let mut guard = ExtractionGuard::new(output_dir, overwrite, limits)?.for_archive(archive_file);
+        for (name, contents) in entries {
+            guard.write_file(&name, &mut contents.as_bytes())?;
+        }
         Ok(())
     }
-
-    fn supported_extensions(&self) -> Vec<&str> {
-        vec!["example"]
-    }
-
-    fn default_extension(&self) -> &str {
-        "example"
-    }
 }
 
-declare_plugin!(ExamplePlugin, ExamplePlugin::new);
+smart_transfer_plugin_api::declare_compression_plugin!(ExamplePlugin, ExamplePlugin::new);
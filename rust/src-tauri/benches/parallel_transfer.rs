@@ -0,0 +1,110 @@
+// Throughput of parallel transfer streams over loopback with injected latency.
+//
+// Run with: cargo bench --bench parallel_transfer
+//
+// Every connection goes through a local proxy that delays each forwarded
+// segment by LATENCY and only keeps WINDOW bytes in flight, which mimics the
+// bandwidth-delay limit of a single TCP stream on a long fat link.
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use smart_transfer::plugins::registry::PluginRegistry;
+use smart_transfer::plugins::tcp::TcpTransferPlugin;
+use smart_transfer::transfer::{send_files_with_options, TransferListener, TransferOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+const LATENCY: Duration = Duration::from_millis(40);
+const SEGMENT_SIZE: usize = 64 * 1024;
+const WINDOW_SEGMENTS: usize = 4;
+const PAYLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+async fn forward_with_latency<R, W>(mut reader: R, mut writer: W)
+where
+    R: AsyncReadExt + Unpin + Send + 'static,
+    W: AsyncWriteExt + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<(Instant, Vec<u8>)>(WINDOW_SEGMENTS);
+
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; SEGMENT_SIZE];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send((Instant::now() + LATENCY, buf[..n].to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    while let Some((deliver_at, segment)) = rx.recv().await {
+        tokio::time::sleep_until(deliver_at.into()).await;
+        if writer.write_all(&segment).await.is_err() {
+            return;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+async fn run_proxy(upstream: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let server = TcpStream::connect(upstream).await.unwrap();
+            client.set_nodelay(true).unwrap();
+            server.set_nodelay(true).unwrap();
+            let (client_read, client_write) = client.into_split();
+            let (server_read, server_write) = server.into_split();
+            tokio::spawn(forward_with_latency(client_read, server_write));
+            tokio::spawn(forward_with_latency(server_read, client_write));
+        }
+    });
+    addr
+}
+
+async fn measure(streams: usize, payload: &std::path::Path) -> f64 {
+    let output_dir = tempfile::tempdir().unwrap();
+    let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = run_proxy(listener.local_addr().unwrap()).await;
+
+    let output = output_dir.path().to_path_buf();
+    let receiver = tokio::spawn(async move { listener.accept_transfer(&output).await });
+
+    let options = TransferOptions {
+        streams,
+        chunk_size: 256 * 1024,
+    };
+    let plugins = PluginRegistry::new();
+    plugins.register(String::from("TCP Transfer Plugin"), Box::new(TcpTransferPlugin::new())).unwrap();
+    let start = Instant::now();
+    send_files_with_options(&plugins, vec![payload.to_path_buf()], proxy.to_string(), &options)
+        .await
+        .unwrap();
+    receiver.await.unwrap().unwrap();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    PAYLOAD_SIZE as f64 / elapsed / (1024.0 * 1024.0)
+}
+
+#[tokio::main]
+async fn main() {
+    let source_dir = tempfile::tempdir().unwrap();
+    let payload = source_dir.path().join("payload.bin");
+    let data: Vec<u8> = (0..PAYLOAD_SIZE).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&payload, &data).unwrap();
+
+    println!(
+        "Loopback with {} ms one-way latency, {} KiB window per stream",
+        LATENCY.as_millis(),
+        SEGMENT_SIZE * WINDOW_SEGMENTS / 1024
+    );
+    for streams in [1, 2, 4, 8] {
+        let throughput = measure(streams, &payload).await;
+        println!("{:>2} streams: {:>8.1} MiB/s", streams, throughput);
+    }
+}
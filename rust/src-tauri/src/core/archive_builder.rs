@@ -0,0 +1,347 @@
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use crate::plugin_api::base::PluginError;
+use crate::plugin_api::compression::{persist_replacement, replacement_file, EntryMetadata, StreamEntry, WriteSeek};
+use crate::plugin_api::types::CompressionOptions;
+use crate::plugins::registry::PluginRef;
+
+enum Source<'a> {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send + 'a>),
+    File(PathBuf),
+    Directory,
+    // A directory on disk with everything below it
+    Tree(PathBuf),
+    Symlink(String),
+}
+
+struct PendingEntry<'a> {
+    name: String,
+    source: Source<'a>,
+    // Files and trees from disk take theirs from the file system when unset
+    metadata: Option<EntryMetadata>,
+}
+
+// Builds an archive from buffers, readers and files with any compression plugin that
+// implements `compress_stream`. Nothing is read until the archive is written, and files
+// are opened one at a time as the plugin gets to them.
+pub struct ArchiveBuilder<'a> {
+    plugin: PluginRef,
+    entries: Vec<PendingEntry<'a>>,
+}
+
+impl<'a> ArchiveBuilder<'a> {
+    pub fn new(plugin: PluginRef) -> Result<Self, PluginError> {
+        if !plugin.call(|plugin| Ok(plugin.as_compression().is_some()))? {
+            return Err(PluginError::InvalidInput(format!(
+                "Plugin '{}' is not a compression plugin",
+                plugin.metadata().name
+            )));
+        }
+        Ok(Self {
+            plugin,
+            entries: Vec::new(),
+        })
+    }
+
+    // Entry names are paths inside the archive, `/`-separated and relative
+    pub fn add_bytes(&mut self, name: impl Into<String>, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.push(name, Source::Bytes(data.into()))
+    }
+
+    pub fn add_reader(&mut self, name: impl Into<String>, reader: impl Read + Send + 'a) -> &mut Self {
+        self.push(name, Source::Reader(Box::new(reader)))
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> &mut Self {
+        self.push(name, Source::File(path.into()))
+    }
+
+    // An empty directory entry
+    pub fn add_dir(&mut self, name: impl Into<String>) -> &mut Self {
+        self.push(name, Source::Directory)
+    }
+
+    // `path` and everything below it, under `name`; an empty name puts its contents at the
+    // root of the archive. Symlinks are stored as symlinks, not followed.
+    pub fn add_dir_all(&mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> &mut Self {
+        self.push(name, Source::Tree(path.into()))
+    }
+
+    pub fn add_symlink(&mut self, name: impl Into<String>, target: impl Into<String>) -> &mut Self {
+        self.push(name, Source::Symlink(target.into()))
+    }
+
+    // Replaces the metadata of the entry added last; for `add_dir_all` that is the top
+    // directory only
+    pub fn metadata(&mut self, metadata: EntryMetadata) -> &mut Self {
+        if let Some(entry) = self.entries.last_mut() {
+            entry.metadata = Some(metadata);
+        }
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn write_to(self, output: &mut dyn Write, options: &CompressionOptions) -> Result<(), PluginError> {
+        let plugin = self.plugin.clone();
+        let mut entries = self.stream_entries();
+        plugin.call(|plugin| match plugin.as_compression() {
+            Some(plugin) => plugin.compress_stream(&mut entries, output, options),
+            None => Err(PluginError::NotImplemented),
+        })
+    }
+
+    // For outputs that can seek, which zip and 7z write to directly instead of in memory
+    pub fn write_to_seekable(self, output: &mut dyn WriteSeek, options: &CompressionOptions) -> Result<(), PluginError> {
+        let plugin = self.plugin.clone();
+        let mut entries = self.stream_entries();
+        plugin.call(|plugin| match plugin.as_compression() {
+            Some(plugin) => plugin.compress_seekable(&mut entries, output, options),
+            None => Err(PluginError::NotImplemented),
+        })
+    }
+
+    // The archive only replaces `path` once it is complete, so a failed build leaves no
+    // partial archive behind and any file already there as it was
+    pub fn write_to_file(self, path: &Path, options: &CompressionOptions) -> Result<(), PluginError> {
+        let mut temp = replacement_file(path)?;
+        self.write_to_seekable(&mut temp, options)?;
+        temp.as_file().sync_all().map_err(|e| PluginError::io(path, e))?;
+        persist_replacement(temp, path)
+    }
+
+    // Adds this builder's entries to an existing archive, replacing entries of the same name,
+    // and deletes the entries named in `remove`
+    pub fn update_archive(self, archive_file: &Path, remove: &[String], options: &CompressionOptions) -> Result<(), PluginError> {
+        let plugin = self.plugin.clone();
+        let mut entries = self.stream_entries();
+        plugin.call(|plugin| match plugin.as_compression() {
+            Some(plugin) => plugin.update(archive_file, &mut entries, remove, options),
+            None => Err(PluginError::NotImplemented),
+        })
+    }
+
+    // Convenience for callers that want the archive as a buffer
+    pub fn to_vec(self, options: &CompressionOptions) -> Result<Vec<u8>, PluginError> {
+        let mut output = Cursor::new(Vec::new());
+        self.write_to_seekable(&mut output, options)?;
+        Ok(output.into_inner())
+    }
+
+    fn push(&mut self, name: impl Into<String>, source: Source<'a>) -> &mut Self {
+        self.entries.push(PendingEntry {
+            name: name.into(),
+            source,
+            metadata: None,
+        });
+        self
+    }
+
+    fn stream_entries(self) -> impl Iterator<Item = Result<StreamEntry<'a>, PluginError>> {
+        self.entries.into_iter().flat_map(|entry| -> Box<dyn Iterator<Item = _> + 'a> {
+            let PendingEntry { name, source, metadata } = entry;
+            if let Source::Tree(root) = source {
+                return Box::new(tree_entries(name, root, metadata));
+            }
+            let entry = check_name(&name).and_then(|()| {
+                let entry = match source {
+                    Source::Bytes(data) => StreamEntry::new(name, Cursor::new(data)),
+                    Source::Reader(reader) => StreamEntry::new(name, reader),
+                    Source::File(path) => return file_entry(name, &path, metadata),
+                    Source::Directory => StreamEntry::directory(name),
+                    Source::Symlink(target) => StreamEntry::symlink(name, target),
+                    Source::Tree(_) => unreachable!(),
+                };
+                Ok(entry.with_metadata(metadata.unwrap_or_default()))
+            });
+            Box::new(std::iter::once(entry))
+        })
+    }
+}
+
+// Absolute names and `..` would let the archive write outside wherever it is extracted
+fn check_name(name: &str) -> Result<(), PluginError> {
+    let invalid = name.is_empty()
+        || name.starts_with('/')
+        || name.contains('\\')
+        || name.trim_end_matches('/').split('/').any(|part| matches!(part, "" | "." | ".."));
+    if invalid {
+        return Err(PluginError::InvalidInput(format!("Invalid archive entry name '{}'", name)));
+    }
+    Ok(())
+}
+
+fn file_entry<'a>(name: String, path: &Path, metadata: Option<EntryMetadata>) -> Result<StreamEntry<'a>, PluginError> {
+    let file = File::open(path).map_err(|e| PluginError::io(path, e))?;
+    let metadata = match metadata {
+        Some(metadata) => metadata,
+        None => disk_metadata(&file.metadata().map_err(|e| PluginError::io(path, e))?),
+    };
+    Ok(StreamEntry::new(name, file).with_metadata(metadata))
+}
+
+fn tree_entries<'a>(
+    name: String,
+    root: PathBuf,
+    metadata: Option<EntryMetadata>,
+) -> impl Iterator<Item = Result<StreamEntry<'a>, PluginError>> + 'a {
+    let prefix = name.trim_end_matches('/').to_string();
+    let checked = if prefix.is_empty() { Ok(()) } else { check_name(&prefix) };
+    let walk = WalkDir::new(&root).sort_by_file_name().into_iter();
+    let mut metadata = Some(metadata);
+    checked.err().map(Err).into_iter().chain(walk.filter_map(move |item| {
+        let item = match item {
+            Ok(item) => item,
+            Err(e) => {
+                let path = e.path().unwrap_or(&root).to_path_buf();
+                return Some(Err(match e.into_io_error() {
+                    Some(e) => PluginError::io(path, e),
+                    None => PluginError::other(format!("Cannot walk '{}'", path.display())),
+                }));
+            }
+        };
+        // Only the top directory takes the metadata given to the builder
+        let metadata = metadata.take().flatten();
+        let relative = item.path().strip_prefix(&root).unwrap_or(item.path());
+        let relative: Vec<_> = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+        let name = match (prefix.is_empty(), relative.is_empty()) {
+            (true, true) => return None,
+            (true, false) => relative.join("/"),
+            (false, true) => prefix.clone(),
+            (false, false) => format!("{}/{}", prefix, relative.join("/")),
+        };
+        Some(disk_entry(name, item.path(), metadata))
+    }))
+}
+
+fn disk_entry<'a>(name: String, path: &Path, metadata: Option<EntryMetadata>) -> Result<StreamEntry<'a>, PluginError> {
+    let file_metadata = fs::symlink_metadata(path).map_err(|e| PluginError::io(path, e))?;
+    let metadata = metadata.unwrap_or_else(|| disk_metadata(&file_metadata));
+    let entry = if file_metadata.is_dir() {
+        StreamEntry::directory(name)
+    } else if file_metadata.file_type().is_symlink() {
+        let target = fs::read_link(path).map_err(|e| PluginError::io(path, e))?;
+        StreamEntry::symlink(name, target.to_string_lossy())
+    } else {
+        return file_entry(name, path, Some(metadata));
+    };
+    Ok(entry.with_metadata(metadata))
+}
+
+fn disk_metadata(metadata: &fs::Metadata) -> EntryMetadata {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o7777)
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    EntryMetadata {
+        modified: metadata.modified().ok(),
+        mode,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use crate::plugin_api::error::ErrorCode;
+    use crate::plugins::registry::PluginRegistry;
+    use crate::plugins::sevenz::SevenZipPlugin;
+    use crate::plugins::zip::ZipPlugin;
+    use tempfile::tempdir;
+
+    fn zip_builder<'a>() -> ArchiveBuilder<'a> {
+        let registry = PluginRegistry::new();
+        ArchiveBuilder::new(registry.register("zip".to_string(), Box::new(ZipPlugin::new())).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_builds_zip_from_memory_and_disk() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("site/css")).unwrap();
+        fs::write(dir.path().join("site/index.html"), "<html>").unwrap();
+        fs::write(dir.path().join("site/css/main.css"), "body {}").unwrap();
+        fs::write(dir.path().join("notes.txt"), "notes").unwrap();
+
+        // Zip times have a resolution of two seconds
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut builder = zip_builder();
+        builder
+            .add_bytes("generated/report.csv", "a,b\n1,2\n")
+            .metadata(EntryMetadata { modified: Some(modified), mode: Some(0o600) })
+            .add_reader("stream.txt", &b"streamed"[..])
+            .add_file("notes.txt", dir.path().join("notes.txt"))
+            .add_dir("empty")
+            .add_symlink("latest", "generated/report.csv")
+            .add_dir_all("site", dir.path().join("site"));
+        assert_eq!(builder.len(), 6);
+        let archive = builder.to_vec(&CompressionOptions::default()).unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let names: Vec<_> = zip.file_names().map(str::to_string).collect();
+        for name in ["empty/", "site/", "site/css/", "site/css/main.css", "site/index.html", "latest"] {
+            assert!(names.iter().any(|n| n == name), "{} missing from {:?}", name, names);
+        }
+
+        let mut report = zip.by_name("generated/report.csv").unwrap();
+        assert_eq!(report.unix_mode().map(|mode| mode & 0o777), Some(0o600));
+        let time = report.last_modified();
+        let local = chrono::DateTime::<chrono::Local>::from(modified);
+        assert_eq!(
+            (time.year() as i32, time.month() as u32, time.day() as u32),
+            (chrono::Datelike::year(&local), chrono::Datelike::month(&local), chrono::Datelike::day(&local))
+        );
+        let mut contents = String::new();
+        report.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "a,b\n1,2\n");
+        drop(report);
+
+        let mut css = String::new();
+        zip.by_name("site/css/main.css").unwrap().read_to_string(&mut css).unwrap();
+        assert_eq!(css, "body {}");
+    }
+
+    #[test]
+    fn test_rejects_what_cannot_be_built() {
+        let dir = tempdir().unwrap();
+
+        for name in ["", "/etc/passwd", "../up.txt", "a/../../b", "a\\b"] {
+            let mut builder = zip_builder();
+            builder.add_bytes(name, "data");
+            let err = builder.to_vec(&CompressionOptions::default()).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::InvalidInput, "{:?}", name);
+        }
+
+        let mut builder = zip_builder();
+        builder.add_file("missing.txt", dir.path().join("missing.txt"));
+        let output = dir.path().join("out.zip");
+        let err = builder.write_to_file(&output, &CompressionOptions::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Io);
+        assert!(!output.exists());
+
+        fs::write(&output, "previous").unwrap();
+        let mut builder = zip_builder();
+        builder.add_file("missing.txt", dir.path().join("missing.txt"));
+        builder.write_to_file(&output, &CompressionOptions::default()).unwrap_err();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "previous");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let registry = PluginRegistry::new();
+        let sevenz = registry.register("7z".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+        let mut builder = ArchiveBuilder::new(sevenz).unwrap();
+        builder.add_bytes("a.txt", "a").add_symlink("link", "a.txt");
+        let err = builder.to_vec(&CompressionOptions::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnsupportedFeature);
+    }
+}
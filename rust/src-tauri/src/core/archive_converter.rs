@@ -0,0 +1,330 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::plugin_api::base::PluginError;
+use crate::plugin_api::compression::{
+    persist_replacement, replacement_file, CompressionPlugin, EntryInfo, EntryKind, StreamEntry, WriteSeek,
+};
+use crate::plugin_api::types::CompressionOptions;
+use crate::plugins::registry::PluginRef;
+
+// Entry data is handed from the reading plugin to the writing one in chunks of up to this
+// size, with a few of them in flight
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+type Entries<'a> = dyn Iterator<Item = Result<StreamEntry<'static>, PluginError>> + 'a;
+
+// Rewrites `archive_file`, read by `source`, as an archive of `target`'s format into
+// `output`. The source reads on a thread of its own and hands each entry over as it gets
+// to it, so only a few chunks are held in memory, whatever the archive's size; formats that
+// assemble their output in memory, such as zip and 7z streams, still do unless the output
+// can seek, see `convert_archive_seekable`.
+pub fn convert_archive(
+    source: &PluginRef,
+    archive_file: &Path,
+    target: &PluginRef,
+    output: &mut dyn Write,
+    options: &CompressionOptions,
+) -> Result<(), PluginError> {
+    convert_with(source, archive_file, target, |plugin, entries| plugin.compress_stream(entries, output, options))
+}
+
+pub fn convert_archive_seekable(
+    source: &PluginRef,
+    archive_file: &Path,
+    target: &PluginRef,
+    output: &mut dyn WriteSeek,
+    options: &CompressionOptions,
+) -> Result<(), PluginError> {
+    convert_with(source, archive_file, target, |plugin, entries| plugin.compress_seekable(entries, output, options))
+}
+
+fn convert_with(
+    source: &PluginRef,
+    archive_file: &Path,
+    target: &PluginRef,
+    write: impl FnOnce(&dyn CompressionPlugin, &mut Entries<'_>) -> Result<(), PluginError>,
+) -> Result<(), PluginError> {
+    // A rendezvous channel, so the source only moves to the next entry once the target
+    // asked for it
+    let (entries_tx, entries_rx) = mpsc::sync_channel::<(EntryInfo, Receiver<Chunk>)>(0);
+
+    thread::scope(|scope| {
+        let reading = scope.spawn(move || {
+            source.call(|plugin| {
+                let plugin = plugin.as_compression().ok_or(PluginError::NotImplemented)?;
+                plugin.read_entries(archive_file, &mut |info, data| {
+                    let (chunks_tx, chunks_rx) = mpsc::sync_channel(CHUNKS_IN_FLIGHT);
+                    // The target stopped taking entries, its error is the one reported
+                    entries_tx.send((info, chunks_rx)).map_err(|_| PluginError::Cancelled)?;
+                    pump(data, &chunks_tx)
+                })
+            })
+        });
+
+        let mut entries = EntryStream {
+            entries: entries_rx,
+            previous: None,
+        };
+        let written = target.call(|plugin| {
+            let plugin = plugin.as_compression().ok_or(PluginError::NotImplemented)?;
+            write(plugin, &mut entries)
+        });
+        // Lets the source run to its end if the target stopped early
+        drop(entries);
+
+        let read = reading
+            .join()
+            .unwrap_or_else(|payload| Err(PluginError::from_panic(&source.metadata().name, payload)));
+        match (read, written) {
+            (Err(PluginError::Cancelled), Err(e)) => Err(e),
+            (Err(e), _) | (Ok(()), Err(e)) => Err(e),
+            (Ok(()), Ok(())) => Ok(()),
+        }
+    })
+}
+
+// `convert_archive` into a file, which only appears once the archive is complete; it may be
+// `archive_file` itself
+pub fn convert_archive_file(
+    source: &PluginRef,
+    archive_file: &Path,
+    target: &PluginRef,
+    output_file: &Path,
+    options: &CompressionOptions,
+) -> Result<(), PluginError> {
+    let mut temp = replacement_file(output_file)?;
+    convert_archive_seekable(source, archive_file, target, &mut temp, options)?;
+    persist_replacement(temp, output_file)
+}
+
+// `End` tells a complete entry from a source that stopped halfway through it
+enum Chunk {
+    Data(Vec<u8>),
+    End,
+}
+
+fn pump(data: &mut dyn Read, chunks: &SyncSender<Chunk>) -> Result<(), PluginError> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = data.read(&mut buf)?;
+        let chunk = if read == 0 { Chunk::End } else { Chunk::Data(buf[..read].to_vec()) };
+        if chunks.send(chunk).is_err() {
+            // The target skipped the rest of this entry; some formats decode entries in
+            // sequence, so it is still read through
+            io::copy(data, &mut io::sink())?;
+            return Ok(());
+        }
+        if read == 0 {
+            return Ok(());
+        }
+    }
+}
+
+// `None` once the target moved on to the next entry
+type SharedChunks = Arc<Mutex<Option<Receiver<Chunk>>>>;
+
+// The entries as the target pulls them
+struct EntryStream {
+    entries: Receiver<(EntryInfo, Receiver<Chunk>)>,
+    previous: Option<SharedChunks>,
+}
+
+impl Iterator for EntryStream {
+    type Item = Result<StreamEntry<'static>, PluginError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A target asking for the next entry is done with the last one, even if it still
+        // holds it; closing it lets the source move on instead of waiting to hand over more
+        if let Some(previous) = self.previous.take() {
+            previous.lock().unwrap_or_else(|e| e.into_inner()).take();
+        }
+        loop {
+            let (mut info, chunks) = self.entries.recv().ok()?;
+            match normalize_name(&info.name) {
+                // The archive's own root, as in `./`
+                Some(name) if name.is_empty() && info.kind == EntryKind::Directory => continue,
+                Some(name) if !name.is_empty() => info.name = name,
+                _ => return Some(Err(PluginError::InvalidInput(format!("Invalid archive entry name '{}'", info.name)))),
+            }
+            let chunks = Arc::new(Mutex::new(Some(chunks)));
+            self.previous = Some(Arc::clone(&chunks));
+            return Some(Ok(StreamEntry::from_info(info, ChunkReader::new(chunks))));
+        }
+    }
+}
+
+// Source archives may spell names as `./a`, `/a`, `a//b` or `a\b`; the target gets plain
+// `/`-separated relative names. `None` for names reaching above the archive.
+fn normalize_name(name: &str) -> Option<String> {
+    let parts: Vec<&str> = name.split(['/', '\\']).filter(|part| !matches!(*part, "" | ".")).collect();
+    (!parts.contains(&"..")).then(|| parts.join("/"))
+}
+
+// The target's side of one entry
+struct ChunkReader {
+    chunks: SharedChunks,
+    current: Vec<u8>,
+    offset: usize,
+    done: bool,
+}
+
+impl ChunkReader {
+    fn new(chunks: SharedChunks) -> Self {
+        Self {
+            chunks,
+            current: Vec::new(),
+            offset: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.current.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let chunks = self.chunks.lock().unwrap_or_else(|e| e.into_inner());
+            let Some(chunks) = chunks.as_ref() else {
+                return Err(io::Error::other("The entry was read after the next one was taken"));
+            };
+            match chunks.recv() {
+                Ok(Chunk::Data(data)) => {
+                    self.current = data;
+                    self.offset = 0;
+                }
+                Ok(Chunk::End) => self.done = true,
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The source archive stopped in the middle of an entry",
+                    ))
+                }
+            }
+        }
+        let read = buf.len().min(self.current.len() - self.offset);
+        buf[..read].copy_from_slice(&self.current[self.offset..self.offset + read]);
+        self.offset += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::plugin_api::compression::{EntryKind, EntryMetadata};
+    use crate::plugin_api::error::ErrorCode;
+    use crate::plugins::registry::PluginRegistry;
+    use crate::plugins::sevenz::SevenZipPlugin;
+    use crate::plugins::zip::ZipPlugin;
+    use crate::plugins::zstd::ZstdPlugin;
+    use tempfile::tempdir;
+
+    fn read_back(plugin: &PluginRef, archive: &Path) -> Vec<(EntryInfo, Vec<u8>)> {
+        let mut entries = Vec::new();
+        plugin
+            .call(|plugin| {
+                plugin.as_compression().unwrap().read_entries(archive, &mut |info, data| {
+                    let mut contents = Vec::new();
+                    data.read_to_end(&mut contents)?;
+                    entries.push((info, contents));
+                    Ok(())
+                })
+            })
+            .unwrap();
+        entries.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        entries
+    }
+
+    #[test]
+    fn test_converts_between_formats() {
+        let dir = tempdir().unwrap();
+        let registry = PluginRegistry::new();
+        let zip = registry.register("zip".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        let sevenz = registry.register("7z".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+        let zstd = registry.register("zstd".to_string(), Box::new(ZstdPlugin::new())).unwrap();
+
+        // Larger than a few chunks, so it only fits through the pipe in pieces
+        let big: Vec<u8> = (0..CHUNK_SIZE * 10).map(|i| (i % 251) as u8).collect();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        let sevenz_archive = dir.path().join("in.7z");
+        let mut entries = vec![
+            Ok(StreamEntry::new("big.bin", big.as_slice())
+                .with_metadata(EntryMetadata { modified: Some(modified), mode: None })),
+            Ok(StreamEntry::new("docs/readme.txt", &b"read me"[..])),
+            Ok(StreamEntry::directory("empty")),
+        ]
+        .into_iter();
+        let mut file = fs::File::create(&sevenz_archive).unwrap();
+        sevenz
+            .call(|plugin| {
+                let plugin = plugin.as_compression().unwrap();
+                plugin.compress_stream(&mut entries, &mut file, &CompressionOptions::default())
+            })
+            .unwrap();
+
+        let zip_archive = dir.path().join("out.zip");
+        let mut output = fs::File::create(&zip_archive).unwrap();
+        convert_archive(&sevenz, &sevenz_archive, &zip, &mut output, &CompressionOptions::default()).unwrap();
+        drop(output);
+
+        let converted = read_back(&zip, &zip_archive);
+        let names: Vec<_> = converted.iter().map(|(info, _)| info.name.as_str()).collect();
+        assert_eq!(names, ["big.bin", "docs/readme.txt", "empty"]);
+        assert_eq!(converted[0].1, big);
+        assert_eq!(converted[0].0.metadata.modified, Some(modified));
+        assert_eq!(converted[1].1, b"read me");
+        assert_eq!(converted[2].0.kind, EntryKind::Directory);
+
+        // zstd holds one file only, the target's error comes through
+        let err = convert_archive(&zip, &zip_archive, &zstd, &mut Vec::new(), &CompressionOptions::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnsupportedFeature);
+
+        // A damaged source fails the conversion rather than cutting entries short
+        let truncated = dir.path().join("truncated.7z");
+        let data = fs::read(&sevenz_archive).unwrap();
+        fs::write(&truncated, &data[..data.len() / 2]).unwrap();
+        assert!(convert_archive(&sevenz, &truncated, &zip, &mut Vec::new(), &CompressionOptions::default()).is_err());
+    }
+
+    fn raw_zip(path: &Path, names: &[&str]) {
+        let mut writer = ::zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for name in names {
+            if name.ends_with('/') {
+                writer.add_directory(*name, Default::default()).unwrap();
+            } else {
+                writer.start_file(*name, Default::default()).unwrap();
+                writer.write_all(name.as_bytes()).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_normalizes_source_names() {
+        let dir = tempdir().unwrap();
+        let registry = PluginRegistry::new();
+        let zip = registry.register("zip".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        let sevenz = registry.register("7z".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+
+        let source = dir.path().join("in.zip");
+        raw_zip(&source, &["./", "./a.txt", "dir\\b.txt", "/abs.txt"]);
+        let output = dir.path().join("out.7z");
+        convert_archive_file(&zip, &source, &sevenz, &output, &CompressionOptions::default()).unwrap();
+        let converted = read_back(&sevenz, &output);
+        let names: Vec<_> = converted.iter().map(|(info, _)| info.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "abs.txt", "dir/b.txt"]);
+        assert_eq!(converted[2].1, b"dir\\b.txt");
+
+        raw_zip(&source, &["a.txt", "../up.txt"]);
+        let err = convert_archive_file(&zip, &source, &sevenz, &output, &CompressionOptions::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidInput);
+        assert_eq!(read_back(&sevenz, &output).len(), 3);
+    }
+}
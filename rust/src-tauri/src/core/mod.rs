@@ -1,7 +1,14 @@
+pub mod archive_builder;
+pub mod archive_converter;
 pub mod plugin_manager;
-pub mod plugin_loader;
+pub mod plugin_settings;
 pub mod types;
 pub mod logging;
+pub mod watcher;
 
+pub use archive_builder::ArchiveBuilder;
+pub use archive_converter::{convert_archive, convert_archive_file};
 pub use plugin_manager::PluginManager;
+pub use plugin_settings::PluginSettingsStore;
 pub use logging::{init_logging, get_logs_directory, cleanup_old_logs};
+pub use watcher::{FolderWatcher, WatchConfig, WatchRule};
@@ -1,51 +1,598 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail, Result};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinError;
 use crate::plugin_api::base::{Plugin, PluginError};
-use crate::plugin_api::types::PluginMetadata;
-use crate::plugins::registry::PluginRegistry;
+use crate::plugin_api::compression::CompressionPlugin;
+use crate::plugin_api::extract::{extract_archive, ExtractionLimits};
+use crate::plugin_api::settings::{PluginSettings, SettingsSchema};
+use crate::plugin_api::types::{Capability, CompressionOptions, PluginMetadata, PluginType};
+use crate::plugins::manifest::MANIFEST_FILE;
+use crate::plugins::registry::{PluginRef, PluginRegistry};
+use crate::plugins::{AsyncCompression, ExternalPlugin, PluginLoader, PluginTrustConfig};
+use crate::transfer::TransferOptions;
+use super::archive_builder::ArchiveBuilder;
+use super::archive_converter::convert_archive_file;
+use super::plugin_settings::PluginSettingsStore;
 
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginLoadFailure {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+// Everything the frontend needs to render a plugin's settings form
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginSettingsView {
+    pub schema: SettingsSchema,
+    // Current values, one for every setting in `schema`
+    pub values: PluginSettings,
+}
+
+type BuiltinConstructor = fn() -> Box<dyn Plugin>;
+
+// Shared by the UI commands, watch rules and the reloader, so all state is behind locks.
+// Jobs hold a `PluginRef` for their duration; unloading or reloading never waits for them.
 pub struct PluginManager {
     registry: PluginRegistry,
+    // Manifest of every plugin loaded from the plugin directory, keyed by registered name
+    external: Mutex<HashMap<String, PathBuf>>,
+    load_failures: Mutex<Vec<PluginLoadFailure>>,
+    // Decides which signatures external plugins need; requires trusted signatures by default
+    trust: PluginTrustConfig,
+    // Built-in plugins by registered name, so they can be re-created with new settings
+    builtins: HashMap<String, BuiltinConstructor>,
+    // Also holds the limits applied to every extraction that goes through an `ExtractionGuard`
+    settings: Mutex<PluginSettingsStore>,
+    // Where `set_plugin_settings` and `set_extraction_limits` persist to; kept in memory only
+    // when unset
+    settings_path: Option<PathBuf>,
 }
 
 impl PluginManager {
     pub fn new() -> Self {
         Self {
             registry: PluginRegistry::new(),
+            external: Mutex::new(HashMap::new()),
+            load_failures: Mutex::new(Vec::new()),
+            trust: PluginTrustConfig::default(),
+            builtins: HashMap::new(),
+            settings: Mutex::new(PluginSettingsStore::default()),
+            settings_path: None,
+        }
+    }
+
+    pub fn set_trust(&mut self, trust: PluginTrustConfig) {
+        self.trust = trust;
+    }
+
+    // Call before registering plugins so they are initialized with the stored values
+    pub fn set_settings(&mut self, settings: PluginSettingsStore, path: PathBuf) {
+        self.settings = Mutex::new(settings);
+        self.settings_path = Some(path);
+    }
+
+    pub fn extraction_limits(&self) -> ExtractionLimits {
+        self.settings().extraction_limits
+    }
+
+    // Takes effect for extractions started afterwards
+    pub fn set_extraction_limits(&self, limits: ExtractionLimits) -> Result<()> {
+        let mut settings = self.settings();
+        settings.extraction_limits = limits;
+        if let Some(path) = &self.settings_path {
+            settings.save_to(path)?;
         }
+        Ok(())
+    }
+
+    fn external(&self) -> MutexGuard<'_, HashMap<String, PathBuf>> {
+        self.external.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn failures(&self) -> MutexGuard<'_, Vec<PluginLoadFailure>> {
+        self.load_failures.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn settings(&self) -> MutexGuard<'_, PluginSettingsStore> {
+        self.settings.lock().unwrap_or_else(|e| e.into_inner())
     }
 
     pub fn register_default_plugins(&mut self) -> Result<(), PluginError> {
-        let zip_plugin = Box::new(crate::plugins::zip::ZipPlugin::new());
-        let zip_name = zip_plugin.get_config().name.clone();
-        self.registry.register(zip_name, zip_plugin)?;
+        let builtins: [BuiltinConstructor; 4] = [
+            || Box::new(crate::plugins::zip::ZipPlugin::new()),
+            || Box::new(crate::plugins::sevenz::SevenZipPlugin::new()),
+            || Box::new(crate::plugins::zstd::ZstdPlugin::new()),
+            || Box::new(crate::plugins::tcp::TcpTransferPlugin::new()),
+        ];
+        for create in builtins {
+            let plugin = create();
+            let name = plugin.get_config().name.clone();
+            let settings = self.settings().get(&name);
+            self.registry.register_with_settings(name.clone(), plugin, &settings)?;
+            self.builtins.insert(name, create);
+        }
+        Ok(())
+    }
+
+    // Call after `register_default_plugins`: on a name collision the plugin registered first
+    // wins, so built-ins always take precedence, then external plugins in path order.
+    // Plugins wait for the plugins providing their requirements; those whose requirements
+    // are never met, including dependency cycles, are refused.
+    pub fn load_external_plugins(&self, plugin_dir: PathBuf) -> usize {
+        let discovery = match PluginLoader::discover(plugin_dir.clone(), &self.trust) {
+            Ok(discovery) => discovery,
+            Err(e) => {
+                self.record_failure(plugin_dir, format!("Failed to scan plugin directory: {}", e));
+                return 0;
+            }
+        };
+        for rejected in discovery.rejected {
+            self.record_failure(rejected.path, rejected.reason);
+        }
 
-        let sevenz_plugin = Box::new(crate::plugins::sevenz::SevenZipPlugin::new());
-        let sevenz_name = sevenz_plugin.get_config().name.clone();
-        self.registry.register(sevenz_name, sevenz_plugin)?;
+        let mut loaded = 0;
+        let mut pending = discovery.plugins;
+        loop {
+            let (ready, waiting): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|p| self.registry.missing_requirements(&p.plugin.metadata()).is_empty());
+            pending = waiting;
+            if ready.is_empty() {
+                break;
+            }
+            for plugin in ready {
+                let path = plugin.library.clone();
+                match self.register_external(plugin) {
+                    Ok(_) => loaded += 1,
+                    Err(e) => self.record_failure(path, format!("{:#}", e)),
+                }
+            }
+        }
+        for plugin in pending {
+            if let Err(e) = self.check_requirements(&plugin.plugin.metadata()) {
+                self.record_failure(plugin.library, e.to_string());
+            }
+        }
+        loaded
+    }
 
+    fn check_requirements(&self, metadata: &PluginMetadata) -> Result<()> {
+        let missing = self.registry.missing_requirements(metadata);
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing.iter().map(Capability::to_string).collect();
+            bail!("Requires {}, which no loaded plugin provides", missing.join(", "));
+        }
         Ok(())
     }
 
-    pub fn get_plugin(&self, name: &str) -> Option<&Box<dyn Plugin>> {
+    // Refuses to take away a capability that another loaded plugin requires
+    fn check_dependents(&self, name: &str, replacement: Option<&PluginMetadata>) -> Result<()> {
+        let dependents = self.registry.dependents_of(name, replacement);
+        if !dependents.is_empty() {
+            bail!("Plugin '{}' is required by {}", name, dependents.join(", "));
+        }
+        Ok(())
+    }
+
+    pub fn load_external_plugin(&self, manifest_path: &Path) -> Result<String> {
+        self.register_external(PluginLoader::load_from_manifest(manifest_path, &self.trust)?)
+    }
+
+    fn register_external(&self, external: ExternalPlugin) -> Result<String> {
+        let ExternalPlugin { plugin, library, manifest_path } = external;
+        self.check_requirements(&plugin.metadata())?;
+        let name = plugin.get_config().name.clone();
+        let settings = self.settings().get(&name);
+        self.registry
+            .register_with_settings(name.clone(), plugin, &settings)
+            .map_err(registration_error)?;
+        self.loaded_from(&name, manifest_path);
+        log::info!("Loaded plugin '{}' from {}", name, library.display());
+        Ok(name)
+    }
+
+    // Remembers where a plugin came from and forgets earlier failures for that directory
+    fn loaded_from(&self, name: &str, manifest_path: PathBuf) {
+        if let Some(plugin_dir) = manifest_path.parent() {
+            self.failures().retain(|failure| !failure.path.starts_with(plugin_dir));
+        }
+        self.external().insert(name.to_string(), manifest_path);
+    }
+
+    // Loads the plugin again from its manifest. The new instance takes over the name right
+    // away; jobs still holding the old one finish on it, then it is cleaned up and unloaded.
+    pub fn reload_plugin(&self, name: &str) -> Result<()> {
+        let manifest_path = self
+            .external()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Plugin '{}' was not loaded from the plugin directory", name))?;
+        let plugin = PluginLoader::load_from_manifest(&manifest_path, &self.trust)?.plugin;
+        let metadata = plugin.metadata();
+        self.check_requirements(&metadata)?;
+        self.check_dependents(name, Some(&metadata))?;
+        let new_name = plugin.get_config().name.clone();
+        let settings = self.settings().get(&new_name);
+        let previous = self
+            .registry
+            .replace(name, new_name.clone(), plugin, &settings)
+            .map_err(registration_error)?;
+        self.external().remove(name);
+        self.loaded_from(&new_name, manifest_path);
+        log::info!("Reloaded plugin '{}'", new_name);
+
+        // May run the old instance's cleanup if no job is using it
+        drop(previous);
+        Ok(())
+    }
+
+    // Starts a fresh instance of a built-in or external plugin, so it picks up its stored
+    // settings or becomes usable again after a panic disabled it
+    pub fn reinitialize_plugin(&self, name: &str) -> Result<()> {
+        let Some(create) = self.builtins.get(name) else {
+            return self.reload_plugin(name);
+        };
+        let settings = self.settings().get(name);
+        let previous = self
+            .registry
+            .replace(name, name.to_string(), create(), &settings)
+            .map_err(registration_error)?;
+        drop(previous);
+        Ok(())
+    }
+
+    pub fn plugin_settings(&self, name: &str) -> Result<PluginSettingsView> {
+        let plugin = self.get_plugin(name).ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        Ok(PluginSettingsView {
+            schema: plugin.settings_schema().clone(),
+            values: plugin.settings().clone(),
+        })
+    }
+
+    // Validates `values` against the plugin's schema, restarts the plugin with them and then
+    // persists them. Settings the plugin refuses on initialize are rolled back.
+    pub fn set_plugin_settings(&self, name: &str, values: PluginSettings) -> Result<PluginSettingsView> {
+        let plugin = self.get_plugin(name).ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        plugin.settings_schema().resolve(&values)?;
+        drop(plugin);
+
+        let previous = self.settings().set(name, values);
+        if let Err(e) = self.reinitialize_plugin(name) {
+            let mut settings = self.settings();
+            match previous {
+                Some(previous) => settings.set(name, previous),
+                None => settings.plugins.remove(name),
+            };
+            return Err(e);
+        }
+        if let Some(path) = &self.settings_path {
+            self.settings().save_to(path)?;
+        }
+        log::info!("Updated settings of plugin '{}'", name);
+        self.plugin_settings(name)
+    }
+
+    pub fn unload_plugin(&self, name: &str) -> Result<()> {
+        self.check_dependents(name, None)?;
+        let previous = self
+            .registry
+            .unregister(name)
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+        self.external().remove(name);
+        log::info!("Unloaded plugin '{}'", name);
+        drop(previous);
+        Ok(())
+    }
+
+    // Brings the registry in line with one plugin directory after its files changed
+    pub fn sync_plugin_dir(&self, plugin_dir: &Path) -> Result<()> {
+        let manifest_path = plugin_dir.join(MANIFEST_FILE);
+        let loaded = self
+            .external()
+            .iter()
+            .find(|(_, path)| **path == manifest_path)
+            .map(|(name, _)| name.clone());
+
+        match loaded {
+            Some(name) if manifest_path.is_file() => self.reload_plugin(&name),
+            Some(name) => self.unload_plugin(&name),
+            None if manifest_path.is_file() => self.load_external_plugin(&manifest_path).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, path: PathBuf, reason: String) {
+        log::warn!("Failed to load plugin {}: {}", path.display(), reason);
+        self.failures().push(PluginLoadFailure { path, reason });
+    }
+
+    pub fn load_failures(&self) -> Vec<PluginLoadFailure> {
+        self.failures().clone()
+    }
+
+    pub fn get_plugin(&self, name: &str) -> Option<PluginRef> {
         self.registry.get_plugin(name)
     }
 
-    pub fn get_plugin_mut(&mut self, name: &str) -> Option<&mut Box<dyn Plugin>> {
-        self.registry.get_plugin_mut(name)
+    // Blocks the calling thread; async callers use `compress_async`
+    pub fn compress(
+        &self,
+        plugin_name: &str,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<()> {
+        self.with_compression_plugin(plugin_name, |plugin| {
+            plugin.compress(input_files, output_file, options)
+        })
+    }
+
+    pub fn decompress(&self, plugin_name: &str, archive_file: &Path, output_dir: &Path, overwrite: bool) -> Result<()> {
+        let limits = self.extraction_limits();
+        self.with_compression_plugin(plugin_name, |plugin| {
+            extract_archive(plugin, archive_file, output_dir, overwrite, limits)
+        })
+    }
+
+    // The plugin stays referenced for the whole job, even if it is reloaded meanwhile
+    fn with_compression_plugin<T>(
+        &self,
+        name: &str,
+        job: impl FnOnce(&dyn CompressionPlugin) -> Result<T, PluginError>,
+    ) -> Result<T> {
+        let plugin = self.get_plugin(name).ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        plugin
+            .call(|plugin| plugin.as_compression().map(job).transpose())?
+            .ok_or_else(|| anyhow!("Plugin '{}' is not a compression plugin", name))
+    }
+
+    // For embedders building archives from buffers or generated content
+    pub fn archive_builder<'a>(&self, plugin_name: &str) -> Result<ArchiveBuilder<'a>> {
+        let plugin = self.get_plugin(plugin_name).ok_or_else(|| anyhow!("Plugin '{}' not found", plugin_name))?;
+        Ok(ArchiveBuilder::new(plugin)?)
+    }
+
+    // Behind the `update_archive` command. Files and directories are stored under their own
+    // name, as `compress` does, replacing entries of that name.
+    pub async fn update_archive_async(
+        &self,
+        plugin_name: &str,
+        archive_file: PathBuf,
+        add_files: Vec<PathBuf>,
+        remove: Vec<String>,
+        options: CompressionOptions,
+    ) -> Result<()> {
+        let mut builder = self.archive_builder(plugin_name)?;
+        for path in add_files {
+            let name = path
+                .file_name()
+                .ok_or_else(|| PluginError::InvalidInput(format!("Invalid file name '{}'", path.display())))?
+                .to_string_lossy()
+                .into_owned();
+            if path.is_dir() {
+                builder.add_dir_all(name, path);
+            } else {
+                builder.add_file(name, path);
+            }
+        }
+        let job = tokio::task::spawn_blocking(move || builder.update_archive(&archive_file, &remove, &options));
+        Ok(joined(job.await, plugin_name)?)
+    }
+
+    // Behind the `convert_archive` command; the entries go straight from one plugin to the
+    // other without being extracted
+    pub async fn convert_archive_async(
+        &self,
+        source_plugin: &str,
+        archive_file: PathBuf,
+        target_plugin: &str,
+        output_file: PathBuf,
+        options: CompressionOptions,
+    ) -> Result<()> {
+        let source = self.compression_plugin(source_plugin)?;
+        let target = self.compression_plugin(target_plugin)?;
+        let job = tokio::task::spawn_blocking(move || {
+            convert_archive_file(&source, &archive_file, &target, &output_file, &options)
+        });
+        // Panics on the source's reading thread already come back as its error, so one here
+        // happened while writing
+        Ok(joined(job.await, target_plugin)?)
+    }
+
+    fn compression_plugin(&self, name: &str) -> Result<PluginRef> {
+        let plugin = self.get_plugin(name).ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        if !plugin.call(|plugin| Ok(plugin.as_compression().is_some()))? {
+            bail!("Plugin '{}' is not a compression plugin", name);
+        }
+        Ok(plugin)
+    }
+
+    // Behind the `compress_files` command and folder watch rules
+    pub async fn compress_async(
+        &self,
+        plugin_name: &str,
+        input_files: Vec<PathBuf>,
+        output_file: PathBuf,
+        options: CompressionOptions,
+    ) -> Result<()> {
+        let job = self.async_compression(plugin_name)?;
+        Ok(job.compress(input_files, output_file, options).await?)
+    }
+
+    // Behind the `decompress_file` command
+    pub async fn decompress_async(
+        &self,
+        plugin_name: &str,
+        archive_file: PathBuf,
+        output_dir: PathBuf,
+        overwrite: bool,
+    ) -> Result<()> {
+        let job = self.async_compression(plugin_name)?;
+        Ok(job.decompress(archive_file, output_dir, overwrite).await?)
+    }
+
+    fn async_compression(&self, name: &str) -> Result<AsyncCompression> {
+        let plugin = self.get_plugin(name).ok_or_else(|| anyhow!("Plugin '{}' not found", name))?;
+        let job = AsyncCompression::new(plugin).ok_or_else(|| anyhow!("Plugin '{}' is not a compression plugin", name))?;
+        Ok(job.with_limits(self.extraction_limits()))
+    }
+
+    // `target` is `scheme://address`, or a bare `host:port` for the built-in TCP transfer
+    pub async fn send_files(&self, files: Vec<PathBuf>, target: String, options: &TransferOptions) -> Result<()> {
+        crate::transfer::send_files_with_options(&self.registry, files, target, options).await
+    }
+
+    pub async fn receive_files(&self, source: String, output_dir: PathBuf) -> Result<()> {
+        crate::transfer::receive_files(&self.registry, source, output_dir).await
+    }
+
+    pub fn plugins_of_type(&self, plugin_type: &PluginType) -> Vec<PluginRef> {
+        self.registry.plugins_of_type(plugin_type)
+    }
+
+    pub fn find_plugins(&self, required: &[Capability]) -> Vec<PluginRef> {
+        self.registry.plugins_with(required)
+    }
+
+    pub fn plugin_for_path(&self, path: &Path) -> Option<PluginRef> {
+        self.registry.plugin_for_path(path)
     }
 
     pub fn list_plugins(&self) -> Vec<PluginMetadata> {
         self.registry.list_plugins()
     }
 
-    pub fn cleanup(&mut self) -> Result<(), PluginError> {
-        self.registry.cleanup_all()
+    pub fn cleanup(&self) {
+        self.registry.cleanup_all();
+        self.external().clear();
+    }
+
+    // Reloads plugins whose directory under `plugin_dir` changes, until the reloader is dropped
+    pub fn watch_plugin_dir(self: &Arc<Self>, plugin_dir: PathBuf) -> Result<PluginReloader> {
+        PluginReloader::start(Arc::downgrade(self), plugin_dir)
+    }
+}
+
+// A blocking job only gets cancelled when the runtime shuts down; a panic in it is reported
+// as one, on behalf of the plugin it was working for
+fn joined<T>(job: Result<Result<T, PluginError>, JoinError>, plugin: &str) -> Result<T, PluginError> {
+    match job {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => Err(PluginError::from_panic(plugin, e.into_panic())),
+        Err(_) => Err(PluginError::Cancelled),
+    }
+}
+
+fn registration_error(error: PluginError) -> anyhow::Error {
+    match error {
+        PluginError::AlreadyExists(name) => anyhow!("A plugin named '{}' is already registered", name),
+        e => anyhow!("Initialization failed: {}", e),
     }
 }
 
 impl Drop for PluginManager {
     fn drop(&mut self) {
-        if let Err(e) = self.cleanup() {
-            eprintln!("Error during plugin cleanup: {}", e);
+        self.cleanup();
+    }
+}
+
+enum ReloadMessage {
+    Event(notify::Result<Event>),
+    Stop,
+}
+
+pub struct PluginReloader {
+    _watcher: RecommendedWatcher,
+    sender: Sender<ReloadMessage>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PluginReloader {
+    fn start(manager: Weak<PluginManager>, plugin_dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&plugin_dir)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let event_sender = sender.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = event_sender.send(ReloadMessage::Event(event));
+        })?;
+        watcher.watch(&plugin_dir, RecursiveMode::Recursive)?;
+
+        let thread = std::thread::spawn(move || run_reload_loop(manager, plugin_dir, receiver));
+        Ok(Self {
+            _watcher: watcher,
+            sender,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for PluginReloader {
+    fn drop(&mut self) {
+        let _ = self.sender.send(ReloadMessage::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_reload_loop(
+    manager: Weak<PluginManager>,
+    plugin_root: PathBuf,
+    receiver: mpsc::Receiver<ReloadMessage>,
+) {
+    // Builds write many files in a burst, so each plugin directory waits for a quiet period
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    // Events carry canonical paths on some platforms
+    let canonical_root = plugin_root.canonicalize().unwrap_or_else(|_| plugin_root.clone());
+
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(ReloadMessage::Event(Ok(event))) => {
+                for path in event.paths {
+                    let plugin_dir = path
+                        .strip_prefix(&plugin_root)
+                        .or_else(|_| path.strip_prefix(&canonical_root))
+                        .ok()
+                        .and_then(|relative| relative.components().next())
+                        .map(|first| plugin_root.join(first));
+                    if let Some(plugin_dir) = plugin_dir {
+                        pending.insert(plugin_dir, Instant::now());
+                    }
+                }
+            }
+            Ok(ReloadMessage::Event(Err(e))) => log::warn!("Plugin watch error: {}", e),
+            Ok(ReloadMessage::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let now = Instant::now();
+        let ready: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= RELOAD_DEBOUNCE)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        if ready.is_empty() {
+            continue;
+        }
+        let Some(manager) = manager.upgrade() else {
+            break;
+        };
+        for plugin_dir in ready {
+            pending.remove(&plugin_dir);
+            if let Err(e) = manager.sync_plugin_dir(&plugin_dir) {
+                manager.record_failure(plugin_dir, format!("{:#}", e));
+            }
         }
     }
 }
@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+use crate::plugin_api::extract::ExtractionLimits;
+use crate::plugin_api::platform::get_config_dir;
+use crate::plugin_api::settings::PluginSettings;
+
+pub const PLUGIN_SETTINGS_FILE: &str = "plugin_settings.json";
+
+// Values the user saved for each plugin, keyed by registered name. Only what the user
+// changed is stored; plugins fill in their own defaults for the rest.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginSettingsStore {
+    #[serde(default)]
+    pub plugins: HashMap<String, PluginSettings>,
+    // Applied to every extraction, whichever plugin runs it
+    #[serde(default)]
+    pub extraction_limits: ExtractionLimits,
+}
+
+impl PluginSettingsStore {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(PLUGIN_SETTINGS_FILE)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::default_path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, plugin: &str) -> PluginSettings {
+        self.plugins.get(plugin).cloned().unwrap_or_default()
+    }
+
+    // Returns the values stored before
+    pub fn set(&mut self, plugin: &str, settings: PluginSettings) -> Option<PluginSettings> {
+        self.plugins.insert(plugin.to_string(), settings)
+    }
+}
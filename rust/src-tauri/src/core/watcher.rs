@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Serialize, Deserialize};
+use crate::plugin_api::platform::get_config_dir;
+
+const WATCH_RULES_FILE: &str = "watch_rules.json";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Compresses the given files with the named plugin into the output archive
+pub type CompressFn = Arc<dyn Fn(&str, &[PathBuf], &Path) -> Result<(), String> + Send + Sync>;
+
+// Sends the file to the target through the matching transfer plugin
+pub type SendFn = Arc<dyn Fn(&Path, &str) -> Result<(), String> + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchRule {
+    pub id: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub recursive: bool,
+    // Wildcard patterns (`*`, `?`) matched against the file name
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    pub compress_with: Option<String>,
+    #[serde(default = "default_archive_extension")]
+    pub archive_extension: String,
+    pub output_dir: Option<PathBuf>,
+    pub send_to: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_debounce_ms() -> u64 {
+    2000
+}
+
+fn default_archive_extension() -> String {
+    String::from("zip")
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl WatchRule {
+    pub fn output_dir(&self) -> PathBuf {
+        self.output_dir.clone().unwrap_or_else(|| self.path.join("compressed"))
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => return false,
+        };
+        if self.exclude.iter().any(|p| wildcard_match(p, &name)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|p| wildcard_match(p, &name))
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        if !self.enabled || path.starts_with(self.output_dir()) {
+            return false;
+        }
+        let parent_matches = match path.parent() {
+            Some(parent) if self.recursive => parent.starts_with(&self.path),
+            Some(parent) => parent == self.path,
+            None => false,
+        };
+        parent_matches && self.matches(path)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchConfig {
+    pub rules: Vec<WatchRule>,
+}
+
+impl WatchConfig {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(WATCH_RULES_FILE)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::default_path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+enum WatchMessage {
+    Event(notify::Result<Event>),
+    Stop,
+}
+
+struct PendingFile {
+    rule: usize,
+    last_change: Instant,
+    size: u64,
+}
+
+pub struct FolderWatcher {
+    _watcher: RecommendedWatcher,
+    sender: Sender<WatchMessage>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FolderWatcher {
+    pub fn start(rules: Vec<WatchRule>, compress: CompressFn, send: SendFn) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let event_sender = sender.clone();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = event_sender.send(WatchMessage::Event(event));
+        })?;
+
+        let rules: Vec<WatchRule> = rules.into_iter().filter(|r| r.enabled).collect();
+        for rule in &rules {
+            let mode = if rule.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            watcher.watch(&rule.path, mode)?;
+            log::info!("Watching {} for rule '{}'", rule.path.display(), rule.id);
+        }
+
+        let thread = std::thread::spawn(move || run_debounce_loop(rules, receiver, compress, send));
+        Ok(Self {
+            _watcher: watcher,
+            sender,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for FolderWatcher {
+    fn drop(&mut self) {
+        let _ = self.sender.send(WatchMessage::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_debounce_loop(
+    rules: Vec<WatchRule>,
+    receiver: mpsc::Receiver<WatchMessage>,
+    compress: CompressFn,
+    send: SendFn,
+) {
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(WatchMessage::Event(Ok(event))) => {
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    continue;
+                }
+                for path in event.paths {
+                    if let Some(rule) = rules.iter().position(|r| r.covers(&path)) {
+                        // Every new write restarts the debounce window for the file
+                        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                        pending.insert(path, PendingFile { rule, last_change: Instant::now(), size });
+                    }
+                }
+            }
+            Ok(WatchMessage::Event(Err(e))) => log::warn!("Watch error: {}", e),
+            Ok(WatchMessage::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        pending.retain(|path, file| {
+            let debounce = Duration::from_millis(rules[file.rule].debounce_ms);
+            if now.duration_since(file.last_change) < debounce {
+                return true;
+            }
+            match fs::metadata(path) {
+                Ok(metadata) if metadata.is_file() => {
+                    // Still growing although no event arrived, keep waiting
+                    if metadata.len() != file.size {
+                        file.size = metadata.len();
+                        file.last_change = now;
+                        return true;
+                    }
+                    ready.push((path.clone(), file.rule));
+                    false
+                }
+                _ => false,
+            }
+        });
+
+        for (path, rule) in ready {
+            if let Err(e) = process_file(&rules[rule], &path, &compress, &send) {
+                log::error!("Watch rule '{}' failed for {}: {}", rules[rule].id, path.display(), e);
+            }
+        }
+    }
+}
+
+fn process_file(rule: &WatchRule, path: &Path, compress: &CompressFn, send: &SendFn) -> Result<()> {
+    let mut to_send = path.to_path_buf();
+
+    if let Some(plugin) = &rule.compress_with {
+        let output_dir = rule.output_dir();
+        fs::create_dir_all(&output_dir)?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let output = output_dir.join(format!("{}.{}", file_name, rule.archive_extension));
+        compress(plugin, &[path.to_path_buf()], &output).map_err(|e| anyhow::anyhow!(e))?;
+        log::info!("Watch rule '{}' compressed {} to {}", rule.id, path.display(), output.display());
+        to_send = output;
+    }
+
+    if let Some(target) = &rule.send_to {
+        send(&to_send, target).map_err(|e| anyhow::anyhow!(e))?;
+        log::info!("Watch rule '{}' sent {} to {}", rule.id, to_send.display(), target);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    fn rule(path: &Path) -> WatchRule {
+        WatchRule {
+            id: String::from("test"),
+            path: path.to_path_buf(),
+            recursive: false,
+            include: vec![String::from("*.log")],
+            exclude: vec![String::from("skip*")],
+            debounce_ms: 200,
+            compress_with: Some(String::from("ZIP Plugin")),
+            archive_extension: default_archive_extension(),
+            output_dir: None,
+            send_to: None,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*.log", "server.log"));
+        assert!(wildcard_match("data-??.csv", "data-01.csv"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*.log", "server.log.tmp"));
+        assert!(!wildcard_match("data-??.csv", "data-1.csv"));
+    }
+
+    #[test]
+    fn test_rule_filters_and_persistence() {
+        let dir = tempdir().unwrap();
+        let rule = rule(dir.path());
+        assert!(rule.covers(&dir.path().join("app.log")));
+        assert!(!rule.covers(&dir.path().join("skip.log")));
+        assert!(!rule.covers(&dir.path().join("app.txt")));
+        assert!(!rule.covers(&dir.path().join("compressed").join("app.log")));
+
+        let config_path = dir.path().join("config").join(WATCH_RULES_FILE);
+        WatchConfig { rules: vec![rule] }.save_to(&config_path).unwrap();
+        let loaded = WatchConfig::load_from(&config_path).unwrap();
+        assert_eq!(loaded.rules.len(), 1);
+        assert_eq!(loaded.rules[0].include, vec![String::from("*.log")]);
+    }
+
+    #[test]
+    fn test_watcher_compresses_new_files() {
+        let dir = tempdir().unwrap();
+        let calls: Arc<Mutex<Vec<(String, PathBuf)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&calls);
+        let compress: CompressFn = Arc::new(move |plugin, _inputs, output| {
+            recorded.lock().unwrap().push((plugin.to_string(), output.to_path_buf()));
+            Ok(())
+        });
+
+        let send: SendFn = Arc::new(|_file, _target| Ok(()));
+
+        let _watcher = FolderWatcher::start(vec![rule(dir.path())], compress, send).unwrap();
+        fs::write(dir.path().join("app.log"), b"line").unwrap();
+        fs::write(dir.path().join("ignored.txt"), b"line").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while calls.lock().unwrap().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        std::thread::sleep(Duration::from_millis(300));
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].0, "ZIP Plugin");
+        assert_eq!(calls[0].1, dir.path().join("compressed").join("app.log.zip"));
+    }
+}
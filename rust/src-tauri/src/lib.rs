@@ -1,8 +1,12 @@
 pub mod core;
-pub mod plugin_api;
 pub mod plugins;
+pub mod transfer;
+
+pub use smart_transfer_plugin_api as plugin_api;
+// Plugin libraries built against this crate keep using `smart_transfer::declare_*_plugin!`
+pub use smart_transfer_plugin_api::{declare_compression_plugin, declare_plugin};
 
 // Re-export commonly used items
-pub use core::{PluginManager, init_logging, get_logs_directory, cleanup_old_logs};
-pub use plugin_api::compression::CompressionPlugin;
+pub use core::{ArchiveBuilder, PluginManager, init_logging, get_logs_directory, cleanup_old_logs};
+pub use plugin_api::compression::{CompressionPlugin, EntryKind, EntryMetadata};
 pub use plugin_api::types::{CompressionMode, CompressionOptions, PluginMetadata, PluginType};
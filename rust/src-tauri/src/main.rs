@@ -5,70 +5,123 @@
 )]
 
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tauri::State;
-use smart_transfer::plugin_api::types::{CompressionOptions, PluginMetadata, CompressionMode};
-use smart_transfer::plugin_api::compression::CompressionPlugin;
-use smart_transfer::core::plugin_manager::PluginManager;
+use smart_transfer::plugin_api::types::{Capability, CompressionOptions, PluginMetadata};
+use smart_transfer::core::plugin_manager::{PluginLoadFailure, PluginManager, PluginReloader, PluginSettingsView};
+use smart_transfer::core::plugin_settings::PluginSettingsStore;
+use smart_transfer::plugin_api::settings::PluginSettings;
+use smart_transfer::plugin_api::{ErrorPayload, ExtractionLimits};
+use smart_transfer::plugin_api::platform::get_plugin_dir;
+use smart_transfer::plugins::host::{self as plugin_host, HOST_FLAG};
+use smart_transfer::plugins::PluginTrustConfig;
+use smart_transfer::core::watcher::{CompressFn, FolderWatcher, SendFn, WatchConfig, WatchRule};
+use smart_transfer::transfer::{HttpShareServer, ShareContent, ShareInfo, ShareOptions, TransferOptions};
+
+// Shares are only reachable from this computer unless `start_http_server` opens them to the network
+const HTTP_SHARE_ADDR: &str = "127.0.0.1:8787";
+const HTTP_SHARE_LAN_ADDR: &str = "0.0.0.0:8787";
 
 struct AppState {
-    plugin_manager: PluginManager,
+    plugin_manager: Arc<PluginManager>,
+    _plugin_reloader: Option<PluginReloader>,
+    watcher: Mutex<Option<FolderWatcher>>,
+    http_share: Mutex<Option<HttpShareServer>>,
+}
+
+// Watch rules run on their own thread, outside of any async runtime
+fn watcher_runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn start_watcher(plugin_manager: &Arc<PluginManager>, rules: Vec<WatchRule>) -> Result<FolderWatcher, String> {
+    let compress_manager = Arc::clone(plugin_manager);
+    let compress: CompressFn = Arc::new(move |plugin_name, inputs, output| {
+        let runtime = watcher_runtime()?;
+        let compress = compress_manager.compress_async(plugin_name, inputs.to_vec(), output.to_path_buf(), CompressionOptions::default());
+        runtime.block_on(compress).map_err(|e| format!("{:#}", e))
+    });
+    let send_manager = Arc::clone(plugin_manager);
+    let send: SendFn = Arc::new(move |file, target| {
+        let runtime = watcher_runtime()?;
+        let options = TransferOptions::default();
+        let send = send_manager.send_files(vec![file.to_path_buf()], target.to_string(), &options);
+        runtime.block_on(send).map_err(|e| format!("{:#}", e))
+    });
+    FolderWatcher::start(rules, compress, send).map_err(|e| e.to_string())
 }
 
+// Async so a long job does not hold up other commands; sync plugins run on the blocking pool
 #[tauri::command]
-fn compress_files(
+async fn compress_files(
     plugin_name: String,
     input_files: Vec<String>,
     output_file: String,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<(), ErrorPayload> {
     let input_paths: Vec<PathBuf> = input_files.into_iter().map(PathBuf::from).collect();
     let output_path = PathBuf::from(output_file);
-
-    let plugin = state.plugin_manager
-        .get_plugin(&plugin_name)
-        .ok_or_else(|| format!("Plugin '{}' not found", plugin_name))?;
-
-    if let Some(compression_plugin) = plugin.as_any().downcast_ref::<Box<dyn CompressionPlugin>>() {
-        compression_plugin
-            .compress(
-                &input_paths,
-                &output_path,
-                &CompressionOptions {
-                    mode: CompressionMode::Normal,
-                    password: None,
-                    split_size: None,
-                    extra_args: Default::default(),
-                },
-            )
-            .map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err(format!("Plugin '{}' is not a compression plugin", plugin_name))
-    }
+    state.plugin_manager
+        .compress_async(&plugin_name, input_paths, output_path, CompressionOptions::default())
+        .await
+        .map_err(ErrorPayload::from)
 }
 
 #[tauri::command]
-fn decompress_file(
+async fn decompress_file(
     plugin_name: String,
     input_file: String,
     output_dir: String,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<(), ErrorPayload> {
     let input_path = PathBuf::from(input_file);
     let output_path = PathBuf::from(output_dir);
 
-    let plugin = state.plugin_manager
-        .get_plugin(&plugin_name)
-        .ok_or_else(|| format!("Plugin '{}' not found", plugin_name))?;
-
-    if let Some(compression_plugin) = plugin.as_any().downcast_ref::<Box<dyn CompressionPlugin>>() {
-        compression_plugin
-            .decompress(&input_path, &output_path, true)
-            .map_err(|e| e.to_string())?;
-        Ok(())
-    } else {
-        Err(format!("Plugin '{}' is not a compression plugin", plugin_name))
-    }
+    state.plugin_manager
+        .decompress_async(&plugin_name, input_path, output_path, true)
+        .await
+        .map_err(ErrorPayload::from)
+}
+
+// Adds `add_files` to an existing archive, replacing entries of the same name, and deletes
+// `remove_entries`, given as paths inside the archive
+#[tauri::command]
+async fn update_archive(
+    plugin_name: String,
+    archive_file: String,
+    add_files: Vec<String>,
+    remove_entries: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), ErrorPayload> {
+    let add_paths: Vec<PathBuf> = add_files.into_iter().map(PathBuf::from).collect();
+    state.plugin_manager
+        .update_archive_async(&plugin_name, PathBuf::from(archive_file), add_paths, remove_entries, CompressionOptions::default())
+        .await
+        .map_err(ErrorPayload::from)
+}
+
+// Rewrites `input_file`, read by `source_plugin`, as an archive of `target_plugin`'s format
+#[tauri::command]
+async fn convert_archive(
+    source_plugin: String,
+    input_file: String,
+    target_plugin: String,
+    output_file: String,
+    state: State<'_, AppState>,
+) -> Result<(), ErrorPayload> {
+    state.plugin_manager
+        .convert_archive_async(
+            &source_plugin,
+            PathBuf::from(input_file),
+            &target_plugin,
+            PathBuf::from(output_file),
+            CompressionOptions::default(),
+        )
+        .await
+        .map_err(ErrorPayload::from)
 }
 
 #[tauri::command]
@@ -76,20 +129,214 @@ fn list_plugins(state: State<'_, AppState>) -> Vec<PluginMetadata> {
     state.plugin_manager.list_plugins()
 }
 
+// `capabilities` are `kind:value` strings, e.g. `["extension:tar.xz", "feature:encryption"]`
+#[tauri::command]
+fn find_plugins(capabilities: Vec<Capability>, state: State<'_, AppState>) -> Vec<PluginMetadata> {
+    state
+        .plugin_manager
+        .find_plugins(&capabilities)
+        .iter()
+        .map(|plugin| plugin.metadata().clone())
+        .collect()
+}
+
+#[tauri::command]
+fn get_plugin_load_failures(state: State<'_, AppState>) -> Vec<PluginLoadFailure> {
+    state.plugin_manager.load_failures()
+}
+
+#[tauri::command]
+fn unload_plugin(name: String, state: State<'_, AppState>) -> Result<(), ErrorPayload> {
+    state.plugin_manager.unload_plugin(&name).map_err(ErrorPayload::from)
+}
+
+#[tauri::command]
+fn reload_plugin(name: String, state: State<'_, AppState>) -> Result<(), ErrorPayload> {
+    state.plugin_manager.reload_plugin(&name).map_err(ErrorPayload::from)
+}
+
+// Brings back a plugin that was disabled after it panicked
+#[tauri::command]
+fn reinitialize_plugin(name: String, state: State<'_, AppState>) -> Result<(), ErrorPayload> {
+    state.plugin_manager.reinitialize_plugin(&name).map_err(ErrorPayload::from)
+}
+
+#[tauri::command]
+fn get_plugin_settings(name: String, state: State<'_, AppState>) -> Result<PluginSettingsView, ErrorPayload> {
+    state.plugin_manager.plugin_settings(&name).map_err(ErrorPayload::from)
+}
+
+#[tauri::command]
+fn set_plugin_settings(
+    name: String,
+    values: PluginSettings,
+    state: State<'_, AppState>,
+) -> Result<PluginSettingsView, ErrorPayload> {
+    state
+        .plugin_manager
+        .set_plugin_settings(&name, values)
+        .map_err(ErrorPayload::from)
+}
+
+#[tauri::command]
+fn get_extraction_limits(state: State<'_, AppState>) -> ExtractionLimits {
+    state.plugin_manager.extraction_limits()
+}
+
+#[tauri::command]
+fn set_extraction_limits(limits: ExtractionLimits, state: State<'_, AppState>) -> Result<(), ErrorPayload> {
+    state.plugin_manager.set_extraction_limits(limits).map_err(ErrorPayload::from)
+}
+
+#[tauri::command]
+fn get_watch_rules() -> Result<Vec<WatchRule>, String> {
+    WatchConfig::load().map(|config| config.rules).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_watch_rules(rules: Vec<WatchRule>, state: State<'_, AppState>) -> Result<(), String> {
+    let mut watcher = state.watcher.lock().map_err(|e| e.to_string())?;
+    // Rules that fail to start are neither saved nor replace the running watcher
+    let started = start_watcher(&state.plugin_manager, rules.clone())?;
+    WatchConfig { rules }.save().map_err(|e| e.to_string())?;
+    *watcher = Some(started);
+    Ok(())
+}
+
+// Address other devices on the local network can reach us at; no packets are sent
+fn lan_ip() -> Option<std::net::IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("8.8.8.8:80").ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+#[tauri::command]
+fn create_http_share(
+    content: ShareContent,
+    options: ShareOptions,
+    state: State<'_, AppState>,
+) -> Result<(String, ShareInfo), String> {
+    let mut server = state.http_share.lock().map_err(|e| e.to_string())?;
+    if server.is_none() {
+        *server = Some(HttpShareServer::start(HTTP_SHARE_ADDR).map_err(|e| e.to_string())?);
+    }
+    let server = server.as_ref().expect("server started above");
+
+    let share = server.add_share(content, options).map_err(|e| e.to_string())?;
+    let addr = server.local_addr();
+    let port = addr.map(|addr| addr.port()).unwrap_or(8787);
+    let host = match addr {
+        Some(addr) if addr.ip().is_unspecified() => lan_ip().map(|ip| ip.to_string()),
+        Some(addr) => Some(addr.ip().to_string()),
+        None => None,
+    };
+    let host = host.unwrap_or_else(|| String::from("localhost"));
+    Ok((format!("http://{}:{}{}", host, port, share.path), share))
+}
+
+// Replaces a running server along with its shares; `lan_access` lets other devices on the
+// network reach it
+#[tauri::command]
+fn start_http_server(lan_access: bool, state: State<'_, AppState>) -> Result<(), String> {
+    let addr = if lan_access { HTTP_SHARE_LAN_ADDR } else { HTTP_SHARE_ADDR };
+    let mut server = state.http_share.lock().map_err(|e| e.to_string())?;
+    // The port has to be free before it is bound again
+    *server = None;
+    *server = Some(HttpShareServer::start(addr).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+#[tauri::command]
+fn revoke_http_share(token: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let server = state.http_share.lock().map_err(|e| e.to_string())?;
+    Ok(server.as_ref().map(|server| server.revoke(&token)).unwrap_or(false))
+}
+
+#[tauri::command]
+fn stop_http_server(state: State<'_, AppState>) -> Result<(), String> {
+    *state.http_share.lock().map_err(|e| e.to_string())? = None;
+    Ok(())
+}
+
 fn main() {
+    // Started by `HostedPlugin` to run an isolated plugin, see `plugins::host`
+    if std::env::args().nth(1).as_deref() == Some(HOST_FLAG) {
+        if let Err(e) = plugin_host::serve_stdio() {
+            eprintln!("Plugin host failed: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut plugin_manager = PluginManager::new();
-    
+    let settings = PluginSettingsStore::load().unwrap_or_else(|e| {
+        eprintln!("Error loading plugin settings: {}", e);
+        PluginSettingsStore::default()
+    });
+    plugin_manager.set_settings(settings, PluginSettingsStore::default_path());
+
     if let Err(e) = plugin_manager.register_default_plugins() {
         eprintln!("Error loading plugins: {}", e);
         return;
     }
+    let trust = PluginTrustConfig::load().unwrap_or_else(|e| {
+        eprintln!("Error loading trusted plugin keys: {}", e);
+        PluginTrustConfig::default()
+    });
+    plugin_manager.set_trust(trust);
+    // Failures are kept on the manager and shown by the UI through `get_plugin_load_failures`
+    plugin_manager.load_external_plugins(get_plugin_dir());
+
+    let plugin_manager = Arc::new(plugin_manager);
+    let plugin_reloader = match plugin_manager.watch_plugin_dir(get_plugin_dir()) {
+        Ok(reloader) => Some(reloader),
+        Err(e) => {
+            eprintln!("Error watching plugin directory: {}", e);
+            None
+        }
+    };
+    let watcher = match WatchConfig::load() {
+        Ok(config) => match start_watcher(&plugin_manager, config.rules) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!("Error starting folder watcher: {}", e);
+                None
+            }
+        },
+        Err(e) => {
+            eprintln!("Error loading watch rules: {}", e);
+            None
+        }
+    };
 
     tauri::Builder::default()
-        .manage(AppState { plugin_manager })
+        .manage(AppState {
+            plugin_manager,
+            _plugin_reloader: plugin_reloader,
+            watcher: Mutex::new(watcher),
+            http_share: Mutex::new(None),
+        })
         .invoke_handler(tauri::generate_handler![
             compress_files,
             decompress_file,
-            list_plugins
+            update_archive,
+            convert_archive,
+            list_plugins,
+            find_plugins,
+            get_plugin_load_failures,
+            unload_plugin,
+            reload_plugin,
+            reinitialize_plugin,
+            get_plugin_settings,
+            set_plugin_settings,
+            get_extraction_limits,
+            set_extraction_limits,
+            get_watch_rules,
+            set_watch_rules,
+            create_http_share,
+            revoke_http_share,
+            start_http_server,
+            stop_http_server
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
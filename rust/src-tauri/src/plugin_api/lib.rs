@@ -1,5 +0,0 @@
-pub mod compression;
-pub mod types;
-
-pub use compression::CompressionPlugin;
-pub use types::*;
@@ -0,0 +1,382 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use crate::plugin_api::base::PluginError;
+use crate::plugin_api::compression::{CompressionPlugin, StreamEntry};
+use crate::plugin_api::extract::{extract_archive, ExtractionLimits, Staging};
+use crate::plugin_api::types::CompressionOptions;
+use crate::plugins::registry::PluginRef;
+
+// Stream data is handed between the plugin and the async side in chunks of up to this size,
+// with a few of them in flight
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_IN_FLIGHT: usize = 4;
+
+// Runs compression jobs for async callers. `AsyncCompressionPlugin`s are awaited directly,
+// while sync plugins run on tokio's blocking pool so a long job never stalls the runtime.
+// Either way the call goes through `LoadedPlugin`, so panics still disable the plugin.
+pub struct AsyncCompression {
+    plugin: PluginRef,
+    limits: ExtractionLimits,
+}
+
+impl AsyncCompression {
+    // `None` if the plugin does not compress at all
+    pub fn new(plugin: PluginRef) -> Option<Self> {
+        let compresses = plugin
+            .call(|plugin| Ok(plugin.as_async_compression().is_some() || plugin.as_compression().is_some()))
+            .unwrap_or(false);
+        compresses.then_some(Self {
+            plugin,
+            limits: ExtractionLimits::default(),
+        })
+    }
+
+    // Bounds what `decompress` and `decompress_stream` may write
+    pub fn with_limits(mut self, limits: ExtractionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn plugin(&self) -> &PluginRef {
+        &self.plugin
+    }
+
+    fn is_async(&self) -> Result<bool, PluginError> {
+        self.plugin.call(|plugin| Ok(plugin.as_async_compression().is_some()))
+    }
+
+    pub async fn compress(
+        &self,
+        input_files: Vec<PathBuf>,
+        output_file: PathBuf,
+        options: CompressionOptions,
+    ) -> Result<(), PluginError> {
+        if self.is_async()? {
+            return self
+                .plugin
+                .call_async(|plugin| async move {
+                    let plugin = plugin.as_async_compression().ok_or(PluginError::NotImplemented)?;
+                    plugin.compress(&input_files, &output_file, &options).await
+                })
+                .await;
+        }
+        self.blocking(move |plugin| plugin.compress(&input_files, &output_file, &options)).await
+    }
+
+    // Async plugins extract into a `Staging` directory, like sync ones without `read_entries`
+    pub async fn decompress(&self, archive_file: PathBuf, output_dir: PathBuf, overwrite: bool) -> Result<(), PluginError> {
+        let limits = self.limits;
+        if self.is_async()? {
+            let staging = Staging::new(&output_dir)?;
+            let (archive, staged) = (archive_file.clone(), staging.path());
+            self.plugin
+                .call_async(|plugin| async move {
+                    let plugin = plugin.as_async_compression().ok_or(PluginError::NotImplemented)?;
+                    plugin.decompress(&archive, &staged, true, limits).await
+                })
+                .await?;
+            let task = tokio::task::spawn_blocking(move || staging.finish(&archive_file, overwrite, limits));
+            return task.await.unwrap_or(Err(PluginError::Cancelled));
+        }
+        self.blocking(move |plugin| extract_archive(plugin, &archive_file, &output_dir, overwrite, limits))
+            .await
+    }
+
+    // Stream variants for async I/O such as a connection. The plugin's `compress_stream` and
+    // `decompress_stream` run on the blocking pool and exchange the data with this task.
+    pub async fn compress_stream<W>(
+        &self,
+        entries: Vec<StreamEntry<'static>>,
+        output: &mut W,
+        options: CompressionOptions,
+    ) -> Result<(), PluginError>
+    where
+        W: AsyncWrite + Unpin + Send + ?Sized,
+    {
+        let (sender, mut receiver) = mpsc::channel::<Vec<u8>>(CHUNKS_IN_FLIGHT);
+        let job = self.blocking(move |plugin| {
+            let mut entries = entries.into_iter().map(Ok);
+            plugin.compress_stream(&mut entries, &mut ChannelWriter(sender), &options)
+        });
+        // Dropping the receiver when a write fails makes the plugin's next write fail too
+        let pump = async move {
+            while let Some(chunk) = receiver.recv().await {
+                output.write_all(&chunk).await?;
+            }
+            output.flush().await
+        };
+        let (result, pumped) = tokio::join!(job, pump);
+        pumped?;
+        result
+    }
+
+    pub async fn decompress_stream<R>(&self, input: &mut R, output_dir: PathBuf, overwrite: bool) -> Result<(), PluginError>
+    where
+        R: AsyncRead + Unpin + Send + ?Sized,
+    {
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>(CHUNKS_IN_FLIGHT);
+        let limits = self.limits;
+        let job = self.blocking(move |plugin| {
+            let mut reader = ChannelReader { receiver, chunk: Vec::new(), position: 0 };
+            plugin.decompress_stream(&mut reader, &output_dir, overwrite, limits)
+        });
+        // Ends with the input, or once the plugin stops reading
+        let pump = async move {
+            loop {
+                let mut chunk = vec![0; CHUNK_SIZE];
+                let read = input.read(&mut chunk).await?;
+                if read == 0 {
+                    return Ok::<(), io::Error>(());
+                }
+                chunk.truncate(read);
+                if sender.send(chunk).await.is_err() {
+                    return Ok(());
+                }
+            }
+        };
+        tokio::pin!(job);
+        tokio::select! {
+            // Archives may end before the input does, e.g. on a connection kept open
+            result = &mut job => result,
+            pumped = pump => {
+                pumped?;
+                job.await
+            }
+        }
+    }
+
+    async fn blocking<F>(&self, job: F) -> Result<(), PluginError>
+    where
+        F: FnOnce(&dyn CompressionPlugin) -> Result<(), PluginError> + Send + 'static,
+    {
+        let plugin = Arc::clone(&self.plugin);
+        let task = tokio::task::spawn_blocking(move || {
+            plugin.call(|plugin| plugin.as_compression().map_or(Err(PluginError::NotImplemented), job))
+        });
+        // `call` catches panics, so the task only fails to finish when the runtime shuts down
+        task.await.unwrap_or(Err(PluginError::Cancelled))
+    }
+}
+
+// The plugin's side of `compress_stream`
+struct ChannelWriter(mpsc::Sender<Vec<u8>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(buf.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "The output stream was closed"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The plugin's side of `decompress_stream`
+struct ChannelReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.chunk.len() - self.position);
+        buf[..read].copy_from_slice(&self.chunk[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::any::Any;
+    use std::path::Path;
+    use std::time::Duration;
+    use async_trait::async_trait;
+    use crate::plugin_api::base::{Plugin, PluginConfig};
+    use crate::plugin_api::compression::AsyncCompressionPlugin;
+    use crate::plugin_api::settings::PluginSettings;
+    use crate::plugin_api::types::{PluginMetadata, PluginType};
+    use crate::plugin_api::error::ErrorCode;
+    use crate::plugins::registry::PluginRegistry;
+    use crate::plugins::sevenz::SevenZipPlugin;
+    use crate::plugins::tcp::TcpTransferPlugin;
+    use crate::plugins::zip::ZipPlugin;
+
+    // Writes the input names into the output after a pause, without blocking the runtime
+    struct ListingPlugin {
+        config: PluginConfig,
+        metadata: PluginMetadata,
+    }
+
+    impl ListingPlugin {
+        fn new() -> Self {
+            let mut metadata = ZipPlugin::new().metadata();
+            metadata.name = String::from("Listing");
+            metadata.extensions = vec![String::from("list")];
+            Self {
+                config: PluginConfig {
+                    name: metadata.name.clone(),
+                    description: metadata.description.clone(),
+                    version: metadata.version.clone(),
+                    plugin_type: PluginType::Compression,
+                },
+                metadata,
+            }
+        }
+    }
+
+    impl Plugin for ListingPlugin {
+        fn get_config(&self) -> &PluginConfig {
+            &self.config
+        }
+
+        fn metadata(&self) -> PluginMetadata {
+            self.metadata.clone()
+        }
+
+        fn initialize(&mut self, _settings: &PluginSettings) -> Result<(), PluginError> {
+            Ok(())
+        }
+
+        fn cleanup(&mut self) -> Result<(), PluginError> {
+            Ok(())
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn as_async_compression(&self) -> Option<&dyn AsyncCompressionPlugin> {
+            Some(self)
+        }
+    }
+
+    #[async_trait]
+    impl AsyncCompressionPlugin for ListingPlugin {
+        async fn compress(
+            &self,
+            input_files: &[PathBuf],
+            output_file: &Path,
+            _options: &CompressionOptions,
+        ) -> Result<(), PluginError> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let names: Vec<String> = input_files.iter().map(|file| file.display().to_string()).collect();
+            tokio::fs::write(output_file, names.join("\n")).await?;
+            Ok(())
+        }
+
+        // Creates an empty file for every name listed, without checking them
+        async fn decompress(
+            &self,
+            archive_file: &Path,
+            output_dir: &Path,
+            _overwrite: bool,
+            _limits: ExtractionLimits,
+        ) -> Result<(), PluginError> {
+            for name in tokio::fs::read_to_string(archive_file).await?.lines() {
+                let path = output_dir.join(name);
+                tokio::fs::create_dir_all(path.parent().unwrap_or(output_dir)).await?;
+                tokio::fs::write(path, "").await?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_and_async_plugins_run_concurrently() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("data.txt");
+        std::fs::write(&input, "compressible ".repeat(10_000)).unwrap();
+
+        let registry = PluginRegistry::new();
+        let zip = registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        let listing = registry.register("Listing".to_string(), Box::new(ListingPlugin::new())).unwrap();
+        let zip = AsyncCompression::new(zip).unwrap();
+        let listing = AsyncCompression::new(listing).unwrap();
+
+        // Both jobs share this single-threaded runtime; the zip job runs on the blocking pool
+        let (zipped, listed) = tokio::join!(
+            zip.compress(vec![input.clone()], dir.path().join("data.zip"), CompressionOptions::default()),
+            listing.compress(vec![input.clone()], dir.path().join("data.list"), CompressionOptions::default()),
+        );
+        zipped.unwrap();
+        listed.unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("data.list")).unwrap(), input.display().to_string());
+
+        let output = dir.path().join("out");
+        zip.decompress(dir.path().join("data.zip"), output.clone(), true).await.unwrap();
+        assert_eq!(std::fs::read(output.join("data.txt")).unwrap(), std::fs::read(&input).unwrap());
+
+        // Async plugins' output goes through the same checks
+        let list = dir.path().join("names.list");
+        std::fs::write(&list, "listed/a.txt\n../escaped.txt").unwrap();
+        let output = dir.path().join("listed");
+        listing.decompress(list.clone(), output.clone(), false).await.unwrap();
+        assert!(output.join("listed").join("a.txt").exists());
+        assert!(!dir.path().join("escaped.txt").exists());
+        assert_eq!(std::fs::read_dir(&output).unwrap().count(), 1);
+        let err = listing.decompress(list, output, false).await.unwrap_err();
+        assert_eq!(err.code(), ErrorCode::AlreadyExists);
+
+        let tcp = registry.register("TCP".to_string(), Box::new(TcpTransferPlugin::new())).unwrap();
+        assert!(AsyncCompression::new(tcp).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_streams_are_piped_through_sync_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = PluginRegistry::new();
+        let zip = registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        let sevenz = registry.register("7-Zip Plugin".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+        let zip = AsyncCompression::new(zip).unwrap();
+        let sevenz = AsyncCompression::new(sevenz).unwrap();
+        let entries = || {
+            vec![
+                StreamEntry::new("notes.txt", io::Cursor::new(b"notes".to_vec())),
+                StreamEntry::new("nested/data.bin", io::Cursor::new(vec![7u8; 200_000])),
+            ]
+        };
+
+        // Both ends at once, as over a connection
+        let (mut sending, mut receiving) = tokio::io::duplex(4096);
+        let output = dir.path().join("zip");
+        let (sent, received) = tokio::join!(
+            zip.compress_stream(entries(), &mut sending, CompressionOptions::default()),
+            zip.decompress_stream(&mut receiving, output.clone(), true),
+        );
+        sent.unwrap();
+        received.unwrap();
+        assert_eq!(std::fs::read_to_string(output.join("notes.txt")).unwrap(), "notes");
+        assert_eq!(std::fs::read(output.join("nested").join("data.bin")).unwrap(), vec![7u8; 200_000]);
+
+        let mut archive = Vec::new();
+        sevenz.compress_stream(entries(), &mut archive, CompressionOptions::default()).await.unwrap();
+        let output = dir.path().join("7z");
+        sevenz.decompress_stream(&mut archive.as_slice(), output.clone(), true).await.unwrap();
+        assert_eq!(std::fs::read_to_string(output.join("notes.txt")).unwrap(), "notes");
+
+        let err = zip.decompress_stream(&mut &b"not a zip archive"[..], output, true).await.unwrap_err();
+        assert_eq!(err.code(), ErrorCode::CorruptArchive);
+    }
+}
@@ -0,0 +1,578 @@
+// Native plugins running in a helper process, so a crash takes down the host instead of the app.
+//
+// The helper is this executable started with `HOST_FLAG`. The core writes one JSON
+// `HostRequest` per line to its stdin and reads one JSON `HostResponse` per line from its
+// stdout. The host keeps stdout to itself and points the plugin's stdout at stderr, so
+// nothing the plugin prints can end up in a response; other lines the host program itself
+// writes before serving are only logged.
+// The first request is always `Load`; the host then serves requests until stdin closes.
+
+use std::any::Any;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::plugin_api::abi::{CompressRequest, DecompressRequest};
+use crate::plugin_api::base::{Plugin, PluginConfig, PluginError};
+use crate::plugin_api::compression::CompressionPlugin;
+use crate::plugin_api::extract::ExtractionLimits;
+use crate::plugin_api::settings::{PluginSettings, SettingsSchema};
+use crate::plugin_api::types::{CompressionOptions, PluginMetadata, PluginType};
+use crate::plugins::loader::{DynamicPlugin, ShadowCopy};
+use crate::plugins::manifest::PluginManifest;
+
+// Command line flag that turns the app binary into a plugin host, see `serve_stdio`
+pub const HOST_FLAG: &str = "--plugin-host";
+
+// How long a host may take to answer before it is killed. Archive work gets far longer
+// than loading or setting up the plugin.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(30);
+const WORK_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+// How long a host gets to exit on its own once its stdin is closed
+const STOP_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum HostRequest {
+    Load { library: PathBuf },
+    Initialize { settings: PluginSettings },
+    Compress(CompressRequest),
+    Decompress(DecompressRequest),
+    Cleanup,
+}
+
+impl HostRequest {
+    fn timeout(&self) -> Duration {
+        match self {
+            HostRequest::Compress(_) | HostRequest::Decompress(_) => WORK_TIMEOUT,
+            _ => SETUP_TIMEOUT,
+        }
+    }
+}
+
+pub type HostResponse = Result<Value, PluginError>;
+
+// What the host reports once the library is loaded
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HostDescription {
+    pub metadata: PluginMetadata,
+    pub settings_schema: SettingsSchema,
+    pub compression: bool,
+}
+
+// How to start a plugin host. The host is this executable, so it is the same for every
+// plugin and set once per process rather than per manager.
+#[derive(Debug, Clone)]
+pub struct PluginHostCommand {
+    program: PathBuf,
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+}
+
+impl PluginHostCommand {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            envs: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn current_exe() -> Result<Self> {
+        Ok(Self::new(std::env::current_exe()?).arg(HOST_FLAG))
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        command
+    }
+}
+
+static HOST_COMMAND: RwLock<Option<PluginHostCommand>> = RwLock::new(None);
+
+pub fn set_host_command(command: PluginHostCommand) {
+    *HOST_COMMAND.write().unwrap_or_else(|e| e.into_inner()) = Some(command);
+}
+
+fn host_command() -> Result<PluginHostCommand> {
+    match &*HOST_COMMAND.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(command) => Ok(command.clone()),
+        None => PluginHostCommand::current_exe(),
+    }
+}
+
+// Why a call got no response
+enum HostFailure {
+    // The host crashed or closed its pipes
+    Gone,
+    TimedOut(Duration),
+}
+
+struct HostProcess {
+    child: Child,
+    stdin: ChildStdin,
+    // Lines the host writes to stdout, read on their own thread so a call can give up waiting
+    stdout: Receiver<String>,
+}
+
+impl HostProcess {
+    fn spawn(command: &PluginHostCommand) -> Result<Self> {
+        let mut child = command
+            .command()
+            .spawn()
+            .with_context(|| format!("Failed to start plugin host {}", command.program.display()))?;
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("Plugin host has no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("Plugin host has no stdout"))?;
+        let (lines, stdout_lines) = mpsc::channel();
+        // Ends once the host closes stdout, or nobody is listening any more
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            stdout: stdout_lines,
+        })
+    }
+
+    fn call(&mut self, name: &str, request: &HostRequest) -> Result<HostResponse, HostFailure> {
+        self.call_within(name, request, request.timeout())
+    }
+
+    fn call_within(&mut self, name: &str, request: &HostRequest, timeout: Duration) -> Result<HostResponse, HostFailure> {
+        let mut line = serde_json::to_string(request).map_err(|_| HostFailure::Gone)?;
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).map_err(|_| HostFailure::Gone)?;
+        self.stdin.flush().map_err(|_| HostFailure::Gone)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = match self.stdout.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Err(HostFailure::TimedOut(timeout)),
+                Err(RecvTimeoutError::Disconnected) => return Err(HostFailure::Gone),
+            };
+            match serde_json::from_str(&line) {
+                Ok(response) => return Ok(response),
+                Err(_) => log::info!("[{}] {}", name, line.trim_end()),
+            }
+        }
+    }
+
+    fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    // Closing stdin asks the host to exit; it is killed if it has not within `STOP_GRACE`
+    fn stop(mut self) -> String {
+        drop(self.stdin);
+        let deadline = Instant::now() + STOP_GRACE;
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Self::reap(self.child)
+    }
+
+    // For a host stuck in a call, which would not notice its stdin closing
+    fn kill(self) -> String {
+        Self::reap(self.child)
+    }
+
+    fn reap(mut child: Child) -> String {
+        let _ = child.kill();
+        match child.wait() {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+// A native plugin loaded into its own host process. Calls are proxied one at a time; a host
+// that died between calls is restarted and re-initialized before the next one, while a call
+// during which it dies fails with an error. A host that does not answer in time is killed
+// and replaced, so one stuck call does not block the plugin for good.
+pub struct HostedPlugin {
+    config: PluginConfig,
+    metadata: PluginMetadata,
+    settings_schema: SettingsSchema,
+    compression: bool,
+    command: PluginHostCommand,
+    path: PathBuf,
+    // Private copy the host loads, so the original can be replaced while it runs
+    library: ShadowCopy,
+    settings: Mutex<Option<PluginSettings>>,
+    process: Mutex<Option<HostProcess>>,
+}
+
+impl HostedPlugin {
+    // `verify` sees the exact bytes the host will load before it is started
+    pub fn load_with_manifest(
+        path: PathBuf,
+        manifest: PluginManifest,
+        verify: &dyn Fn(&[u8]) -> Result<()>,
+    ) -> Result<Self> {
+        let library = ShadowCopy::create(&path, verify)?;
+        Self::start(path, library, manifest, host_command()?)
+    }
+
+    fn start(path: PathBuf, library: ShadowCopy, manifest: PluginManifest, command: PluginHostCommand) -> Result<Self> {
+        let (process, description) = launch(&command, library.path(), &manifest.name)?;
+        let HostDescription { mut metadata, settings_schema, compression } = description;
+        if matches!(manifest.plugin_type(), PluginType::Compression) && !compression {
+            process.stop();
+            bail!(
+                "Plugin '{}' is declared as a compression plugin but {} does not export compression",
+                manifest.name,
+                path.display()
+            );
+        }
+        manifest.apply_to(&mut metadata);
+        Ok(Self {
+            config: PluginConfig {
+                name: metadata.name.clone(),
+                description: metadata.description.clone(),
+                version: metadata.version.clone(),
+                plugin_type: metadata.plugin_type.clone(),
+            },
+            metadata,
+            settings_schema,
+            compression,
+            command,
+            path,
+            library,
+            settings: Mutex::new(None),
+            process: Mutex::new(Some(process)),
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    // Process id of the running host, if there is one
+    pub fn host_id(&self) -> Option<u32> {
+        self.process().as_ref().map(|process| process.child.id())
+    }
+
+    fn process(&self) -> MutexGuard<'_, Option<HostProcess>> {
+        self.process.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn call(&self, request: &HostRequest) -> Result<Value, PluginError> {
+        let name = &self.metadata.name;
+        let mut process = self.process();
+        if let Some(running) = process.as_mut() {
+            if !running.is_running() {
+                let status = process.take().map(HostProcess::stop).unwrap_or_default();
+                log::warn!("Plugin host for '{}' exited ({}), restarting it", name, status);
+            }
+        }
+        if process.is_none() {
+            *process = Some(self.restart()?);
+        }
+
+        let running = process.as_mut().expect("host process was just started");
+        match running.call(name, request) {
+            Ok(response) => response,
+            Err(HostFailure::Gone) => {
+                let status = process.take().map(HostProcess::stop).unwrap_or_default();
+                Err(PluginError::other(format!("Plugin host for '{}' crashed ({})", name, status)))
+            }
+            Err(HostFailure::TimedOut(timeout)) => {
+                if let Some(stuck) = process.take() {
+                    stuck.kill();
+                }
+                match self.restart() {
+                    Ok(restarted) => *process = Some(restarted),
+                    // Tried again on the next call
+                    Err(e) => log::warn!("Failed to restart the plugin host for '{}': {}", name, e),
+                }
+                Err(PluginError::other(format!(
+                    "Plugin host for '{}' did not answer within {}s and was restarted",
+                    name,
+                    timeout.as_secs()
+                )))
+            }
+        }
+    }
+
+    fn restart(&self) -> Result<HostProcess, PluginError> {
+        self.library.check_unchanged()?;
+        let (mut process, _) = launch(&self.command, self.library.path(), &self.metadata.name)?;
+        let settings = self.settings.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(settings) = settings {
+            let request = HostRequest::Initialize { settings };
+            match process.call(&self.metadata.name, &request) {
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    process.stop();
+                    return Err(e);
+                }
+                Err(HostFailure::Gone) => {
+                    let status = process.stop();
+                    return Err(PluginError::other(format!(
+                        "Plugin host for '{}' crashed on initialize ({})",
+                        self.metadata.name, status
+                    )));
+                }
+                Err(HostFailure::TimedOut(timeout)) => {
+                    process.kill();
+                    return Err(PluginError::other(format!(
+                        "Plugin host for '{}' did not finish initializing within {}s",
+                        self.metadata.name,
+                        timeout.as_secs()
+                    )));
+                }
+            }
+        }
+        Ok(process)
+    }
+}
+
+fn launch(command: &PluginHostCommand, library: &Path, name: &str) -> Result<(HostProcess, HostDescription)> {
+    let mut process = HostProcess::spawn(command)?;
+    let request = HostRequest::Load { library: library.to_path_buf() };
+    let described = match process.call(name, &request) {
+        Ok(Ok(value)) => serde_json::from_value(value).map_err(anyhow::Error::from),
+        Ok(Err(e)) => Err(anyhow!("Plugin host failed to load {}: {}", library.display(), e)),
+        Err(HostFailure::Gone) => Err(anyhow!("Plugin host exited while loading {}", library.display())),
+        Err(HostFailure::TimedOut(timeout)) => {
+            process.kill();
+            bail!("Plugin host did not load {} within {}s", library.display(), timeout.as_secs());
+        }
+    };
+    match described {
+        Ok(description) => Ok((process, description)),
+        Err(e) => {
+            process.stop();
+            Err(e)
+        }
+    }
+}
+
+impl Drop for HostedPlugin {
+    fn drop(&mut self) {
+        if let Some(process) = self.process().take() {
+            process.stop();
+        }
+    }
+}
+
+impl Plugin for HostedPlugin {
+    fn get_config(&self) -> &PluginConfig {
+        &self.config
+    }
+
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn settings_schema(&self) -> SettingsSchema {
+        self.settings_schema.clone()
+    }
+
+    // Kept so a restarted host is initialized the same way
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError> {
+        *self.settings.lock().unwrap_or_else(|e| e.into_inner()) = Some(settings.clone());
+        self.call(&HostRequest::Initialize { settings: settings.clone() }).map(|_| ())
+    }
+
+    fn cleanup(&mut self) -> Result<(), PluginError> {
+        // Nothing to clean up in a host that is no longer running
+        let running = self.process().as_mut().is_some_and(HostProcess::is_running);
+        if !running {
+            return Ok(());
+        }
+        self.call(&HostRequest::Cleanup).map(|_| ())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        if self.compression {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl CompressionPlugin for HostedPlugin {
+    fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        self.call(&HostRequest::Compress(CompressRequest {
+            input_files: input_files.to_vec(),
+            output_file: output_file.to_path_buf(),
+            options: options.clone(),
+        }))
+        .map(|_| ())
+    }
+
+    fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        self.call(&HostRequest::Decompress(DecompressRequest {
+            archive_file: archive_file.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            overwrite,
+            limits,
+        }))
+        .map(|_| ())
+    }
+}
+
+// The host side: serves requests from stdin until it is closed
+pub fn serve_stdio() -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut out = protocol_output().context("Failed to set up the plugin host output")?;
+    let mut plugin: Option<DynamicPlugin> = None;
+
+    for line in stdin.lock().lines() {
+        let response = match serde_json::from_str::<HostRequest>(&line?) {
+            Ok(request) => handle(&mut plugin, request),
+            Err(e) => Err(PluginError::InvalidInput(e.to_string())),
+        };
+        let mut line = serde_json::to_string(&response)?;
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// Takes a duplicate of stdout for the responses, then points stdout at stderr
+#[cfg(unix)]
+fn protocol_output() -> Result<File> {
+    use std::os::fd::AsFd;
+    std::io::stdout().flush()?;
+    let protocol = std::io::stdout().as_fd().try_clone_to_owned()?;
+    // SAFETY: both are the process's own standard descriptors, which stay open
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(File::from(protocol))
+}
+
+#[cfg(windows)]
+fn protocol_output() -> Result<File> {
+    use std::os::windows::io::{AsHandle, AsRawHandle};
+    use windows_sys::Win32::System::Console::{SetStdHandle, STD_OUTPUT_HANDLE};
+    std::io::stdout().flush()?;
+    let protocol = std::io::stdout().as_handle().try_clone_to_owned()?;
+    // SAFETY: the stderr handle stays open for the lifetime of the process
+    if unsafe { SetStdHandle(STD_OUTPUT_HANDLE, std::io::stderr().as_raw_handle() as _) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(File::from(protocol))
+}
+
+fn handle(plugin: &mut Option<DynamicPlugin>, request: HostRequest) -> HostResponse {
+    match request {
+        HostRequest::Load { library } => return load(plugin, library),
+        HostRequest::Initialize { settings } => loaded(plugin)?.initialize(&settings)?,
+        HostRequest::Cleanup => loaded(plugin)?.cleanup()?,
+        HostRequest::Compress(request) => compression(plugin)?
+            .compress(&request.input_files, &request.output_file, &request.options)?,
+        HostRequest::Decompress(request) => compression(plugin)?
+            .decompress(&request.archive_file, &request.output_dir, request.overwrite, request.limits)?,
+    }
+    Ok(Value::Null)
+}
+
+fn load(plugin: &mut Option<DynamicPlugin>, library: PathBuf) -> HostResponse {
+    if plugin.is_some() {
+        return Err(PluginError::AlreadyExists(library.display().to_string()));
+    }
+    let loaded = DynamicPlugin::load(library)?;
+    let description = HostDescription {
+        metadata: loaded.metadata(),
+        settings_schema: loaded.settings_schema(),
+        compression: loaded.supports_compression(),
+    };
+    *plugin = Some(loaded);
+    serde_json::to_value(description).map_err(|e| PluginError::with_source("Failed to encode the plugin description", e))
+}
+
+fn loaded(plugin: &mut Option<DynamicPlugin>) -> Result<&mut DynamicPlugin, PluginError> {
+    plugin
+        .as_mut()
+        .ok_or_else(|| PluginError::InvalidInput("No plugin loaded".to_string()))
+}
+
+fn compression(plugin: &mut Option<DynamicPlugin>) -> Result<&dyn CompressionPlugin, PluginError> {
+    loaded(plugin)?.as_compression().ok_or(PluginError::NotImplemented)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> HostProcess {
+        HostProcess::spawn(&PluginHostCommand::new("sh").arg("-c").arg(script)).unwrap()
+    }
+
+    #[test]
+    fn test_call_gives_up_on_a_silent_host() {
+        // Reads requests but never answers; fd 3 keeps the stdout pipe open
+        let mut host = shell("exec cat 3>&1 > /dev/null");
+        let started = Instant::now();
+        let result = host.call_within("silent", &HostRequest::Cleanup, Duration::from_millis(200));
+        assert!(matches!(result, Err(HostFailure::TimedOut(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // It exits on its own once stdin closes, so it is not killed
+        let status = host.stop();
+        assert!(status.contains("exit status: 0"), "{}", status);
+    }
+
+    #[test]
+    fn test_stop_kills_a_host_that_does_not_exit() {
+        let host = shell("exec sleep 30 < /dev/null");
+        let started = Instant::now();
+        let status = host.stop();
+        assert!(started.elapsed() >= STOP_GRACE);
+        assert!(started.elapsed() < Duration::from_secs(20));
+        assert!(status.contains("signal"), "{}", status);
+    }
+}
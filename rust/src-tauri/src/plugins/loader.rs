@@ -1,51 +1,455 @@
-use std::path::PathBuf;
-use anyhow::Result;
+use std::any::Any;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
 use libloading::{Library, Symbol};
-use crate::plugin_api::base::Plugin;
-use crate::plugins::PluginInstance;
+use sha2::{Digest, Sha256};
+use crate::plugin_api::abi::{
+    self, AbiVersionFn, CompressRequest, CreateFn, DecompressRequest, FfiBuffer, FfiStr, PluginHandle,
+};
+use crate::plugin_api::base::{Plugin, PluginConfig, PluginError};
+use crate::plugin_api::compression::CompressionPlugin;
+use crate::plugin_api::extract::ExtractionLimits;
+use crate::plugin_api::settings::{PluginSettings, SettingsSchema};
+use crate::plugin_api::types::{CompressionOptions, PluginMetadata, PluginType};
+use crate::plugins::host::HostedPlugin;
+use crate::plugins::manifest::{
+    core_version, is_plugin_library, PluginIsolation, PluginManifest, PluginRuntime, MANIFEST_FILE,
+};
+use crate::plugins::signature::PluginTrustConfig;
+use crate::plugins::wasm::WasmPlugin;
+
+pub struct RejectedPlugin {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+// A plugin loaded from the plugin directory, whatever runtime it uses
+pub struct ExternalPlugin {
+    pub plugin: Box<dyn Plugin>,
+    pub library: PathBuf,
+    pub manifest_path: PathBuf,
+}
+
+#[derive(Default)]
+pub struct PluginDiscovery {
+    pub plugins: Vec<ExternalPlugin>,
+    pub rejected: Vec<RejectedPlugin>,
+}
 
 pub struct PluginLoader;
 
 impl PluginLoader {
-    pub fn load_plugin(path: PathBuf) -> Result<PluginInstance> {
-        unsafe {
-            let lib = Library::new(path)?;
-            let constructor: Symbol<fn() -> Box<dyn Plugin>> = lib.get(b"_plugin_create")?;
-            let plugin = constructor();
-            Ok(PluginInstance::new(plugin))
-        }
+    // Loads the plugin described by a `plugin.toml`, refusing it if the core version does not
+    // match or its signature is not accepted by `trust`
+    pub fn load_from_manifest(manifest_path: &Path, trust: &PluginTrustConfig) -> Result<ExternalPlugin> {
+        let (manifest, source) = PluginManifest::load_with_source(manifest_path)?;
+        manifest.check_compatibility(&core_version())?;
+        let plugin_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+        let library = manifest.library_path(plugin_dir)?;
+        let verify = |library: &[u8]| trust.verify(plugin_dir, source.as_bytes(), library);
+        let plugin: Box<dyn Plugin> = match (manifest.runtime, manifest.isolation) {
+            (PluginRuntime::Native, PluginIsolation::InProcess) => {
+                Box::new(DynamicPlugin::load_with_manifest(library.clone(), manifest, &verify)?)
+            }
+            (PluginRuntime::Native, PluginIsolation::Process) => {
+                Box::new(HostedPlugin::load_with_manifest(library.clone(), manifest, &verify)?)
+            }
+            (PluginRuntime::Wasm, PluginIsolation::InProcess) => {
+                Box::new(WasmPlugin::load_with_manifest(library.clone(), manifest, &verify)?)
+            }
+            (PluginRuntime::Wasm, PluginIsolation::Process) => {
+                bail!("WebAssembly plugins are already sandboxed, `isolation = \"process\"` only applies to native plugins")
+            }
+        };
+        Ok(ExternalPlugin {
+            plugin,
+            library,
+            manifest_path: manifest_path.to_path_buf(),
+        })
     }
 
-    pub fn discover_plugins(plugin_dir: PathBuf) -> Result<Vec<PluginInstance>> {
-        let mut plugins = Vec::new();
-        
+    // Each plugin lives in its own directory holding a `plugin.toml` and the library.
+    // Entries are visited in path order so the result does not depend on the file system.
+    pub fn discover(plugin_dir: PathBuf, trust: &PluginTrustConfig) -> Result<PluginDiscovery> {
+        let mut discovery = PluginDiscovery::default();
+
         if !plugin_dir.exists() {
-            return Ok(plugins);
+            return Ok(discovery);
         }
 
-        for entry in std::fs::read_dir(plugin_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.is_file() && Self::is_plugin_library(&path) {
-                if let Ok(plugin) = Self::load_plugin(path) {
-                    plugins.push(plugin);
-                }
+        let mut paths = std::fs::read_dir(plugin_dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.sort();
+
+        for path in paths {
+            let manifest_path = path.join(MANIFEST_FILE);
+
+            let result = if path.is_dir() && manifest_path.is_file() {
+                Self::load_from_manifest(&manifest_path, trust)
+            } else if path.is_file() && is_plugin_library(&path) {
+                Err(anyhow!("Plugin library has no {} next to it", MANIFEST_FILE))
+            } else {
+                continue;
+            };
+
+            match result {
+                Ok(plugin) => discovery.plugins.push(plugin),
+                Err(e) => discovery.rejected.push(RejectedPlugin {
+                    path,
+                    reason: format!("{:#}", e),
+                }),
             }
         }
 
-        Ok(plugins)
+        Ok(discovery)
+    }
+
+    pub fn discover_plugins(plugin_dir: PathBuf) -> Result<Vec<ExternalPlugin>> {
+        let discovery = Self::discover(plugin_dir, &PluginTrustConfig::load()?)?;
+        for rejected in &discovery.rejected {
+            log::warn!("Skipping plugin {}: {}", rejected.path.display(), rejected.reason);
+        }
+        Ok(discovery.plugins)
+    }
+}
+
+// A private copy of a plugin library, removed along with its directory when dropped. The
+// copy is written from the same bytes `verify` accepted, into a fresh directory only this
+// user can open, so nobody can swap it between the check and loading.
+pub(super) struct ShadowCopy {
+    _dir: tempfile::TempDir,
+    path: PathBuf,
+    // SHA-256 of the verified bytes, for loading the copy again later
+    digest: [u8; 32],
+}
+
+impl ShadowCopy {
+    pub(super) fn create(library: &Path, verify: &dyn Fn(&[u8]) -> Result<()>) -> Result<Self> {
+        let bytes = fs::read(library).with_context(|| format!("Failed to read {}", library.display()))?;
+        verify(&bytes)?;
+
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("smart_transfer_plugin");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o700));
+        }
+        let dir = builder.tempdir().context("Failed to create a directory for the plugin copy")?;
+        let stem = library.file_stem().unwrap_or_default().to_string_lossy();
+        let path = dir.path().join(format!("{}.{}", stem, std::env::consts::DLL_EXTENSION));
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        file.write_all(&bytes)?;
+        Ok(Self { _dir: dir, path, digest: Sha256::digest(&bytes).into() })
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Fails unless the copy still holds exactly the bytes that were verified
+    pub(super) fn check_unchanged(&self) -> Result<()> {
+        let bytes = fs::read(&self.path).with_context(|| format!("Failed to read {}", self.path.display()))?;
+        if Sha256::digest(&bytes).as_slice() != self.digest {
+            bail!("Plugin copy {} changed after it was verified", self.path.display());
+        }
+        Ok(())
+    }
+}
+
+// Owns the loaded library and deletes its shadow copy, if any, once it is unloaded
+struct PluginLibrary {
+    library: Option<Library>,
+    shadow_copy: Option<ShadowCopy>,
+}
+
+impl PluginLibrary {
+    fn get(&self) -> &Library {
+        self.library.as_ref().expect("library is loaded")
     }
+}
 
-    fn is_plugin_library(path: &PathBuf) -> bool {
-        if let Some(ext) = path.extension() {
-            #[cfg(target_os = "windows")]
-            return ext == "dll";
-            #[cfg(target_os = "linux")]
-            return ext == "so";
-            #[cfg(target_os = "macos")]
-            return ext == "dylib";
+impl Drop for PluginLibrary {
+    fn drop(&mut self) {
+        if let Some(library) = self.library.take() {
+            if let Err(e) = library.close() {
+                log::warn!("Failed to unload plugin library: {}", e);
+            }
         }
-        false
+        // Only once the library is closed
+        self.shadow_copy.take();
+    }
+}
+
+// A plugin living in a shared library, driven through the C ABI in `plugin_api::abi`
+pub struct DynamicPlugin {
+    handle: PluginHandle,
+    config: PluginConfig,
+    metadata: PluginMetadata,
+    settings_schema: SettingsSchema,
+    manifest: Option<PluginManifest>,
+    path: PathBuf,
+    // Declared last so the library is unloaded only after the instance is destroyed in `drop`
+    _library: PluginLibrary,
+}
+
+// The plugin contract requires implementations to be `Send + Sync`
+unsafe impl Send for DynamicPlugin {}
+unsafe impl Sync for DynamicPlugin {}
+
+impl DynamicPlugin {
+    // Skips the manifest and signature checks, so it is only for a library that was already
+    // verified: the plugin host loads the shadow copy its parent checked
+    pub(crate) fn load(path: PathBuf) -> Result<Self> {
+        Self::open(path, None)
+    }
+
+    // Loads a private copy of the library. The original file can then be rebuilt and loaded
+    // again while this instance is still alive; `dlopen` would hand back the old image otherwise.
+    // `verify` sees the copy's contents before any of its code runs.
+    fn load_shadow_copy(path: PathBuf, verify: &dyn Fn(&[u8]) -> Result<()>) -> Result<Self> {
+        let shadow = ShadowCopy::create(&path, verify)?;
+        Self::open(path, Some(shadow))
+    }
+
+    fn open(path: PathBuf, shadow_copy: Option<ShadowCopy>) -> Result<Self> {
+        let mut library = PluginLibrary { library: None, shadow_copy };
+        unsafe {
+            let file = library.shadow_copy.as_ref().map(ShadowCopy::path).unwrap_or(&path);
+            library.library = Some(Library::new(file)?);
+
+            let abi_version: Symbol<AbiVersionFn> = library.get().get(abi::ABI_VERSION_SYMBOL)?;
+            let version = abi_version();
+            if version != abi::PLUGIN_ABI_VERSION {
+                bail!(
+                    "Plugin {} uses ABI version {}, expected {}",
+                    path.display(),
+                    version,
+                    abi::PLUGIN_ABI_VERSION
+                );
+            }
+
+            let create: Symbol<CreateFn> = library.get().get(abi::CREATE_SYMBOL)?;
+            let handle = create();
+            if handle.instance.is_null() || handle.abi_version != abi::PLUGIN_ABI_VERSION {
+                bail!("Plugin {} returned an invalid handle", path.display());
+            }
+
+            let described = read_json::<PluginMetadata>(&handle, handle.vtable.metadata)
+                .map_err(|e| anyhow!("Plugin {} returned invalid metadata: {}", path.display(), e))
+                .and_then(|metadata| {
+                    read_json::<SettingsSchema>(&handle, handle.vtable.settings_schema)
+                        .map(|schema| (metadata, schema))
+                        .map_err(|e| anyhow!("Plugin {} returned an invalid settings schema: {}", path.display(), e))
+                });
+            let (metadata, settings_schema) = match described {
+                Ok(described) => described,
+                Err(e) => {
+                    (handle.vtable.destroy)(handle.instance);
+                    return Err(e);
+                }
+            };
+
+            Ok(Self {
+                config: PluginConfig {
+                    name: metadata.name.clone(),
+                    description: metadata.description.clone(),
+                    version: metadata.version.clone(),
+                    plugin_type: metadata.plugin_type.clone(),
+                },
+                metadata,
+                settings_schema,
+                manifest: None,
+                handle,
+                path,
+                _library: library,
+            })
+        }
+    }
+
+    // Plugins described by a manifest are always loaded from a shadow copy so they can be reloaded
+    pub fn load_with_manifest(
+        path: PathBuf,
+        manifest: PluginManifest,
+        verify: &dyn Fn(&[u8]) -> Result<()>,
+    ) -> Result<Self> {
+        let mut plugin = Self::load_shadow_copy(path, verify)?;
+        if matches!(manifest.plugin_type(), PluginType::Compression) && !plugin.supports_compression() {
+            bail!(
+                "Plugin '{}' is declared as a compression plugin but {} does not export compression",
+                manifest.name,
+                plugin.path.display()
+            );
+        }
+        manifest.apply_to(&mut plugin.metadata);
+        plugin.config.name = plugin.metadata.name.clone();
+        plugin.config.description = plugin.metadata.description.clone();
+        plugin.config.version = plugin.metadata.version.clone();
+        plugin.config.plugin_type = plugin.metadata.plugin_type.clone();
+        plugin.manifest = Some(manifest);
+        Ok(plugin)
+    }
+
+    pub fn manifest(&self) -> Option<&PluginManifest> {
+        self.manifest.as_ref()
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn supports_compression(&self) -> bool {
+        self.handle.capabilities & abi::CAP_COMPRESSION != 0
+    }
+
+    fn check_status(&self, status: i32, error: FfiBuffer) -> Result<(), PluginError> {
+        let message = unsafe { String::from_utf8_lossy(error.as_bytes()).into_owned() };
+        unsafe { (self.handle.vtable.free_buffer)(error) };
+        match status {
+            abi::ABI_OK => Ok(()),
+            abi::ABI_UNSUPPORTED => Err(PluginError::NotImplemented),
+            abi::ABI_PANICKED => Err(PluginError::Panicked {
+                plugin: self.metadata.name.clone(),
+                message,
+            }),
+            _ => Err(PluginError::decode(message)),
+        }
+    }
+
+    fn call_with_request<T: serde::Serialize>(
+        &self,
+        call: unsafe extern "C" fn(*mut std::ffi::c_void, FfiStr, *mut FfiBuffer) -> i32,
+        request: &T,
+    ) -> Result<(), PluginError> {
+        let payload = serde_json::to_vec(request)
+            .map_err(|e| PluginError::with_source("Failed to encode the plugin request", e))?;
+        let mut error = FfiBuffer::empty();
+        let status = unsafe { call(self.handle.instance, FfiStr::new(&payload), &mut error) };
+        self.check_status(status, error)
+    }
+}
+
+// `call` must be one of `handle`'s own functions returning a buffer
+unsafe fn read_json<T: serde::de::DeserializeOwned>(
+    handle: &PluginHandle,
+    call: unsafe extern "C" fn(*mut std::ffi::c_void) -> FfiBuffer,
+) -> serde_json::Result<T> {
+    let json = call(handle.instance);
+    let parsed = serde_json::from_slice(json.as_bytes());
+    (handle.vtable.free_buffer)(json);
+    parsed
+}
+
+impl Drop for DynamicPlugin {
+    fn drop(&mut self) {
+        unsafe { (self.handle.vtable.destroy)(self.handle.instance) };
+    }
+}
+
+impl Plugin for DynamicPlugin {
+    fn get_config(&self) -> &PluginConfig {
+        &self.config
+    }
+
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn settings_schema(&self) -> SettingsSchema {
+        self.settings_schema.clone()
+    }
+
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError> {
+        self.call_with_request(self.handle.vtable.initialize, settings)
+    }
+
+    fn cleanup(&mut self) -> Result<(), PluginError> {
+        let mut error = FfiBuffer::empty();
+        let status = unsafe { (self.handle.vtable.cleanup)(self.handle.instance, &mut error) };
+        self.check_status(status, error)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        if self.supports_compression() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl CompressionPlugin for DynamicPlugin {
+    fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let request = CompressRequest {
+            input_files: input_files.to_vec(),
+            output_file: output_file.to_path_buf(),
+            options: options.clone(),
+        };
+        self.call_with_request(self.handle.vtable.compress, &request)
+    }
+
+    fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        let request = DecompressRequest {
+            archive_file: archive_file.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            overwrite,
+            limits,
+        };
+        self.call_with_request(self.handle.vtable.decompress, &request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_shadow_copy_is_private() {
+        let dir = tempdir().unwrap();
+        let library = dir.path().join(format!("plugin.{}", std::env::consts::DLL_EXTENSION));
+        fs::write(&library, b"library bytes").unwrap();
+
+        let copy = ShadowCopy::create(&library, &|bytes| {
+            assert_eq!(bytes, b"library bytes");
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(fs::read(copy.path()).unwrap(), b"library bytes");
+        copy.check_unchanged().unwrap();
+        fs::write(copy.path(), b"swapped bytes").unwrap();
+        assert!(copy.check_unchanged().is_err());
+        let copy_dir = copy.path().parent().unwrap().to_path_buf();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&copy_dir).unwrap().permissions().mode() & 0o777, 0o700);
+        }
+        drop(copy);
+        assert!(!copy_dir.exists());
+
+        assert!(ShadowCopy::create(&library, &|_| bail!("untrusted")).is_err());
     }
 }
@@ -0,0 +1,259 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{bail, Context, Result};
+use semver::{Version, VersionReq};
+use serde::{Serialize, Deserialize};
+use crate::plugin_api::types::{Capability, PluginMetadata, PluginType};
+
+pub const MANIFEST_FILE: &str = "plugin.toml";
+
+// Version of the core that plugin `core_version` requirements are matched against
+pub const CORE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub fn core_version() -> Version {
+    Version::parse(CORE_VERSION).expect("crate version is valid semver")
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginRuntime {
+    // Shared library using the C ABI in `plugin_api::abi`, runs with full process privileges
+    #[default]
+    Native,
+    // Sandboxed WebAssembly module using the host interface in `plugins::wasm`
+    Wasm,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PluginIsolation {
+    #[default]
+    InProcess,
+    // Runs in a helper process through `plugins::host`, so crashes only take down the helper
+    Process,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: String,
+    pub core_version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "type")]
+    pub plugin_type: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub runtime: PluginRuntime,
+    #[serde(default)]
+    pub isolation: PluginIsolation,
+    // Library file relative to the manifest; defaults to the only library next to it
+    pub library: Option<String>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    // Capabilities as `kind:value` strings, see `Capability`
+    #[serde(default)]
+    pub provides: Vec<Capability>,
+    #[serde(default)]
+    pub requires: Vec<Capability>,
+}
+
+impl PluginManifest {
+    pub fn parse(source: &str) -> Result<Self> {
+        let manifest: Self = toml::from_str(source)?;
+        Version::parse(&manifest.version)
+            .with_context(|| format!("Invalid plugin version '{}'", manifest.version))?;
+        VersionReq::parse(&manifest.core_version)
+            .with_context(|| format!("Invalid core_version requirement '{}'", manifest.core_version))?;
+        Ok(manifest)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::load_with_source(path)?.0)
+    }
+
+    // Also returns the raw file, which is what plugin signatures cover
+    pub fn load_with_source(path: &Path) -> Result<(Self, String)> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = Self::parse(&source).with_context(|| format!("Invalid manifest {}", path.display()))?;
+        Ok((manifest, source))
+    }
+
+    pub fn check_compatibility(&self, core: &Version) -> Result<()> {
+        let requirement = VersionReq::parse(&self.core_version)?;
+        if !requirement.matches(core) {
+            bail!(
+                "Plugin '{}' requires core version {}, but this is {}",
+                self.name,
+                self.core_version,
+                core
+            );
+        }
+        Ok(())
+    }
+
+    pub fn plugin_type(&self) -> PluginType {
+        match self.plugin_type.to_lowercase().as_str() {
+            "compression" => PluginType::Compression,
+            "transfer" => PluginType::Transfer,
+            _ => PluginType::Other(self.plugin_type.clone()),
+        }
+    }
+
+    pub fn library_path(&self, plugin_dir: &Path) -> Result<PathBuf> {
+        if let Some(library) = &self.library {
+            // Only names below the plugin's own directory, never `/abs/lib.so` or `../other`
+            let mut components = Path::new(library).components().filter(|c| *c != Component::CurDir).peekable();
+            let inside = components.peek().is_some() && components.all(|c| matches!(c, Component::Normal(_)));
+            if !inside {
+                bail!("`library` in {} must be a path inside the plugin directory: {}", MANIFEST_FILE, library);
+            }
+            return Ok(plugin_dir.join(library));
+        }
+
+        let mut libraries = Vec::new();
+        for entry in fs::read_dir(plugin_dir)? {
+            let path = entry?.path();
+            let matches = match self.runtime {
+                PluginRuntime::Native => is_plugin_library(&path),
+                PluginRuntime::Wasm => path.extension().map(|ext| ext == "wasm").unwrap_or(false),
+            };
+            if path.is_file() && matches {
+                libraries.push(path);
+            }
+        }
+        match libraries.len() {
+            1 => Ok(libraries.remove(0)),
+            0 => bail!("No plugin library found in {}", plugin_dir.display()),
+            _ => bail!(
+                "Several libraries found in {}, set `library` in {}",
+                plugin_dir.display(),
+                MANIFEST_FILE
+            ),
+        }
+    }
+
+    // The manifest is authoritative for identity; the library still reports author and platforms
+    pub fn apply_to(&self, metadata: &mut PluginMetadata) {
+        metadata.name = self.name.clone();
+        metadata.version = self.version.clone();
+        metadata.plugin_type = self.plugin_type();
+        metadata.core_version = Some(self.core_version.clone());
+        if !self.description.is_empty() {
+            metadata.description = self.description.clone();
+        }
+        if !self.author.is_empty() {
+            metadata.author = self.author.clone();
+        }
+        if !self.extensions.is_empty() {
+            metadata.extensions = self.extensions.clone();
+        }
+        if !self.provides.is_empty() {
+            metadata.provides = self.provides.clone();
+        }
+        if !self.requires.is_empty() {
+            metadata.requires = self.requires.clone();
+        }
+    }
+}
+
+pub fn is_plugin_library(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext == std::env::consts::DLL_EXTENSION)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const ZIP_MANIFEST: &str = r#"
+name = "ZIP"
+version = "0.1.0"
+core_version = ">=0.1.0"
+description = "ZIP compression plugin for Smart Transfer"
+type = "compression"
+"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = PluginManifest::parse(ZIP_MANIFEST).unwrap();
+        assert_eq!(manifest.name, "ZIP");
+        assert!(matches!(manifest.plugin_type(), PluginType::Compression));
+        assert!(manifest.library.is_none());
+        assert_eq!(manifest.runtime, PluginRuntime::Native);
+        manifest.check_compatibility(&core_version()).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_incompatible_core() {
+        let source = ZIP_MANIFEST.replace(">=0.1.0", "^2.0");
+        let manifest = PluginManifest::parse(&source).unwrap();
+        let err = manifest.check_compatibility(&Version::new(0, 1, 0)).unwrap_err();
+        assert!(err.to_string().contains("requires core version ^2.0"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_manifest() {
+        assert!(PluginManifest::parse(&ZIP_MANIFEST.replace(">=0.1.0", "not a version")).is_err());
+        assert!(PluginManifest::parse(&ZIP_MANIFEST.replace("version = \"0.1.0\"", "version = \"1\"")).is_err());
+        assert!(PluginManifest::parse("name = \"ZIP\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_capabilities() {
+        let source = format!(
+            "{}extensions = [\"tar.zst\"]\nprovides = [\"format:tar\", \"feature:streaming\"]\nrequires = [\"format:zstd\"]\n",
+            ZIP_MANIFEST
+        );
+        let manifest = PluginManifest::parse(&source).unwrap();
+        assert_eq!(manifest.requires, [Capability::Format("zstd".to_string())]);
+
+        let mut metadata: PluginMetadata = serde_json::from_str(
+            r#"{"name":"","version":"","author":"","description":"","plugin_type":"Compression","platform_support":{"windows":true,"linux":true,"macos":true}}"#,
+        )
+        .unwrap();
+        manifest.apply_to(&mut metadata);
+        assert!(metadata.has_capability(&"extension:.TAR.ZST".parse().unwrap()));
+        assert!(metadata.has_capability(&"feature:streaming".parse().unwrap()));
+        assert!(!metadata.has_capability(&"format:zstd".parse().unwrap()));
+
+        let unknown = ZIP_MANIFEST.to_string() + "provides = [\"feature:teleport\"]\n";
+        assert!(PluginManifest::parse(&unknown).is_err());
+        assert!(PluginManifest::parse(&(ZIP_MANIFEST.to_string() + "requires = [\"tar\"]\n")).is_err());
+    }
+
+    #[test]
+    fn test_library_path() {
+        let dir = tempdir().unwrap();
+        let manifest = PluginManifest::parse(ZIP_MANIFEST).unwrap();
+        assert!(manifest.library_path(dir.path()).is_err());
+
+        let library = dir.path().join(format!("zip.{}", std::env::consts::DLL_EXTENSION));
+        fs::write(&library, b"").unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE), ZIP_MANIFEST).unwrap();
+        assert_eq!(manifest.library_path(dir.path()).unwrap(), library);
+
+        fs::write(dir.path().join(format!("other.{}", std::env::consts::DLL_EXTENSION)), b"").unwrap();
+        assert!(manifest.library_path(dir.path()).is_err());
+
+        assert_eq!(manifest.isolation, PluginIsolation::InProcess);
+        let isolated = PluginManifest::parse(&format!("{}isolation = \"process\"\n", ZIP_MANIFEST)).unwrap();
+        assert_eq!(isolated.isolation, PluginIsolation::Process);
+
+        let wasm = PluginManifest::parse(&format!("{}runtime = \"wasm\"\n", ZIP_MANIFEST)).unwrap();
+        assert_eq!(wasm.runtime, PluginRuntime::Wasm);
+        fs::write(dir.path().join("zip.wasm"), b"").unwrap();
+        assert_eq!(wasm.library_path(dir.path()).unwrap(), dir.path().join("zip.wasm"));
+
+        let nested = PluginManifest::parse(&format!("{}library = \"lib/zip.wasm\"\n", ZIP_MANIFEST)).unwrap();
+        assert_eq!(nested.library_path(dir.path()).unwrap(), dir.path().join("lib").join("zip.wasm"));
+        for library in ["/usr/lib/libevil.so", "../other/zip.wasm", "lib/../../zip.wasm", ".", ""] {
+            let manifest = PluginManifest::parse(&format!("{}library = {:?}\n", ZIP_MANIFEST, library)).unwrap();
+            assert!(manifest.library_path(dir.path()).is_err(), "{}", library);
+        }
+    }
+}
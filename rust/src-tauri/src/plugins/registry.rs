@@ -1,59 +1,457 @@
 use std::collections::HashMap;
-use anyhow::Result;
-use crate::plugin_api::base::{Plugin, PluginError};
-use crate::plugin_api::types::PluginMetadata;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::task::{Context, Poll};
+use crate::plugin_api::base::{panic_message, Plugin, PluginError};
+use crate::plugin_api::settings::{PluginSettings, SettingsSchema};
+use crate::plugin_api::types::{Capability, PluginMetadata, PluginType};
 
+// A registered, initialized plugin shared with running jobs. Unregistering only removes it
+// from the registry; `cleanup` runs and the instance is dropped once the last job lets go.
+// The plugin itself is only reachable through `call`.
+pub struct LoadedPlugin {
+    plugin: Box<dyn Plugin>,
+    // Taken once at registration so lookups never call into the plugin
+    metadata: PluginMetadata,
+    settings_schema: SettingsSchema,
+    // What `initialize` was given, a value for every key in the plugin's schema
+    settings: PluginSettings,
+    // Message of the panic that disabled the plugin
+    panicked: Mutex<Option<String>>,
+}
+
+impl LoadedPlugin {
+    // Stored values that no longer fit the schema, say after a plugin update changed it,
+    // must not keep the plugin from loading, so the plugin then starts on its defaults
+    fn initialize(mut plugin: Box<dyn Plugin>, stored: &PluginSettings) -> Result<Self, PluginError> {
+        let name = plugin.get_config().name.clone();
+        let initialized = catch_unwind(AssertUnwindSafe(|| {
+            let schema = plugin.settings_schema();
+            let metadata = plugin.metadata();
+            let settings = schema.resolve(stored).unwrap_or_else(|e| {
+                log::warn!("Ignoring stored settings of plugin '{}': {}", metadata.name, e);
+                schema.defaults()
+            });
+            plugin.initialize(&settings).map(|()| (metadata, schema, settings))
+        }));
+        let (metadata, settings_schema, settings) =
+            initialized.unwrap_or_else(|payload| Err(PluginError::from_panic(&name, payload)))?;
+        Ok(Self {
+            plugin,
+            metadata,
+            settings_schema,
+            settings,
+            panicked: Mutex::new(None),
+        })
+    }
+
+    pub fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    pub fn settings_schema(&self) -> &SettingsSchema {
+        &self.settings_schema
+    }
+
+    pub fn settings(&self) -> &PluginSettings {
+        &self.settings
+    }
+
+    fn panicked(&self) -> MutexGuard<'_, Option<String>> {
+        self.panicked.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.panicked().is_none()
+    }
+
+    // Runs one call into the plugin. A panic, caught here or reported by the plugin's own
+    // boundary, disables the plugin, and calls into a disabled plugin are refused; only a
+    // new instance from `PluginRegistry::replace` is used again.
+    pub fn call<T>(&self, call: impl FnOnce(&dyn Plugin) -> Result<T, PluginError>) -> Result<T, PluginError> {
+        self.check_healthy()?;
+        let result = catch_unwind(AssertUnwindSafe(|| call(self.plugin.as_ref())));
+        self.settle(result)
+    }
+
+    // `call` for async plugin methods; panics while the future is polled are caught as well
+    pub async fn call_async<'a, T, F, Fut>(&'a self, call: F) -> Result<T, PluginError>
+    where
+        F: FnOnce(&'a dyn Plugin) -> Fut,
+        Fut: Future<Output = Result<T, PluginError>>,
+    {
+        self.check_healthy()?;
+        let result = match catch_unwind(AssertUnwindSafe(|| call(self.plugin.as_ref()))) {
+            Ok(future) => CatchUnwind(Box::pin(future)).await,
+            Err(payload) => Err(payload),
+        };
+        self.settle(result)
+    }
+
+    fn check_healthy(&self) -> Result<(), PluginError> {
+        match self.panicked().as_ref() {
+            Some(message) => Err(PluginError::other(format!(
+                "Plugin '{}' is disabled after it panicked ({}), reinitialize it to use it again",
+                self.metadata.name, message
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn settle<T>(&self, result: std::thread::Result<Result<T, PluginError>>) -> Result<T, PluginError> {
+        let result = result.unwrap_or_else(|payload| Err(PluginError::from_panic(&self.metadata.name, payload)));
+        if let Err(PluginError::Panicked { message, .. }) = &result {
+            log::error!("Plugin '{}' panicked and is disabled: {}", self.metadata.name, message);
+            *self.panicked() = Some(message.clone());
+        }
+        result
+    }
+
+    fn handles(&self, file_name: &str) -> Option<usize> {
+        self.metadata
+            .extensions
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty() && file_name.ends_with(&format!(".{}", ext)))
+            .map(|ext| ext.len())
+            .max()
+    }
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        match catch_unwind(AssertUnwindSafe(|| self.plugin.cleanup())) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("Error during cleanup of plugin '{}': {}", self.metadata.name, e),
+            Err(payload) => log::warn!(
+                "Plugin '{}' panicked during cleanup: {}",
+                self.metadata.name,
+                panic_message(payload.as_ref())
+            ),
+        }
+    }
+}
+
+// Turns a panic while polling the inner future into an `Err`, like `catch_unwind` does for calls
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+        match catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
+
+pub type PluginRef = Arc<LoadedPlugin>;
+
+// The one place plugins live once loaded, built-in or external. Plugins are initialized on
+// the way in and cleaned up when their last reference goes, so callers never manage either.
+// All methods take `&self`; lookups only hold the lock long enough to clone a `PluginRef`.
 pub struct PluginRegistry {
-    plugins: HashMap<String, Box<dyn Plugin>>,
+    plugins: RwLock<HashMap<String, PluginRef>>,
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PluginRegistry {
     pub fn new() -> Self {
         Self {
-            plugins: HashMap::new(),
+            plugins: RwLock::new(HashMap::new()),
+        }
+    }
+
+    // A panicking job must not take the plugin system down with it, so poisoning is ignored
+    fn plugins(&self) -> RwLockReadGuard<'_, HashMap<String, PluginRef>> {
+        self.plugins.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn plugins_mut(&self) -> RwLockWriteGuard<'_, HashMap<String, PluginRef>> {
+        self.plugins.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Registers `plugin` with its default settings
+    pub fn register(&self, name: String, plugin: Box<dyn Plugin>) -> Result<PluginRef, PluginError> {
+        self.register_with_settings(name, plugin, &PluginSettings::new())
+    }
+
+    // Initializes `plugin` with `settings` over its defaults and registers it under `name`;
+    // taken names are refused before the plugin is initialized
+    pub fn register_with_settings(
+        &self,
+        name: String,
+        plugin: Box<dyn Plugin>,
+        settings: &PluginSettings,
+    ) -> Result<PluginRef, PluginError> {
+        if self.contains(&name) {
+            return Err(PluginError::AlreadyExists(name));
+        }
+        let loaded = Arc::new(LoadedPlugin::initialize(plugin, settings)?);
+
+        let mut plugins = self.plugins_mut();
+        if plugins.contains_key(&name) {
+            return Err(PluginError::AlreadyExists(name));
         }
+        plugins.insert(name, Arc::clone(&loaded));
+        Ok(loaded)
     }
 
-    pub fn register(&mut self, name: String, plugin: Box<dyn Plugin>) -> Result<(), PluginError> {
-        if self.plugins.contains_key(&name) {
+    // Swaps the plugin registered as `previous` for `plugin` under `name` in one step, so
+    // lookups never see neither. Returns the previous instance; drop it outside any lock
+    // held by the caller, as that may run its cleanup.
+    pub fn replace(
+        &self,
+        previous: &str,
+        name: String,
+        plugin: Box<dyn Plugin>,
+        settings: &PluginSettings,
+    ) -> Result<Option<PluginRef>, PluginError> {
+        let loaded = Arc::new(LoadedPlugin::initialize(plugin, settings)?);
+
+        let mut plugins = self.plugins_mut();
+        if name != previous && plugins.contains_key(&name) {
             return Err(PluginError::AlreadyExists(name));
         }
-        self.plugins.insert(name, plugin);
-        Ok(())
+        let replaced = plugins.remove(previous);
+        plugins.insert(name, loaded);
+        Ok(replaced)
+    }
+
+    pub fn unregister(&self, name: &str) -> Option<PluginRef> {
+        self.plugins_mut().remove(name)
+    }
+
+    pub fn get_plugin(&self, name: &str) -> Option<PluginRef> {
+        self.plugins().get(name).cloned()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.plugins().contains_key(name)
+    }
+
+    // Sorted by name so callers get a stable order
+    pub fn plugins_of_type(&self, plugin_type: &PluginType) -> Vec<PluginRef> {
+        let plugins = self.plugins();
+        let mut matching: Vec<_> = plugins
+            .iter()
+            .filter(|(_, plugin)| plugin.metadata.plugin_type == *plugin_type)
+            .collect();
+        matching.sort_by(|a, b| a.0.cmp(b.0));
+        matching.into_iter().map(|(_, p)| Arc::clone(p)).collect()
+    }
+
+    // Plugins providing every capability in `required`, sorted by name. Answers questions
+    // like "which plugin writes .tar.xz with a password".
+    pub fn plugins_with(&self, required: &[Capability]) -> Vec<PluginRef> {
+        let plugins = self.plugins();
+        let mut matching: Vec<_> = plugins
+            .iter()
+            .filter(|(_, plugin)| required.iter().all(|c| plugin.metadata.has_capability(c)))
+            .collect();
+        matching.sort_by(|a, b| a.0.cmp(b.0));
+        matching.into_iter().map(|(_, p)| Arc::clone(p)).collect()
+    }
+
+    // Requirements of `metadata` that no registered plugin provides
+    pub fn missing_requirements(&self, metadata: &PluginMetadata) -> Vec<Capability> {
+        let plugins = self.plugins();
+        metadata
+            .requires
+            .iter()
+            .filter(|required| !plugins.values().any(|p| p.metadata.has_capability(required)))
+            .cloned()
+            .collect()
+    }
+
+    // Plugins whose requirements would go unmet if `name` were removed, or replaced by a
+    // plugin with `replacement` metadata
+    pub fn dependents_of(&self, name: &str, replacement: Option<&PluginMetadata>) -> Vec<String> {
+        let plugins = self.plugins();
+        let provided = |required: &Capability| {
+            replacement.is_some_and(|m| m.has_capability(required))
+                || plugins.iter().any(|(other, p)| other != name && p.metadata.has_capability(required))
+        };
+        let mut dependents: Vec<String> = plugins
+            .iter()
+            .filter(|(other, plugin)| *other != name && !plugin.metadata.requires.iter().all(provided))
+            .map(|(other, _)| other.clone())
+            .collect();
+        dependents.sort();
+        dependents
+    }
+
+    // Accepts `zip`, `.zip` or `tar.gz`
+    pub fn plugin_for_extension(&self, extension: &str) -> Option<PluginRef> {
+        self.plugin_for_file_name(&format!("file.{}", extension.trim_start_matches('.')))
     }
 
-    pub fn get_plugin(&self, name: &str) -> Option<&Box<dyn Plugin>> {
-        self.plugins.get(name)
+    // Picks the plugin declaring the longest extension the file name ends with, so a
+    // `tar.gz` plugin wins over a `gz` one. Ties go to the first name in sort order.
+    pub fn plugin_for_path(&self, path: &Path) -> Option<PluginRef> {
+        let file_name = path.file_name()?.to_string_lossy();
+        self.plugin_for_file_name(&file_name)
     }
 
-    pub fn get_plugin_mut(&mut self, name: &str) -> Option<&mut Box<dyn Plugin>> {
-        self.plugins.get_mut(name)
+    fn plugin_for_file_name(&self, file_name: &str) -> Option<PluginRef> {
+        let file_name = file_name.to_lowercase();
+        let plugins = self.plugins();
+        plugins
+            .iter()
+            .filter_map(|(name, plugin)| plugin.handles(&file_name).map(|len| (len, name, plugin)))
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1)))
+            .map(|(_, _, plugin)| Arc::clone(plugin))
     }
 
     pub fn list_plugins(&self) -> Vec<PluginMetadata> {
-        self.plugins.values()
-            .map(|plugin| plugin.metadata())
+        self.plugins().values()
+            .map(|plugin| plugin.metadata.clone())
             .collect()
     }
 
-    pub fn cleanup_all(&mut self) -> Result<(), PluginError> {
-        for plugin in self.plugins.values_mut() {
-            plugin.cleanup()?;
-        }
-        Ok(())
+    // Releases the registry's references; plugins still used by a job are cleaned up after it
+    pub fn cleanup_all(&self) {
+        // Taken out first so cleanup does not run under the lock
+        let plugins = std::mem::take(&mut *self.plugins_mut());
+        drop(plugins);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plugins::sevenz::SevenZipPlugin;
     use crate::plugins::zip::ZipPlugin;
 
     #[test]
     fn test_plugin_registry() {
-        let mut registry = PluginRegistry::new();
+        let registry = PluginRegistry::new();
         let plugin = Box::new(ZipPlugin::new());
         registry.register("ZIP Plugin".to_string(), plugin).unwrap();
         assert!(registry.get_plugin("ZIP Plugin").is_some());
+        assert!(matches!(
+            registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())),
+            Err(PluginError::AlreadyExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_unregister_keeps_running_references() {
+        let registry = PluginRegistry::new();
+        registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        let in_use = registry.get_plugin("ZIP Plugin").unwrap();
+
+        let previous = registry
+            .replace("ZIP Plugin", "ZIP Plugin".to_string(), Box::new(ZipPlugin::new()), &PluginSettings::new())
+            .unwrap()
+            .unwrap();
+        assert!(Arc::ptr_eq(&previous, &in_use));
+        assert!(!Arc::ptr_eq(&registry.get_plugin("ZIP Plugin").unwrap(), &in_use));
+        drop(previous);
+
+        registry.unregister("ZIP Plugin");
+        assert!(registry.get_plugin("ZIP Plugin").is_none());
+        assert_eq!(in_use.metadata().name, "ZIP Plugin");
+    }
+
+    #[test]
+    fn test_lookup_by_type_and_extension() {
+        let registry = PluginRegistry::new();
+        registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        registry.register("7-Zip Plugin".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+
+        let names: Vec<String> = registry
+            .plugins_of_type(&PluginType::Compression)
+            .iter()
+            .map(|p| p.metadata().name.clone())
+            .collect();
+        assert_eq!(names, ["7-Zip Plugin", "ZIP Plugin"]);
+        assert!(registry.plugins_of_type(&PluginType::Transfer).is_empty());
+
+        assert_eq!(registry.plugin_for_extension(".ZIP").unwrap().metadata().name, "ZIP Plugin");
+        assert_eq!(registry.plugin_for_extension("7z").unwrap().metadata().name, "7-Zip Plugin");
+        assert_eq!(
+            registry.plugin_for_path(Path::new("/tmp/backup.2024.zip")).unwrap().metadata().name,
+            "ZIP Plugin"
+        );
+        assert!(registry.plugin_for_path(Path::new("notes.txt")).is_none());
+        assert!(registry.plugin_for_path(Path::new("zip")).is_none());
+    }
+
+    #[test]
+    fn test_lookup_by_capability() {
+        let registry = PluginRegistry::new();
+        registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        registry.register("7-Zip Plugin".to_string(), Box::new(SevenZipPlugin::new())).unwrap();
+
+        let zip: Vec<Capability> = vec!["extension:zip".parse().unwrap(), "format:zip".parse().unwrap()];
+        let found = registry.plugins_with(&zip);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].metadata().name, "ZIP Plugin");
+        assert!(registry.plugins_with(&["feature:encryption".parse().unwrap()]).is_empty());
+        assert_eq!(registry.plugins_with(&[]).len(), 2);
+
+        let mut tar_zst = ZipPlugin::new().metadata();
+        tar_zst.requires = vec!["format:7z".parse().unwrap(), "format:zstd".parse().unwrap()];
+        assert_eq!(registry.missing_requirements(&tar_zst), [Capability::Format("zstd".to_string())]);
+    }
+
+    #[test]
+    fn test_invalid_stored_settings_fall_back_to_defaults() {
+        let registry = PluginRegistry::new();
+        let mut stored = PluginSettings::new();
+        stored.insert("level".to_string(), serde_json::json!(3));
+        let zip = registry
+            .register_with_settings("ZIP Plugin".to_string(), Box::new(ZipPlugin::new()), &stored)
+            .unwrap();
+        assert_eq!(zip.settings()["level"], serde_json::json!(3));
+
+        stored.insert("level".to_string(), serde_json::json!("fast"));
+        registry
+            .replace("ZIP Plugin", "ZIP Plugin".to_string(), Box::new(ZipPlugin::new()), &stored)
+            .unwrap();
+        let zip = registry.get_plugin("ZIP Plugin").unwrap();
+        assert_eq!(zip.settings(), &zip.settings_schema().defaults());
+    }
+
+    #[tokio::test]
+    async fn test_panics_disable_the_plugin() {
+        let registry = PluginRegistry::new();
+        let zip = registry.register("ZIP Plugin".to_string(), Box::new(ZipPlugin::new())).unwrap();
+        assert_eq!(zip.call(|plugin| Ok(plugin.metadata().name)).unwrap(), "ZIP Plugin");
+
+        let err = zip.call(|_| -> Result<(), PluginError> { panic!("boom") }).unwrap_err();
+        assert!(matches!(&err, PluginError::Panicked { plugin, message } if plugin == "ZIP Plugin" && message == "boom"));
+        assert!(!zip.is_healthy());
+        let err = zip.call(|_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains("disabled after it panicked (boom)"));
+
+        registry
+            .replace("ZIP Plugin", "ZIP Plugin".to_string(), Box::new(ZipPlugin::new()), &PluginSettings::new())
+            .unwrap();
+        let zip = registry.get_plugin("ZIP Plugin").unwrap();
+        assert!(zip.is_healthy());
+        let err = zip
+            .call_async(|_| async {
+                tokio::task::yield_now().await;
+                panic!("boom while polled");
+                #[allow(unreachable_code)]
+                Ok::<(), PluginError>(())
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("boom while polled"));
+        assert!(!zip.is_healthy());
     }
 }
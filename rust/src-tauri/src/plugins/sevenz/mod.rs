@@ -1,11 +1,16 @@
 use std::any::Any;
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 use anyhow::Result;
 use crate::plugin_api::base::{Plugin, PluginError, PluginConfig};
-use crate::plugin_api::compression::CompressionPlugin;
-use crate::plugin_api::types::{CompressionOptions, PluginMetadata, PlatformSupport, PluginType};
-use sevenz_rust;
+use crate::plugin_api::compression::{
+    is_removed, persist_replacement, replacement_file, CompressionPlugin, EntryInfo, EntryKind, EntryMetadata,
+    StreamEntry, WriteSeek,
+};
+use crate::plugin_api::extract::{extract_entries, ExtractionGuard, ExtractionLimits};
+use crate::plugin_api::settings::PluginSettings;
+use crate::plugin_api::types::{Capability, CompressionOptions, PluginMetadata, PlatformSupport, PluginType};
+use sevenz_rust::{self, Password, SevenZArchiveEntry, SevenZReader, SevenZWriter};
 use tempfile;
 
 pub struct SevenZipPlugin {
@@ -33,6 +38,10 @@ impl SevenZipPlugin {
                     macos: true,
                 },
                 plugin_type: PluginType::Compression,
+                core_version: None,
+                extensions: vec![String::from("7z")],
+                provides: vec![Capability::Format(String::from("7z"))],
+                requires: Vec::new(),
             },
         }
     }
@@ -47,7 +56,7 @@ impl Plugin for SevenZipPlugin {
         self.metadata.clone()
     }
 
-    fn initialize(&mut self) -> Result<(), PluginError> {
+    fn initialize(&mut self, _settings: &PluginSettings) -> Result<(), PluginError> {
         Ok(())
     }
 
@@ -62,53 +71,273 @@ impl Plugin for SevenZipPlugin {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        Some(self)
+    }
 }
 
 impl CompressionPlugin for SevenZipPlugin {
     fn compress(
         &self,
         input_files: &[PathBuf],
-        output_file: &PathBuf,
+        output_file: &Path,
         _options: &CompressionOptions,
     ) -> Result<(), PluginError> {
         // Create a temporary directory to store files
-        let temp_dir = tempfile::tempdir().map_err(|e| PluginError::Other(e.to_string()))?;
+        let temp_dir = tempfile::tempdir()
+            .map_err(|e| PluginError::with_source("Failed to create a staging directory", e))?;
         
         // Copy files to temp directory to maintain relative paths
         for input_file in input_files {
             let file_name = input_file.file_name()
                 .ok_or_else(|| PluginError::InvalidInput("Invalid file name".to_string()))?;
             let temp_path = temp_dir.path().join(file_name);
-            std::fs::copy(input_file, &temp_path).map_err(PluginError::from)?;
+            std::fs::copy(input_file, &temp_path).map_err(|e| PluginError::io(input_file, e))?;
         }
 
         // Compress the directory
         sevenz_rust::compress_to_path(temp_dir.path(), output_file)
-            .map_err(|e| PluginError::Other(e.to_string()))?;
+            .map_err(|e| sevenz_error(Some(output_file), e))?;
 
         Ok(())
     }
 
     fn decompress(
         &self,
-        archive_file: &PathBuf,
-        output_dir: &PathBuf,
-        _overwrite: bool,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
     ) -> Result<(), PluginError> {
-        // Create output directory
-        std::fs::create_dir_all(output_dir)?;
-
-        // Read archive file
-        let archive_data = std::fs::read(archive_file)?;
+        extract_entries(self, archive_file, output_dir, overwrite, limits)
+    }
 
-        // Decompress using sevenz-rust
-        sevenz_rust::decompress(
-            &mut Cursor::new(archive_data),
-            output_dir,
-        ).map_err(|e| PluginError::Other(e.to_string()))?;
+    // 7z keeps its index at the end and is written with seeks, so streamed archives are
+    // assembled, or read, in memory
+    fn compress_stream(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        output: &mut dyn Write,
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let mut writer = SevenZWriter::new(Cursor::new(Vec::new())).map_err(|e| sevenz_error(None, e))?;
+        for entry in entries {
+            push_entry(&mut writer, entry?, None)?;
+        }
+        let archive = writer.finish()?;
+        output.write_all(archive.get_ref())?;
+        output.flush()?;
+        Ok(())
+    }
 
+    fn compress_seekable(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        output: &mut dyn WriteSeek,
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let mut writer = SevenZWriter::new(&mut *output).map_err(|e| sevenz_error(None, e))?;
+        for entry in entries {
+            push_entry(&mut writer, entry?, None)?;
+        }
+        writer.finish()?;
+        output.flush()?;
         Ok(())
     }
+
+    fn read_entries(
+        &self,
+        archive_file: &Path,
+        visit: &mut dyn FnMut(EntryInfo, &mut dyn Read) -> Result<(), PluginError>,
+    ) -> Result<(), PluginError> {
+        let mut reader = SevenZReader::open(archive_file, Password::empty())
+            .map_err(|e| sevenz_error(Some(archive_file), e))?;
+        visit_entries(&mut reader, Some(archive_file), visit)
+    }
+
+    // Solid blocks cannot be copied apart, so the kept entries are decompressed and written
+    // again into a new file that replaces the archive once it is complete
+    fn update(
+        &self,
+        archive_file: &Path,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        remove: &[String],
+        _options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let entries = entries.collect::<Result<Vec<_>, _>>()?;
+        let mut reader = SevenZReader::open(archive_file, Password::empty())
+            .map_err(|e| sevenz_error(Some(archive_file), e))?;
+        let existing = &reader.archive().files;
+        if let Some(missing) = remove.iter().find(|name| !existing.iter().any(|entry| is_removed(&entry.name, name))) {
+            return Err(PluginError::NotFound(missing.clone()));
+        }
+
+        let mut writer = SevenZWriter::new(replacement_file(archive_file)?).map_err(|e| sevenz_error(None, e))?;
+        let keep = |name: &str| {
+            !remove.iter().any(|removed| is_removed(name, removed))
+                && !entries.iter().any(|new| new.name.trim_end_matches('/') == name.trim_end_matches('/'))
+        };
+        reader
+            .for_each_entries(|entry, data| {
+                if !keep(&entry.name) {
+                    std::io::copy(data, &mut std::io::sink())?;
+                    return Ok(true);
+                }
+                let mut copy = SevenZArchiveEntry::new();
+                copy.name = entry.name.clone();
+                copy.is_directory = entry.is_directory;
+                copy.has_stream = entry.has_stream;
+                copy.has_last_modified_date = entry.has_last_modified_date;
+                copy.last_modified_date = entry.last_modified_date;
+                copy.has_windows_attributes = entry.has_windows_attributes;
+                copy.windows_attributes = entry.windows_attributes;
+                let data = if entry.has_stream { Some(data) } else { None };
+                writer.push_archive_entry(copy, data)?;
+                Ok(true)
+            })
+            .map_err(|e| sevenz_error(Some(archive_file), e))?;
+        for entry in entries {
+            push_entry(&mut writer, entry, Some(archive_file))?;
+        }
+        let temp = writer.finish().map_err(|e| PluginError::io(archive_file, e))?;
+        persist_replacement(temp, archive_file)
+    }
+
+    fn decompress_stream(
+        &self,
+        input: &mut dyn Read,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        // 7z has to seek, so the stream is spooled to disk first, up to as much as may be
+        // extracted from it
+        let max = limits.max_total_size.unwrap_or(u64::MAX);
+        let mut spool = tempfile::tempfile()?;
+        let len = io::copy(&mut input.take(max.saturating_add(1)), &mut spool)?;
+        if len > max {
+            return Err(PluginError::LimitExceeded(format!("the archive is larger than {} bytes", max)));
+        }
+        spool.rewind()?;
+        let mut guard = ExtractionGuard::new(output_dir, overwrite, limits)?;
+        guard.set_compressed_size(len);
+        let mut reader = SevenZReader::new(spool, len, Password::empty()).map_err(|e| sevenz_error(None, e))?;
+        visit_entries(&mut reader, None, &mut |info, data| guard.extract(info, data))
+    }
+}
+
+// `visit` errors stop the walk and are returned as they are
+fn visit_entries<R: Read + Seek>(
+    reader: &mut SevenZReader<R>,
+    archive: Option<&Path>,
+    visit: &mut dyn FnMut(EntryInfo, &mut dyn Read) -> Result<(), PluginError>,
+) -> Result<(), PluginError> {
+    let mut failed = None;
+    reader
+        .for_each_entries(|entry, data| {
+            let info = entry_info(entry, data).map_err(sevenz_rust::Error::io)?;
+            // `compress_to_path` stores the directory it was given as a nameless entry
+            if info.name.is_empty() && info.kind == EntryKind::Directory {
+                return Ok(true);
+            }
+            match visit(info, data) {
+                Ok(()) => Ok(true),
+                Err(e) => {
+                    failed = Some(e);
+                    Ok(false)
+                }
+            }
+        })
+        .map_err(|e| sevenz_error(archive, e))?;
+    failed.map_or(Ok(()), Err)
+}
+
+fn push_entry<W: Write + Seek>(
+    writer: &mut SevenZWriter<W>,
+    entry: StreamEntry<'_>,
+    archive: Option<&Path>,
+) -> Result<(), PluginError> {
+    let mut archive_entry = SevenZArchiveEntry::new();
+    archive_entry.name = entry.name;
+    // 7z keeps Windows attributes rather than Unix modes, so only the time is stored
+    if let Some(modified) = entry.metadata.modified.and_then(|time| time.try_into().ok()) {
+        archive_entry.last_modified_date = modified;
+        archive_entry.has_last_modified_date = true;
+    }
+    let reader = match entry.kind {
+        EntryKind::File => {
+            archive_entry.has_stream = true;
+            Some(entry.reader)
+        }
+        EntryKind::Directory => {
+            archive_entry.is_directory = true;
+            None
+        }
+        EntryKind::Symlink { .. } => {
+            return Err(PluginError::UnsupportedFeature(format!(
+                "7z archives cannot hold the symlink '{}'",
+                archive_entry.name
+            )))
+        }
+    };
+    writer
+        .push_archive_entry(archive_entry, reader)
+        .map_err(|e| sevenz_error(archive, e))?;
+    Ok(())
+}
+
+// Set in the Windows attributes by archivers that keep a Unix mode in their upper 16 bits
+const UNIX_EXTENSION: u32 = 0x8000;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+// Symlinks keep their target as the entry's data, which is read here
+fn entry_info(entry: &SevenZArchiveEntry, data: &mut dyn Read) -> std::io::Result<EntryInfo> {
+    let unix_mode = (entry.has_windows_attributes && entry.windows_attributes & UNIX_EXTENSION != 0)
+        .then_some(entry.windows_attributes >> 16);
+    let metadata = EntryMetadata {
+        modified: entry.has_last_modified_date.then(|| entry.last_modified_date.into()),
+        mode: unix_mode.map(|mode| mode & 0o7777),
+    };
+    let kind = if entry.is_directory {
+        EntryKind::Directory
+    } else if unix_mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+        let mut target = String::new();
+        data.read_to_string(&mut target)?;
+        EntryKind::Symlink { target }
+    } else {
+        EntryKind::File
+    };
+    Ok(EntryInfo {
+        name: entry.name.trim_end_matches('/').to_string(),
+        kind,
+        metadata,
+    })
+}
+
+// `archive` is the file being read or written when the error happened, if there is one
+fn sevenz_error(archive: Option<&Path>, error: sevenz_rust::Error) -> PluginError {
+    use sevenz_rust::Error;
+    let path = archive.map(Path::to_path_buf);
+    match error {
+        Error::Io(e, _) | Error::FileOpen(e, _) => match archive {
+            Some(archive) => PluginError::io(archive, e),
+            None => e.into(),
+        },
+        Error::PasswordRequired | Error::MaybeBadPassword(_) => PluginError::WrongPassword { path },
+        Error::UnsupportedCompressionMethod(method) => {
+            PluginError::UnsupportedFeature(format!("7z compression method {}", method))
+        }
+        Error::Unsupported(feature) => PluginError::UnsupportedFeature(feature.into_owned()),
+        error @ (Error::ExternalUnsupported | Error::MaxMemLimited { .. }) => {
+            PluginError::UnsupportedFeature(error.to_string())
+        }
+        error => PluginError::CorruptArchive {
+            path,
+            source: Some(Box::new(error)),
+        },
+    }
 }
 
 #[cfg(test)]
@@ -122,4 +351,102 @@ mod tests {
         let plugin = SevenZipPlugin::new();
         assert_eq!(plugin.get_config().name, "7-Zip Plugin");
     }
+
+    #[test]
+    fn test_corrupt_archive() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("garbage.7z");
+        fs::write(&archive, "not a 7z archive".repeat(8)).unwrap();
+
+        let err = SevenZipPlugin::new()
+            .decompress(&archive, &dir.path().join("out"), true, ExtractionLimits::default())
+            .unwrap_err();
+        assert_eq!(err.code(), crate::plugin_api::ErrorCode::CorruptArchive);
+        assert_eq!(err.path(), Some(archive.as_path()));
+    }
+
+    #[test]
+    fn test_escaping_entries_are_refused() {
+        let dir = tempdir().unwrap();
+        let plugin = SevenZipPlugin::new();
+        let options = CompressionOptions::default();
+        let archive = dir.path().join("escaping.7z");
+        let mut entries = vec![Ok(StreamEntry::new("../evil.txt", &b"evil"[..]))].into_iter();
+        plugin.compress_seekable(&mut entries, &mut fs::File::create(&archive).unwrap(), &options).unwrap();
+
+        let output = dir.path().join("out");
+        let err = plugin.decompress(&archive, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), crate::plugin_api::ErrorCode::PathTraversal);
+        assert!(!dir.path().join("evil.txt").exists());
+
+        let data = fs::read(&archive).unwrap();
+        let err = plugin.decompress_stream(&mut data.as_slice(), &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), crate::plugin_api::ErrorCode::PathTraversal);
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_streamed_archives_are_capped() {
+        let dir = tempdir().unwrap();
+        let plugin = SevenZipPlugin::new();
+        let mut archive = Vec::new();
+        let mut entries = vec![Ok(StreamEntry::new("notes.txt", &b"some notes"[..]))].into_iter();
+        plugin.compress_stream(&mut entries, &mut archive, &CompressionOptions::default()).unwrap();
+
+        let output = dir.path().join("out");
+        let limits = ExtractionLimits {
+            max_total_size: Some(archive.len() as u64 - 1),
+            ..ExtractionLimits::unlimited()
+        };
+        let err = plugin.decompress_stream(&mut archive.as_slice(), &output, true, limits).unwrap_err();
+        assert_eq!(err.code(), crate::plugin_api::ErrorCode::LimitExceeded);
+        assert!(!output.join("notes.txt").exists());
+
+        plugin.decompress_stream(&mut archive.as_slice(), &output, true, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read(output.join("notes.txt")).unwrap(), b"some notes");
+    }
+
+    #[test]
+    fn test_update_rewrites_the_archive() {
+        let dir = tempdir().unwrap();
+        let plugin = SevenZipPlugin::new();
+        let options = CompressionOptions::default();
+        let archive = dir.path().join("files.7z");
+        let mut entries = vec![
+            Ok(StreamEntry::new("a.txt", &b"first"[..])),
+            Ok(StreamEntry::new("old/b.txt", &b"second"[..])),
+            Ok(StreamEntry::new("empty.txt", &b""[..])),
+        ]
+        .into_iter();
+        plugin.compress_seekable(&mut entries, &mut fs::File::create(&archive).unwrap(), &options).unwrap();
+
+        let mut entries = vec![
+            Ok(StreamEntry::new("a.txt", &b"replaced"[..])),
+            Ok(StreamEntry::new("c.txt", &b"third"[..])),
+        ]
+        .into_iter();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&archive, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        plugin.update(&archive, &mut entries, &["old".to_string()], &options).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&archive).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+
+        let output = dir.path().join("out");
+        plugin.decompress(&archive, &output, true, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read_to_string(output.join("a.txt")).unwrap(), "replaced");
+        assert_eq!(fs::read_to_string(output.join("c.txt")).unwrap(), "third");
+        assert_eq!(fs::read_to_string(output.join("empty.txt")).unwrap(), "");
+        assert!(!output.join("old").exists());
+
+        let before = fs::read(&archive).unwrap();
+        let err = plugin.update(&archive, &mut std::iter::empty(), &["missing.txt".to_string()], &options).unwrap_err();
+        assert_eq!(err.code(), crate::plugin_api::error::ErrorCode::NotFound);
+        assert_eq!(fs::read(&archive).unwrap(), before);
+    }
 }
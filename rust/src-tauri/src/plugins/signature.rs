@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::plugin_api::platform::get_config_dir;
+
+// Detached signature next to `plugin.toml`, base64 encoded
+pub const SIGNATURE_FILE: &str = "plugin.sig";
+const TRUST_FILE: &str = "trusted_plugin_keys.json";
+const SIGNING_CONTEXT: &[u8] = b"smart-transfer plugin signature v1\0";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignaturePolicy {
+    // Only plugins signed by a trusted key are loaded
+    #[default]
+    Require,
+    // Unsigned plugins load with a warning, meant for plugin development.
+    // A signature that does not verify is still refused.
+    Warn,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustedKey {
+    pub name: String,
+    // Base64 encoded 32-byte ed25519 public key
+    pub public_key: String,
+}
+
+impl TrustedKey {
+    pub fn new(name: &str, key: &VerifyingKey) -> Self {
+        Self {
+            name: name.to_string(),
+            public_key: base64::engine::general_purpose::STANDARD.encode(key.as_bytes()),
+        }
+    }
+
+    fn verifying_key(&self) -> Result<VerifyingKey> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(self.public_key.trim())?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Public key must be 32 bytes"))?;
+        Ok(VerifyingKey::from_bytes(&bytes)?)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginTrustConfig {
+    #[serde(default)]
+    pub policy: SignaturePolicy,
+    #[serde(default)]
+    pub keys: Vec<TrustedKey>,
+}
+
+impl PluginTrustConfig {
+    pub fn default_path() -> PathBuf {
+        get_config_dir().join(TRUST_FILE)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path())
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::default_path())
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    // Checks the exact manifest and library bytes that are about to be loaded
+    pub fn verify(&self, plugin_dir: &Path, manifest: &[u8], library: &[u8]) -> Result<()> {
+        let signature_path = plugin_dir.join(SIGNATURE_FILE);
+        if !signature_path.exists() {
+            match self.policy {
+                SignaturePolicy::Require => {
+                    bail!("Plugin is not signed, {} is missing", SIGNATURE_FILE)
+                }
+                SignaturePolicy::Warn => {
+                    log::warn!("Loading unsigned plugin from {}", plugin_dir.display());
+                    return Ok(());
+                }
+            }
+        }
+
+        let signature = read_signature(&signature_path)?;
+        let payload = signing_payload(manifest, library);
+        for key in &self.keys {
+            let verifying_key = match key.verifying_key() {
+                Ok(verifying_key) => verifying_key,
+                Err(e) => {
+                    log::warn!("Ignoring invalid trusted key '{}': {}", key.name, e);
+                    continue;
+                }
+            };
+            if verifying_key.verify(&payload, &signature).is_ok() {
+                log::info!("Plugin in {} is signed by '{}'", plugin_dir.display(), key.name);
+                return Ok(());
+            }
+        }
+        bail!("Plugin signature does not match any trusted key, the plugin may have been modified")
+    }
+}
+
+fn read_signature(path: &Path) -> Result<Signature> {
+    let encoded = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .with_context(|| format!("Malformed signature in {}", path.display()))?;
+    let bytes: [u8; 64] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("Malformed signature in {}", path.display()))?;
+    Ok(Signature::from_bytes(&bytes))
+}
+
+// Both files are hashed separately so neither can be shifted into the other
+pub fn signing_payload(manifest: &[u8], library: &[u8]) -> Vec<u8> {
+    let mut payload = SIGNING_CONTEXT.to_vec();
+    payload.extend_from_slice(&Sha256::digest(manifest));
+    payload.extend_from_slice(&Sha256::digest(library));
+    payload
+}
+
+// Writes `plugin.sig` for the manifest and library of a plugin directory
+pub fn sign_plugin(key: &SigningKey, manifest_path: &Path, library_path: &Path) -> Result<()> {
+    let payload = signing_payload(&fs::read(manifest_path)?, &fs::read(library_path)?);
+    let signature = key.sign(&payload);
+    let plugin_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    fs::write(
+        plugin_dir.join(SIGNATURE_FILE),
+        base64::engine::general_purpose::STANDARD.encode(signature.to_bytes()),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn generate_key() -> SigningKey {
+        SigningKey::from_bytes(&rand::random::<[u8; 32]>())
+    }
+
+    fn plugin_dir(dir: &Path) -> (PathBuf, PathBuf) {
+        let manifest = dir.join("plugin.toml");
+        let library = dir.join("plugin.wasm");
+        fs::write(&manifest, "name = \"Signed\"").unwrap();
+        fs::write(&library, b"\0asm library bytes").unwrap();
+        (manifest, library)
+    }
+
+    fn trust(policy: SignaturePolicy, key: &SigningKey) -> PluginTrustConfig {
+        PluginTrustConfig {
+            policy,
+            keys: vec![TrustedKey::new("test", &key.verifying_key())],
+        }
+    }
+
+    #[test]
+    fn test_signed_plugin_verifies() {
+        let dir = tempdir().unwrap();
+        let key = generate_key();
+        let (manifest, library) = plugin_dir(dir.path());
+        sign_plugin(&key, &manifest, &library).unwrap();
+
+        let config = trust(SignaturePolicy::Require, &key);
+        config
+            .verify(dir.path(), &fs::read(&manifest).unwrap(), &fs::read(&library).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_rejects_tampered_and_untrusted() {
+        let dir = tempdir().unwrap();
+        let key = generate_key();
+        let (manifest, library) = plugin_dir(dir.path());
+        sign_plugin(&key, &manifest, &library).unwrap();
+        let manifest = fs::read(&manifest).unwrap();
+        let library = fs::read(&library).unwrap();
+
+        // Even the lenient policy refuses a signature that does not verify
+        let config = trust(SignaturePolicy::Warn, &key);
+        let mut tampered = library.clone();
+        tampered[0] ^= 1;
+        let err = config.verify(dir.path(), &manifest, &tampered).unwrap_err();
+        assert!(err.to_string().contains("does not match any trusted key"));
+        assert!(config.verify(dir.path(), b"name = \"Other\"", &library).is_err());
+
+        let other = trust(SignaturePolicy::Require, &generate_key());
+        assert!(other.verify(dir.path(), &manifest, &library).is_err());
+    }
+
+    #[test]
+    fn test_unsigned_plugin_policy() {
+        let dir = tempdir().unwrap();
+        let key = generate_key();
+        let (manifest, library) = plugin_dir(dir.path());
+        let manifest = fs::read(&manifest).unwrap();
+        let library = fs::read(&library).unwrap();
+
+        let err = trust(SignaturePolicy::Require, &key)
+            .verify(dir.path(), &manifest, &library)
+            .unwrap_err();
+        assert!(err.to_string().contains("not signed"));
+        trust(SignaturePolicy::Warn, &key).verify(dir.path(), &manifest, &library).unwrap();
+    }
+
+    #[test]
+    fn test_trust_config_persistence() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(TRUST_FILE);
+        assert_eq!(PluginTrustConfig::load_from(&path).unwrap().policy, SignaturePolicy::Require);
+
+        let key = generate_key();
+        trust(SignaturePolicy::Warn, &key).save_to(&path).unwrap();
+        let loaded = PluginTrustConfig::load_from(&path).unwrap();
+        assert_eq!(loaded.policy, SignaturePolicy::Warn);
+        assert_eq!(loaded.keys[0].verifying_key().unwrap(), key.verifying_key());
+    }
+}
@@ -0,0 +1,125 @@
+use std::any::Any;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use crate::plugin_api::base::{Plugin, PluginError, PluginConfig};
+use crate::plugin_api::settings::PluginSettings;
+use crate::plugin_api::transfer::{TransferCapabilities, TransferManifest, TransferPlugin, TransferSession};
+use crate::plugin_api::types::{PluginMetadata, PlatformSupport, PluginType};
+use crate::transfer::{TcpSender, TransferListener, TransferOptions};
+
+// The built-in transfer protocol, also used for bare `host:port` targets
+pub struct TcpTransferPlugin {
+    config: PluginConfig,
+    metadata: PluginMetadata,
+}
+
+impl TcpTransferPlugin {
+    pub fn new() -> Self {
+        TcpTransferPlugin {
+            config: PluginConfig {
+                name: String::from("TCP Transfer Plugin"),
+                description: String::from("Sends files over parallel TCP streams"),
+                version: String::from("1.0.0"),
+                plugin_type: PluginType::Transfer,
+            },
+            metadata: PluginMetadata {
+                name: String::from("TCP Transfer Plugin"),
+                description: String::from("Sends files over parallel TCP streams"),
+                version: String::from("1.0.0"),
+                author: String::from("Smart Transfer Team"),
+                platform_support: PlatformSupport {
+                    windows: true,
+                    linux: true,
+                    macos: true,
+                },
+                plugin_type: PluginType::Transfer,
+                core_version: None,
+                extensions: Vec::new(),
+                provides: Vec::new(),
+                requires: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Default for TcpTransferPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plugin for TcpTransferPlugin {
+    fn get_config(&self) -> &PluginConfig {
+        &self.config
+    }
+
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn initialize(&mut self, _settings: &PluginSettings) -> Result<(), PluginError> {
+        Ok(())
+    }
+
+    fn cleanup(&mut self) -> Result<(), PluginError> {
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_transfer(&self) -> Option<&dyn TransferPlugin> {
+        Some(self)
+    }
+}
+
+struct TcpSession(TcpSender);
+
+#[async_trait]
+impl TransferSession for TcpSession {
+    async fn send_manifest(&mut self, manifest: &TransferManifest) -> Result<(), PluginError> {
+        Ok(self.0.send_manifest(manifest).await?)
+    }
+
+    async fn send_data(&mut self, sources: &[PathBuf]) -> Result<(), PluginError> {
+        Ok(self.0.send_data(sources).await?)
+    }
+
+    async fn finish(self: Box<Self>) -> Result<(), PluginError> {
+        Ok(self.0.finish().await?)
+    }
+}
+
+#[async_trait]
+impl TransferPlugin for TcpTransferPlugin {
+    fn schemes(&self) -> Vec<String> {
+        vec![String::from("tcp")]
+    }
+
+    fn capabilities(&self) -> TransferCapabilities {
+        TransferCapabilities {
+            resumable: false,
+            encrypted: false,
+            parallel_streams: true,
+        }
+    }
+
+    async fn connect(
+        &self,
+        address: &str,
+        options: &TransferOptions,
+    ) -> Result<Box<dyn TransferSession>, PluginError> {
+        let sender = TcpSender::connect(address.to_string(), options).await?;
+        Ok(Box::new(TcpSession(sender)))
+    }
+
+    async fn receive(&self, address: &str, output_dir: &Path) -> Result<(), PluginError> {
+        let listener = TransferListener::bind(address).await?;
+        Ok(listener.accept_transfer(output_dir).await?)
+    }
+}
@@ -1,8 +1,9 @@
 use std::any::Any;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use crate::plugin_api::base::{Plugin, PluginError};
 use crate::plugin_api::types::{PluginMetadata, CompressionOptions};
 use crate::plugin_api::compression::CompressionPlugin;
+use crate::plugin_api::extract::ExtractionLimits;
 
 /// Template Plugin - Use this as a base for creating new plugins
 /// This template follows the core principles:
@@ -44,23 +45,24 @@ impl CompressionPlugin for TemplatePlugin {
     fn compress(
         &self,
         input_files: &[PathBuf],
-        output_file: &PathBuf,
+        output_file: &Path,
         options: &CompressionOptions,
     ) -> Result<(), PluginError> {
         // Implement your compression logic here
         // This is just a placeholder implementation
-        Err(PluginError::Other("Not implemented".to_string()))
+        Err(PluginError::NotImplemented)
     }
 
     fn decompress(
         &self,
-        input_file: &PathBuf,
-        output_dir: &PathBuf,
+        input_file: &Path,
+        output_dir: &Path,
         overwrite: bool,
+        limits: ExtractionLimits,
     ) -> Result<(), PluginError> {
         // Implement your decompression logic here
         // This is just a placeholder implementation
-        Err(PluginError::Other("Not implemented".to_string()))
+        Err(PluginError::NotImplemented)
     }
 }
 
@@ -95,6 +97,7 @@ mod tests {
             &PathBuf::from("test.zip"),
             &PathBuf::from("output"),
             true,
+            ExtractionLimits::default(),
         );
         assert!(result.is_err());
     }
@@ -0,0 +1,750 @@
+// Sandboxed plugins compiled to WebAssembly.
+//
+// A module only reaches the outside world through the imports linked below: WASI
+// preview1 with no preopened directories, environment or inherited stdio, plus the
+// `smart_transfer` host module. The latter gives read access to the job's input files
+// by index and write access to its output file or output directory, nothing else.
+// Every call runs in a fresh instance, so no state leaks from one job to the next. Each call
+// gets a fuel budget, topped up for every input byte read, so a guest that spins without
+// making progress fails instead of hanging the caller.
+//
+// Guest exports, all sizes and pointers are i32 offsets into the exported `memory`:
+//   st_abi_version() -> i32                   must return `WASM_ABI_VERSION`
+//   st_alloc(len) -> ptr                      buffer the host copies requests into
+//   st_metadata() -> packed                   JSON `PluginMetadata`
+//   st_compress(ptr, len) -> status           request is JSON `CompressionOptions`
+//   st_decompress(ptr, len) -> status         request is JSON `WasmDecompressRequest`
+// Optional exports:
+//   st_settings_schema() -> packed            JSON `SettingsSchema`
+//   st_configure(ptr, len) -> status          JSON `PluginSettings`, called on every new
+//                                             instance before the job runs
+// `packed` is `ptr << 32 | len`; a `status` of 0 means success, anything else is a
+// packed UTF-8 error message.
+//
+// Host imports from `smart_transfer`; any misuse traps and fails the job:
+//   input_count() -> i32
+//   input_size(index) -> i64
+//   input_name(index, buf, cap) -> i32        file name only, returns its full length
+//   read_input(index, offset: i64, buf, len) -> i32   bytes read, 0 at end of file
+//   create_output(name, name_len) -> handle   compress: name is ignored, one output only;
+//                                             decompress: relative path in the output dir
+//   write_output(handle, buf, len) -> i32
+//   log(ptr, len)
+
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Serialize, Deserialize};
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+use wasmtime_wasi::preview1::{self, WasiP1Ctx};
+use wasmtime_wasi::WasiCtxBuilder;
+use crate::plugin_api::base::{Plugin, PluginConfig, PluginError};
+use crate::plugin_api::compression::CompressionPlugin;
+use crate::plugin_api::extract::{ExtractionGuard, ExtractionLimits};
+use crate::plugin_api::settings::{PluginSettings, SettingsSchema};
+use crate::plugin_api::types::{CompressionOptions, PluginMetadata, PluginType};
+use crate::plugins::manifest::PluginManifest;
+
+pub const WASM_ABI_VERSION: i32 = 1;
+pub const HOST_MODULE: &str = "smart_transfer";
+
+const MAX_MEMORY: usize = 512 * 1024 * 1024;
+const MAX_IO_CHUNK: usize = 4 * 1024 * 1024;
+// Roughly one unit per guest instruction
+pub const DEFAULT_FUEL_PER_CALL: u64 = 1_000_000_000;
+const FUEL_PER_INPUT_BYTE: u64 = 1_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WasmDecompressRequest {
+    pub overwrite: bool,
+}
+
+enum JobOutput {
+    None,
+    File(PathBuf),
+    // Guest writes are checked and counted like any other extraction
+    Directory(ExtractionGuard),
+}
+
+// The only files a guest can touch during one call
+struct JobFiles {
+    inputs: Vec<PathBuf>,
+    open_inputs: Vec<Option<File>>,
+    output: JobOutput,
+    outputs: Vec<File>,
+}
+
+impl JobFiles {
+    fn new(inputs: Vec<PathBuf>, output: JobOutput) -> Self {
+        let open_inputs = inputs.iter().map(|_| None).collect();
+        Self {
+            inputs,
+            open_inputs,
+            output,
+            outputs: Vec::new(),
+        }
+    }
+
+    fn input_path(&self, index: i32) -> Result<&PathBuf> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.inputs.get(i))
+            .ok_or_else(|| anyhow!("Plugin asked for unknown input {}", index))
+    }
+
+    fn input(&mut self, index: i32) -> Result<&mut File> {
+        let path = self.input_path(index)?.clone();
+        let slot = &mut self.open_inputs[index as usize];
+        if slot.is_none() {
+            *slot = Some(File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?);
+        }
+        Ok(slot.as_mut().expect("input opened above"))
+    }
+
+    fn create_output(&mut self, name: &str) -> Result<i32> {
+        let file = match &mut self.output {
+            JobOutput::None => bail!("Plugin tried to write a file outside of a job"),
+            JobOutput::File(path) => {
+                if !self.outputs.is_empty() {
+                    bail!("Plugin tried to create more than one output file");
+                }
+                File::create(&*path).with_context(|| format!("Failed to create {}", path.display()))?
+            }
+            JobOutput::Directory(guard) => guard.create_file(name)?.1,
+        };
+        self.outputs.push(file);
+        Ok(self.outputs.len() as i32 - 1)
+    }
+
+    fn write_output(&mut self, handle: i32, bytes: &[u8]) -> Result<()> {
+        if let JobOutput::Directory(guard) = &mut self.output {
+            guard.count(bytes.len() as u64)?;
+        }
+        self.output(handle)?.write_all(bytes)?;
+        Ok(())
+    }
+
+    fn output(&mut self, handle: i32) -> Result<&mut File> {
+        usize::try_from(handle)
+            .ok()
+            .and_then(|i| self.outputs.get_mut(i))
+            .ok_or_else(|| anyhow!("Plugin used unknown output handle {}", handle))
+    }
+}
+
+struct HostState {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    job: JobFiles,
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => bail!("Plugin does not export its memory"),
+    }
+}
+
+fn guest_range(ptr: i32, len: i32) -> Result<(usize, usize)> {
+    match (usize::try_from(ptr), usize::try_from(len)) {
+        (Ok(ptr), Ok(len)) => Ok((ptr, len)),
+        _ => bail!("Plugin passed an invalid buffer"),
+    }
+}
+
+fn read_guest(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let (ptr, len) = guest_range(ptr, len)?;
+    let memory = guest_memory(caller)?;
+    let mut buffer = vec![0; len];
+    memory
+        .read(&*caller, ptr, &mut buffer)
+        .map_err(|_| anyhow!("Plugin passed an out-of-bounds buffer"))?;
+    Ok(buffer)
+}
+
+fn write_guest(caller: &mut Caller<'_, HostState>, ptr: i32, bytes: &[u8]) -> Result<()> {
+    let (ptr, _) = guest_range(ptr, 0)?;
+    let memory = guest_memory(caller)?;
+    memory
+        .write(&mut *caller, ptr, bytes)
+        .map_err(|_| anyhow!("Plugin passed an out-of-bounds buffer"))
+}
+
+fn add_host_functions(linker: &mut Linker<HostState>) -> Result<()> {
+    linker.func_wrap(HOST_MODULE, "input_count", |caller: Caller<'_, HostState>| -> i32 {
+        caller.data().job.inputs.len() as i32
+    })?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "input_size",
+        |caller: Caller<'_, HostState>, index: i32| -> Result<i64> {
+            let path = caller.data().job.input_path(index)?;
+            Ok(fs::metadata(path)?.len() as i64)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "input_name",
+        |mut caller: Caller<'_, HostState>, index: i32, buf: i32, cap: i32| -> Result<i32> {
+            let path = caller.data().job.input_path(index)?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let (_, cap) = guest_range(0, cap)?;
+            let bytes = name.as_bytes();
+            write_guest(&mut caller, buf, &bytes[..bytes.len().min(cap)])?;
+            Ok(bytes.len() as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "read_input",
+        |mut caller: Caller<'_, HostState>, index: i32, offset: i64, buf: i32, len: i32| -> Result<i32> {
+            let (_, len) = guest_range(0, len)?;
+            let offset = u64::try_from(offset).map_err(|_| anyhow!("Plugin passed a negative offset"))?;
+            let mut buffer = vec![0; len.min(MAX_IO_CHUNK)];
+            let file = caller.data_mut().job.input(index)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let read = file.read(&mut buffer)?;
+            write_guest(&mut caller, buf, &buffer[..read])?;
+            let fuel = caller.get_fuel()?;
+            caller.set_fuel(fuel.saturating_add(read as u64 * FUEL_PER_INPUT_BYTE))?;
+            Ok(read as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "create_output",
+        |mut caller: Caller<'_, HostState>, name: i32, name_len: i32| -> Result<i32> {
+            let name = String::from_utf8(read_guest(&mut caller, name, name_len)?)?;
+            caller.data_mut().job.create_output(&name)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "write_output",
+        |mut caller: Caller<'_, HostState>, handle: i32, buf: i32, len: i32| -> Result<i32> {
+            let bytes = read_guest(&mut caller, buf, len)?;
+            caller.data_mut().job.write_output(handle, &bytes)?;
+            Ok(len)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+            let message = read_guest(&mut caller, ptr, len)?;
+            log::info!("[wasm plugin] {}", String::from_utf8_lossy(&message));
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+    (((packed as u64) >> 32) as usize, (packed as u64 & 0xffff_ffff) as usize)
+}
+
+fn read_packed(store: &mut Store<HostState>, memory: &Memory, packed: i64) -> Result<Vec<u8>> {
+    let (ptr, len) = unpack(packed);
+    let mut buffer = vec![0; len];
+    memory
+        .read(&*store, ptr, &mut buffer)
+        .map_err(|_| anyhow!("Plugin returned an out-of-bounds buffer"))?;
+    Ok(buffer)
+}
+
+// Copies `request` into a guest buffer and calls `export(ptr, len) -> status`. Returns the
+// guest's error message if the status is not 0.
+fn call_with_request(
+    store: &mut Store<HostState>,
+    instance: &Instance,
+    memory: &Memory,
+    export: &str,
+    request: &[u8],
+) -> Result<Option<String>> {
+    let len = i32::try_from(request.len())?;
+    let ptr = instance
+        .get_typed_func::<i32, i32>(&mut *store, "st_alloc")?
+        .call(&mut *store, len)?;
+    memory
+        .write(&mut *store, guest_range(ptr, 0)?.0, request)
+        .map_err(|_| anyhow!("Plugin allocated an out-of-bounds buffer"))?;
+
+    let status = instance
+        .get_typed_func::<(i32, i32), i64>(&mut *store, export)?
+        .call(&mut *store, (ptr, len))?;
+    if status == 0 {
+        return Ok(None);
+    }
+    let message = read_packed(store, memory, status)?;
+    Ok(Some(String::from_utf8_lossy(&message).into_owned()))
+}
+
+pub struct WasmPlugin {
+    engine: Engine,
+    module: Module,
+    linker: Linker<HostState>,
+    config: PluginConfig,
+    metadata: PluginMetadata,
+    path: PathBuf,
+    has_compression: bool,
+    settings_schema: SettingsSchema,
+    // JSON `PluginSettings` from `initialize`, replayed into every instance
+    settings: Vec<u8>,
+    fuel_per_call: u64,
+}
+
+impl WasmPlugin {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_bytes(path, &bytes)
+    }
+
+    // Compiles the module from `bytes`; `path` is only kept for messages
+    pub fn from_bytes(path: PathBuf, bytes: &[u8]) -> Result<Self> {
+        let engine = Engine::new(Config::new().consume_fuel(true))?;
+        let module = Module::new(&engine, bytes)
+            .with_context(|| format!("Failed to compile {}", path.display()))?;
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |state: &mut HostState| &mut state.wasi)?;
+        add_host_functions(&mut linker)?;
+
+        let has_compression = module.get_export("st_compress").is_some()
+            && module.get_export("st_decompress").is_some();
+        let mut plugin = Self {
+            engine,
+            module,
+            linker,
+            config: PluginConfig {
+                name: String::new(),
+                description: String::new(),
+                version: String::new(),
+                plugin_type: PluginType::Compression,
+            },
+            metadata: PluginMetadata {
+                name: String::new(),
+                version: String::new(),
+                author: String::new(),
+                description: String::new(),
+                plugin_type: PluginType::Compression,
+                platform_support: crate::plugin_api::types::PlatformSupport {
+                    windows: true,
+                    linux: true,
+                    macos: true,
+                },
+                core_version: None,
+                extensions: Vec::new(),
+                provides: Vec::new(),
+                requires: Vec::new(),
+            },
+            path,
+            has_compression,
+            settings_schema: SettingsSchema::default(),
+            settings: b"{}".to_vec(),
+            fuel_per_call: DEFAULT_FUEL_PER_CALL,
+        };
+        plugin.metadata = plugin.read_metadata()?;
+        plugin.settings_schema = plugin.read_settings_schema()?;
+        plugin.sync_config();
+        Ok(plugin)
+    }
+
+    pub fn load_with_manifest(
+        path: PathBuf,
+        manifest: PluginManifest,
+        verify: &dyn Fn(&[u8]) -> Result<()>,
+    ) -> Result<Self> {
+        let bytes = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        verify(&bytes)?;
+        let mut plugin = Self::from_bytes(path, &bytes)?;
+        if matches!(manifest.plugin_type(), PluginType::Compression) && !plugin.has_compression {
+            bail!(
+                "Plugin '{}' is declared as a compression plugin but {} does not export st_compress and st_decompress",
+                manifest.name,
+                plugin.path.display()
+            );
+        }
+        manifest.apply_to(&mut plugin.metadata);
+        plugin.sync_config();
+        Ok(plugin)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn supports_compression(&self) -> bool {
+        self.has_compression
+    }
+
+    // Budget each call starts with, before the top-ups for input read
+    pub fn set_fuel_per_call(&mut self, fuel: u64) {
+        self.fuel_per_call = fuel;
+    }
+
+    fn sync_config(&mut self) {
+        self.config = PluginConfig {
+            name: self.metadata.name.clone(),
+            description: self.metadata.description.clone(),
+            version: self.metadata.version.clone(),
+            plugin_type: self.metadata.plugin_type.clone(),
+        };
+    }
+
+    fn instantiate(&self, job: JobFiles) -> Result<(Store<HostState>, Instance, Memory)> {
+        let state = HostState {
+            wasi: WasiCtxBuilder::new().build_p1(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).instances(1).build(),
+            job,
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel_per_call)?;
+        let instance = self.linker.instantiate(&mut store, &self.module)?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&mut store, "st_abi_version")?
+            .call(&mut store, ())?;
+        if version != WASM_ABI_VERSION {
+            bail!("Plugin uses ABI version {}, expected {}", version, WASM_ABI_VERSION);
+        }
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("Plugin does not export its memory"))?;
+
+        if instance.get_export(&mut store, "st_configure").is_some() {
+            if let Some(message) = call_with_request(&mut store, &instance, &memory, "st_configure", &self.settings)? {
+                bail!("Plugin rejected its settings: {}", message);
+            }
+        }
+        Ok((store, instance, memory))
+    }
+
+    fn read_metadata(&self) -> Result<PluginMetadata> {
+        let (mut store, instance, memory) = self.instantiate(JobFiles::new(Vec::new(), JobOutput::None))?;
+        let packed = instance
+            .get_typed_func::<(), i64>(&mut store, "st_metadata")?
+            .call(&mut store, ())?;
+        let json = read_packed(&mut store, &memory, packed)?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Plugin {} returned invalid metadata", self.path.display()))
+    }
+
+    fn read_settings_schema(&self) -> Result<SettingsSchema> {
+        if self.module.get_export("st_settings_schema").is_none() {
+            return Ok(SettingsSchema::default());
+        }
+        let (mut store, instance, memory) = self.instantiate(JobFiles::new(Vec::new(), JobOutput::None))?;
+        let packed = instance
+            .get_typed_func::<(), i64>(&mut store, "st_settings_schema")?
+            .call(&mut store, ())?;
+        let json = read_packed(&mut store, &memory, packed)?;
+        serde_json::from_slice(&json)
+            .with_context(|| format!("Plugin {} returned an invalid settings schema", self.path.display()))
+    }
+
+    fn run_job(&self, export: &str, request: &[u8], job: JobFiles) -> Result<(), PluginError> {
+        if !self.has_compression {
+            return Err(PluginError::NotImplemented);
+        }
+        let result = (|| -> Result<Option<String>> {
+            let (mut store, instance, memory) = self.instantiate(job)?;
+            call_with_request(&mut store, &instance, &memory, export, request)
+        })();
+
+        match result {
+            Ok(None) => Ok(()),
+            Ok(Some(message)) => Err(PluginError::decode(message)),
+            Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) => Err(PluginError::with_source(
+                format!("Plugin {} ran out of its execution budget", self.path.display()),
+                e,
+            )),
+            Err(e) => Err(PluginError::from(e)),
+        }
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn get_config(&self) -> &PluginConfig {
+        &self.config
+    }
+
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn settings_schema(&self) -> SettingsSchema {
+        self.settings_schema.clone()
+    }
+
+    // Instances only live for the duration of a call, so the settings are kept and handed to
+    // each of them through `st_configure`
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError> {
+        self.settings = serde_json::to_vec(settings)
+            .map_err(|e| PluginError::with_source("Failed to encode the plugin settings", e))?;
+        Ok(())
+    }
+
+    fn cleanup(&mut self) -> Result<(), PluginError> {
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        if self.has_compression {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl CompressionPlugin for WasmPlugin {
+    fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let request = serde_json::to_vec(options)
+            .map_err(|e| PluginError::with_source("Failed to encode the plugin request", e))?;
+        let job = JobFiles::new(input_files.to_vec(), JobOutput::File(output_file.to_path_buf()));
+        let result = self.run_job("st_compress", &request, job);
+        if result.is_err() {
+            let _ = fs::remove_file(output_file);
+        }
+        result
+    }
+
+    fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        let request = serde_json::to_vec(&WasmDecompressRequest { overwrite })
+            .map_err(|e| PluginError::with_source("Failed to encode the plugin request", e))?;
+        let guard = ExtractionGuard::new(output_dir, overwrite, limits)?.for_archive(archive_file);
+        let job = JobFiles::new(vec![archive_file.to_path_buf()], JobOutput::Directory(guard));
+        self.run_job("st_decompress", &request, job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::plugin_api::error::ErrorCode;
+    use crate::plugins::signature::{PluginTrustConfig, SignaturePolicy};
+    use crate::plugins::PluginLoader;
+    use tempfile::tempdir;
+
+    const METADATA: &str = r#"{"name":"Wasm Store","version":"0.1.0","author":"Tests","description":"Stores a single file unchanged","plugin_type":"Compression","platform_support":{"windows":true,"linux":true,"macos":true}}"#;
+
+    const SCHEMA: &str = r#"{"settings":[{"key":"fail","label":"Fail","type":"boolean","default":false}]}"#;
+
+    // Copies input 0 to the output; decompress writes it to `entry_name` in the output directory.
+    // `st_configure` rejects `{"fail":true}`, the only 13 byte settings object it can receive.
+    fn store_plugin_wat(entry_name: &str) -> String {
+        format!(
+            r#"(module
+  (import "smart_transfer" "input_count" (func $input_count (result i32)))
+  (import "smart_transfer" "read_input" (func $read_input (param i32 i64 i32 i32) (result i32)))
+  (import "smart_transfer" "create_output" (func $create_output (param i32 i32) (result i32)))
+  (import "smart_transfer" "write_output" (func $write_output (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 3)
+  (global $heap (mut i32) (i32.const 8192))
+  (data (i32.const 0) "{metadata}")
+  (data (i32.const 4096) "{entry}")
+  (data (i32.const 6144) "no input files")
+  (data (i32.const 6400) "{schema}")
+  (data (i32.const 7168) "fail is set")
+  (func (export "st_abi_version") (result i32) (i32.const 1))
+  (func (export "st_alloc") (param $len i32) (result i32) (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+    (local.get $ptr))
+  (func (export "st_metadata") (result i64) (i64.const {metadata_len}))
+  (func (export "st_settings_schema") (result i64)
+    (i64.or (i64.shl (i64.const 6400) (i64.const 32)) (i64.const {schema_len})))
+  (func (export "st_configure") (param i32 i32) (result i64)
+    (if (i32.eq (local.get 1) (i32.const 13))
+      (then (return (i64.or (i64.shl (i64.const 7168) (i64.const 32)) (i64.const 11)))))
+    (i64.const 0))
+  (func $copy (param $out i32) (local $offset i64) (local $read i32)
+    (block $done
+      (loop $next
+        (local.set $read (call $read_input (i32.const 0) (local.get $offset) (i32.const 65536) (i32.const 65536)))
+        (br_if $done (i32.eqz (local.get $read)))
+        (drop (call $write_output (local.get $out) (i32.const 65536) (local.get $read)))
+        (local.set $offset (i64.add (local.get $offset) (i64.extend_i32_u (local.get $read))))
+        (br $next))))
+  (func (export "st_compress") (param i32 i32) (result i64)
+    (if (i32.eqz (call $input_count))
+      (then (return (i64.or (i64.shl (i64.const 6144) (i64.const 32)) (i64.const 14)))))
+    (call $copy (call $create_output (i32.const 0) (i32.const 0)))
+    (i64.const 0))
+  (func (export "st_decompress") (param i32 i32) (result i64)
+    (call $copy (call $create_output (i32.const 4096) (i32.const {entry_len})))
+    (i64.const 0)))"#,
+            metadata = METADATA.replace('"', "\\\""),
+            metadata_len = METADATA.len(),
+            schema = SCHEMA.replace('"', "\\\""),
+            schema_len = SCHEMA.len(),
+            entry = entry_name,
+            entry_len = entry_name.len(),
+        )
+    }
+
+    fn write_plugin(dir: &Path, entry_name: &str) -> PathBuf {
+        let path = dir.join("store.wat");
+        fs::write(&path, store_plugin_wat(entry_name)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_wasm_plugin_roundtrip() {
+        let dir = tempdir().unwrap();
+        let plugin = WasmPlugin::load(write_plugin(dir.path(), "nested/restored.txt")).unwrap();
+        assert_eq!(plugin.metadata().name, "Wasm Store");
+        assert!(plugin.supports_compression());
+
+        let input = dir.path().join("input.txt");
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&input, &data).unwrap();
+        let archive = dir.path().join("input.store");
+        plugin.compress(&[input], &archive, &CompressionOptions::default()).unwrap();
+        assert_eq!(fs::read(&archive).unwrap(), data);
+
+        let output = dir.path().join("out");
+        plugin.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read(output.join("nested").join("restored.txt")).unwrap(), data);
+
+        let err = plugin.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap_err();
+        assert!(err.to_string().contains("Already exists"));
+        plugin.decompress(&archive, &output, true, ExtractionLimits::default()).unwrap();
+    }
+
+    #[test]
+    fn test_wasm_plugin_from_manifest() {
+        let dir = tempdir().unwrap();
+        write_plugin(dir.path(), "restored.txt");
+        let manifest = dir.path().join("plugin.toml");
+        fs::write(
+            &manifest,
+            "name = \"Sandboxed\"\nversion = \"1.2.0\"\ncore_version = \">=0.1.0\"\ntype = \"compression\"\nruntime = \"wasm\"\nlibrary = \"store.wat\"\n",
+        )
+        .unwrap();
+
+        let trust = PluginTrustConfig {
+            policy: SignaturePolicy::Warn,
+            keys: Vec::new(),
+        };
+        let external = PluginLoader::load_from_manifest(&manifest, &trust).unwrap();
+        assert_eq!(external.plugin.get_config().name, "Sandboxed");
+        assert_eq!(external.plugin.metadata().author, "Tests");
+        assert!(external.plugin.as_any().downcast_ref::<WasmPlugin>().is_some());
+    }
+
+    #[test]
+    fn test_wasm_plugin_settings() {
+        let dir = tempdir().unwrap();
+        let mut plugin = WasmPlugin::load(write_plugin(dir.path(), "restored.txt")).unwrap();
+        let schema = plugin.settings_schema();
+        assert_eq!(schema.defaults()["fail"], serde_json::json!(false));
+
+        let input = dir.path().join("input.txt");
+        fs::write(&input, b"payload").unwrap();
+        let archive = dir.path().join("input.store");
+        let mut settings = PluginSettings::new();
+        settings.insert("fail".to_string(), serde_json::json!(true));
+        plugin.initialize(&schema.resolve(&settings).unwrap()).unwrap();
+        let err = plugin.compress(std::slice::from_ref(&input), &archive, &CompressionOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Plugin rejected its settings: fail is set"));
+
+        plugin.initialize(&schema.defaults()).unwrap();
+        plugin.compress(&[input], &archive, &CompressionOptions::default()).unwrap();
+        assert_eq!(fs::read(&archive).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_wasm_plugin_reports_errors() {
+        let dir = tempdir().unwrap();
+        let plugin = WasmPlugin::load(write_plugin(dir.path(), "restored.txt")).unwrap();
+        let archive = dir.path().join("empty.store");
+        let err = plugin.compress(&[], &archive, &CompressionOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("no input files"));
+        assert!(!archive.exists());
+    }
+
+    #[test]
+    fn test_wasm_plugin_runs_out_of_fuel() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("spin.wat");
+        let spinning = store_plugin_wat("spin.txt").replace(
+            r#"(func (export "st_decompress") (param i32 i32) (result i64)"#,
+            r#"(func (export "st_decompress") (param i32 i32) (result i64) (loop $spin (br $spin))"#,
+        );
+        fs::write(&path, spinning).unwrap();
+        let mut plugin = WasmPlugin::load(path).unwrap();
+        plugin.set_fuel_per_call(10_000_000);
+
+        // Jobs that finish within the budget are unaffected
+        let input = dir.path().join("input.txt");
+        fs::write(&input, vec![7; 1024 * 1024]).unwrap();
+        let archive = dir.path().join("input.store");
+        plugin.compress(&[input], &archive, &CompressionOptions::default()).unwrap();
+
+        let err = plugin
+            .decompress(&archive, &dir.path().join("out"), true, ExtractionLimits::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("execution budget"), "{}", err);
+    }
+
+    #[test]
+    fn test_wasm_plugin_cannot_escape_output_dir() {
+        let dir = tempdir().unwrap();
+        let plugin = WasmPlugin::load(write_plugin(dir.path(), "../escape.txt")).unwrap();
+        let archive = dir.path().join("archive.store");
+        fs::write(&archive, b"payload").unwrap();
+
+        let output = dir.path().join("out");
+        let err = plugin.decompress(&archive, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::PathTraversal);
+        assert!(err.to_string().contains("outside the output directory"));
+        assert!(!dir.path().join("escape.txt").exists());
+    }
+
+    #[test]
+    fn test_wasm_plugin_output_counts_against_limits() {
+        let dir = tempdir().unwrap();
+        let plugin = WasmPlugin::load(write_plugin(dir.path(), "restored.txt")).unwrap();
+        let archive = dir.path().join("archive.store");
+        fs::write(&archive, b"payload").unwrap();
+
+        let limits = ExtractionLimits {
+            max_total_size: Some(4),
+            ..ExtractionLimits::unlimited()
+        };
+        let err = plugin.decompress(&archive, &dir.path().join("out"), true, limits).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+    }
+}
@@ -1,18 +1,33 @@
 use std::any::Any;
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::SystemTime;
 use anyhow::Result;
 use crate::plugin_api::base::{Plugin, PluginError, PluginConfig};
-use crate::plugin_api::compression::CompressionPlugin;
-use crate::plugin_api::types::{PluginMetadata, PlatformSupport, PluginType, CompressionOptions};
+use crate::plugin_api::compression::{
+    is_removed, persist_replacement, replacement_file, CompressionPlugin, EntryInfo, EntryKind, EntryMetadata,
+    StreamEntry, WriteSeek,
+};
+use crate::plugin_api::extract::{extract_entries, ExtractionGuard, ExtractionLimits};
+use chrono::{Datelike, TimeZone, Timelike};
+use crate::plugin_api::settings::{PluginSettings, SettingDescriptor, SettingKind, SettingsSchema};
+use crate::plugin_api::types::{Capability, PluginMetadata, PlatformSupport, PluginType, CompressionOptions, CompressionMode};
 use zip::write::FileOptions;
+use zip::read::{read_zipfile_from_stream, ZipFile};
+use zip::result::ZipError;
 use zip::ZipArchive;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+const DEFAULT_LEVEL: i64 = 6;
 
 pub struct ZipPlugin {
     config: PluginConfig,
     metadata: PluginMetadata,
+    // Deflate level used for `CompressionMode::Normal`
+    level: i64,
 }
 
 impl ZipPlugin {
@@ -35,7 +50,20 @@ impl ZipPlugin {
                     macos: true,
                 },
                 plugin_type: PluginType::Compression,
+                core_version: None,
+                extensions: vec![String::from("zip")],
+                provides: vec![Capability::Format(String::from("zip"))],
+                requires: Vec::new(),
             },
+            level: DEFAULT_LEVEL,
+        }
+    }
+
+    fn level_for(&self, mode: &CompressionMode) -> i32 {
+        match mode {
+            CompressionMode::Fast => 1,
+            CompressionMode::Normal => self.level as i32,
+            CompressionMode::Best => 9,
         }
     }
 }
@@ -49,7 +77,20 @@ impl Plugin for ZipPlugin {
         self.metadata.clone()
     }
 
-    fn initialize(&mut self) -> Result<(), PluginError> {
+    fn settings_schema(&self) -> SettingsSchema {
+        SettingsSchema {
+            settings: vec![SettingDescriptor {
+                key: String::from("level"),
+                label: String::from("Default compression level"),
+                description: String::from("Deflate level for normal compression, 0 stores files uncompressed"),
+                kind: SettingKind::Integer { min: Some(0), max: Some(9) },
+                default: DEFAULT_LEVEL.into(),
+            }],
+        }
+    }
+
+    fn initialize(&mut self, settings: &PluginSettings) -> Result<(), PluginError> {
+        self.level = settings.get("level").and_then(|level| level.as_i64()).unwrap_or(DEFAULT_LEVEL);
         Ok(())
     }
 
@@ -64,71 +105,355 @@ impl Plugin for ZipPlugin {
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        Some(self)
+    }
 }
 
 impl CompressionPlugin for ZipPlugin {
     fn compress(
         &self,
         input_files: &[PathBuf],
-        output_file: &PathBuf,
-        _options: &CompressionOptions,
+        output_file: &Path,
+        options: &CompressionOptions,
     ) -> Result<(), PluginError> {
         // Create output file
-        let file = File::create(output_file)?;
-        let mut zip = zip::ZipWriter::new(file);
-        let options = FileOptions::default();
-
-        // Process each input file
-        for input_file in input_files {
-            let mut file = File::open(input_file)?;
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)?;
-
+        let file = File::create(output_file).map_err(|e| PluginError::io(output_file, e))?;
+        let mut entries = input_files.iter().map(|input_file| {
             let name = input_file
                 .file_name()
                 .ok_or_else(|| PluginError::InvalidInput("Invalid file name".to_string()))?
-                .to_string_lossy();
+                .to_string_lossy()
+                .into_owned();
+            let file = File::open(input_file).map_err(|e| PluginError::io(input_file, e))?;
+            Ok(StreamEntry::new(name, file))
+        });
+        self.write_zip(&mut entries, file, options, Some(output_file))?;
+        Ok(())
+    }
 
-            zip.start_file(name.as_ref(), options).map_err(|e| PluginError::Other(e.to_string()))?;
-            zip.write_all(&buffer).map_err(|e| PluginError::Other(e.to_string()))?;
+    fn decompress(
+        &self,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        extract_entries(self, archive_file, output_dir, overwrite, limits)
+    }
+
+    // The writer seeks back to fill in each entry's header, so the archive is assembled in
+    // memory before it goes out
+    fn compress_stream(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        output: &mut dyn Write,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let archive = self.write_zip(entries, Cursor::new(Vec::new()), options, None)?;
+        output.write_all(archive.get_ref())?;
+        output.flush()?;
+        Ok(())
+    }
+
+    fn compress_seekable(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        output: &mut dyn WriteSeek,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        self.write_zip(entries, &mut *output, options, None)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    fn read_entries(
+        &self,
+        archive_file: &Path,
+        visit: &mut dyn FnMut(EntryInfo, &mut dyn Read) -> Result<(), PluginError>,
+    ) -> Result<(), PluginError> {
+        let file = File::open(archive_file).map_err(|e| PluginError::io(archive_file, e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| zip_error(Some(archive_file), e))?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(|e| zip_error(Some(archive_file), e))?;
+            let info = entry_info(&mut file)?;
+            visit(info, &mut file)?;
         }
+        Ok(())
+    }
 
-        // Finish writing zip file
-        zip.finish().map_err(|e| PluginError::Other(e.to_string()))?;
+    // Pure additions are appended in place; replacing or deleting entries rewrites the archive
+    fn update(
+        &self,
+        archive_file: &Path,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        remove: &[String],
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        // Every new entry is opened before the archive is touched
+        let entries = entries.collect::<Result<Vec<_>, _>>()?;
+        let file = File::open(archive_file).map_err(|e| PluginError::io(archive_file, e))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| zip_error(Some(archive_file), e))?;
+        let mut existing = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            existing.push(archive.by_index_raw(i).map_err(|e| zip_error(Some(archive_file), e))?.name().to_string());
+        }
+        if let Some(missing) = remove.iter().find(|name| !existing.iter().any(|entry| is_removed(entry, name))) {
+            return Err(PluginError::NotFound(missing.clone()));
+        }
+
+        let replaced: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+        let keep = |name: &str| {
+            !remove.iter().any(|removed| is_removed(name, removed))
+                && !replaced.iter().any(|new| new.trim_end_matches('/') == name.trim_end_matches('/'))
+        };
+        if existing.iter().all(|name| keep(name)) {
+            drop(archive);
+            return self.append(archive_file, entries, options);
+        }
+        self.rewrite(archive_file, &mut archive, entries, keep, options)
+    }
+
+    // Reads the local headers in order, without the central directory at the end
+    fn decompress_stream(
+        &self,
+        input: &mut dyn Read,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        let mut guard = ExtractionGuard::new(output_dir, overwrite, limits)?;
+        let consumed = Rc::new(Cell::new(0));
+        let mut input = CountingReader { inner: input, count: Rc::clone(&consumed) };
+        while let Some(mut file) = read_zipfile_from_stream(&mut input).map_err(|e| zip_error(None, e))? {
+            // The entry's data has not been read yet, but its compressed size is known
+            guard.set_compressed_size(consumed.get() + file.compressed_size());
+            let info = entry_info(&mut file)?;
+            guard.extract(info, &mut file)?;
+        }
         Ok(())
     }
+}
+
+impl ZipPlugin {
+    fn write_zip<W: Write + Seek>(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        writer: W,
+        options: &CompressionOptions,
+        archive: Option<&Path>,
+    ) -> Result<W, PluginError> {
+        let mut zip = zip::ZipWriter::new(writer);
+        for entry in entries {
+            self.write_entry(&mut zip, entry?, options, archive)?;
+        }
+        zip.finish().map_err(|e| zip_error(archive, e))
+    }
 
-    fn decompress(
+    fn write_entry<W: Write + Seek>(
         &self,
-        archive_file: &PathBuf,
-        output_dir: &PathBuf,
-        _overwrite: bool,
+        zip: &mut zip::ZipWriter<W>,
+        mut entry: StreamEntry<'_>,
+        options: &CompressionOptions,
+        archive: Option<&Path>,
     ) -> Result<(), PluginError> {
-        let file = File::open(archive_file)?;
-        let mut archive = ZipArchive::new(file).map_err(|e| PluginError::Other(e.to_string()))?;
+        let defaults = FileOptions::default().compression_level(Some(self.level_for(&options.mode)));
+        let options = entry_options(defaults, &entry.metadata);
+        match entry.kind {
+            EntryKind::File => {
+                zip.start_file(entry.name.as_str(), options).map_err(|e| zip_error(archive, e))?;
+                std::io::copy(&mut entry.reader, zip)?;
+            }
+            EntryKind::Directory => {
+                zip.add_directory(entry.name.as_str(), options).map_err(|e| zip_error(archive, e))?
+            }
+            EntryKind::Symlink { target } => {
+                zip.add_symlink(entry.name.as_str(), target, options).map_err(|e| zip_error(archive, e))?
+            }
+        }
+        Ok(())
+    }
 
+    // New entries go after the existing ones, which are not touched
+    fn append(&self, archive_file: &Path, entries: Vec<StreamEntry<'_>>, options: &CompressionOptions) -> Result<(), PluginError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(archive_file)
+            .map_err(|e| PluginError::io(archive_file, e))?;
+        let len = file.metadata().map_err(|e| PluginError::io(archive_file, e))?.len();
+        let mut saved = None;
+        let result = (|| {
+            let guard = AppendGuard { file: &mut file, saved: &mut saved };
+            let mut zip = zip::ZipWriter::new_append(guard).map_err(|e| zip_error(Some(archive_file), e))?;
+            for entry in entries {
+                self.write_entry(&mut zip, entry, options, Some(archive_file))?;
+            }
+            zip.finish().map_err(|e| zip_error(Some(archive_file), e))?;
+            Ok(())
+        })();
+        if let (Err(_), Some((offset, directory))) = (&result, saved) {
+            restore(&mut file, offset, &directory, len).map_err(|e| PluginError::io(archive_file, e))?;
+        }
+        result
+    }
+
+    // Untouched entries are copied without recompressing them, into a new file that only
+    // replaces the archive once it is complete
+    fn rewrite(
+        &self,
+        archive_file: &Path,
+        archive: &mut ZipArchive<File>,
+        entries: Vec<StreamEntry<'_>>,
+        keep: impl Fn(&str) -> bool,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let mut zip = zip::ZipWriter::new(replacement_file(archive_file)?);
         for i in 0..archive.len() {
-            let mut file = archive.by_index(i).map_err(|e| PluginError::Other(e.to_string()))?;
-            let outpath = match file.enclosed_name() {
-                Some(path) => output_dir.join(path),
-                None => continue,
-            };
-
-            if let Some(p) = outpath.parent() {
-                std::fs::create_dir_all(p)?;
+            let file = archive.by_index_raw(i).map_err(|e| zip_error(Some(archive_file), e))?;
+            if keep(file.name()) {
+                zip.raw_copy_file(file).map_err(|e| zip_error(Some(archive_file), e))?;
             }
+        }
+        for entry in entries {
+            self.write_entry(&mut zip, entry, options, Some(archive_file))?;
+        }
+        let temp = zip.finish().map_err(|e| zip_error(Some(archive_file), e))?;
+        persist_replacement(temp, archive_file)
+    }
+}
+
+// The archive file under an in-place append. Before the first write it saves everything
+// from there on, which is the central directory, so a failed append can be undone.
+struct AppendGuard<'a> {
+    file: &'a mut File,
+    saved: &'a mut Option<(u64, Vec<u8>)>,
+}
+
+impl Read for AppendGuard<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
 
-            let mut outfile = File::create(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
+impl Seek for AppendGuard<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Write for AppendGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.saved.is_none() {
+            let offset = self.file.stream_position()?;
+            let mut directory = Vec::new();
+            self.file.read_to_end(&mut directory)?;
+            self.file.seek(SeekFrom::Start(offset))?;
+            *self.saved = Some((offset, directory));
         }
+        self.file.write(buf)
+    }
 
-        Ok(())
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn restore(file: &mut File, offset: u64, directory: &[u8], len: u64) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(directory)?;
+    file.set_len(len)
+}
+
+fn entry_options(defaults: FileOptions, metadata: &EntryMetadata) -> FileOptions {
+    let mut options = defaults;
+    if let Some(mode) = metadata.mode {
+        options = options.unix_permissions(mode);
+    }
+    // Zip stores local time and cannot go back before 1980, such times are left out
+    if let Some(modified) = metadata.modified {
+        let local = chrono::DateTime::<chrono::Local>::from(modified);
+        let time = zip::DateTime::from_date_and_time(
+            local.year().try_into().unwrap_or(0),
+            local.month() as u8,
+            local.day() as u8,
+            local.hour() as u8,
+            local.minute() as u8,
+            local.second() as u8,
+        );
+        if let Ok(time) = time {
+            options = options.last_modified_time(time);
+        }
+    }
+    options
+}
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+// Symlinks keep their target as the entry's data, which is read here
+fn entry_info(file: &mut ZipFile<'_>) -> Result<EntryInfo, PluginError> {
+    let metadata = EntryMetadata {
+        modified: system_time(file.last_modified()),
+        mode: file.unix_mode().map(|mode| mode & 0o7777),
+    };
+    let name = file.name().trim_end_matches('/').to_string();
+    let kind = if file.is_dir() {
+        EntryKind::Directory
+    } else if file.unix_mode().is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+        let mut target = String::new();
+        file.read_to_string(&mut target)?;
+        EntryKind::Symlink { target }
+    } else {
+        EntryKind::File
+    };
+    Ok(EntryInfo { name, kind, metadata })
+}
+
+// Zip times are local
+fn system_time(time: zip::DateTime) -> Option<SystemTime> {
+    let local = chrono::NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+        .and_hms_opt(time.hour().into(), time.minute().into(), time.second().into())?;
+    chrono::Local.from_local_datetime(&local).earliest().map(SystemTime::from)
+}
+
+// Tells how much of a streamed archive has been read
+struct CountingReader<'a> {
+    inner: &'a mut dyn Read,
+    count: Rc<Cell<u64>>,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + read as u64);
+        Ok(read)
+    }
+}
+
+// `archive` is the file being read or written when the error happened, if there is one
+fn zip_error(archive: Option<&Path>, error: ZipError) -> PluginError {
+    let path = archive.map(Path::to_path_buf);
+    match error {
+        ZipError::Io(e) => match archive {
+            Some(archive) => PluginError::io(archive, e),
+            None => e.into(),
+        },
+        ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => PluginError::WrongPassword { path },
+        ZipError::UnsupportedArchive(feature) => PluginError::UnsupportedFeature(feature.to_string()),
+        ZipError::FileNotFound => PluginError::NotFound(String::from("entry in the archive")),
+        error @ ZipError::InvalidArchive(_) => PluginError::CorruptArchive {
+            path,
+            source: Some(Box::new(error)),
+        },
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plugin_api::error::ErrorCode;
     use std::fs;
     use tempfile::tempdir;
 
@@ -137,4 +462,211 @@ mod tests {
         let plugin = ZipPlugin::new();
         assert_eq!(plugin.get_config().name, "ZIP Plugin");
     }
+
+    #[test]
+    fn test_level_setting() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("data.txt");
+        fs::write(&input, "compressible ".repeat(10_000)).unwrap();
+        let options = CompressionOptions::default();
+
+        let mut archive_sizes = Vec::new();
+        for level in [0, 9] {
+            let mut plugin = ZipPlugin::new();
+            let mut values = PluginSettings::new();
+            values.insert(String::from("level"), level.into());
+            plugin.initialize(&plugin.settings_schema().resolve(&values).unwrap()).unwrap();
+
+            let archive = dir.path().join(format!("level{}.zip", level));
+            plugin.compress(std::slice::from_ref(&input), &archive, &options).unwrap();
+            archive_sizes.push(fs::metadata(&archive).unwrap().len());
+        }
+        assert!(archive_sizes[1] < archive_sizes[0] / 10);
+    }
+
+    #[test]
+    fn test_decompress_errors_have_codes() {
+        let dir = tempdir().unwrap();
+        let plugin = ZipPlugin::new();
+        let output = dir.path().join("out");
+
+        let missing = dir.path().join("missing.zip");
+        let err = plugin.decompress(&missing, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Io);
+        assert_eq!(err.path(), Some(missing.as_path()));
+
+        let garbage = dir.path().join("garbage.zip");
+        fs::write(&garbage, "not a zip file").unwrap();
+        let err = plugin.decompress(&garbage, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::CorruptArchive);
+        assert!(std::error::Error::source(&err).is_some());
+
+        let escaping = dir.path().join("escaping.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&escaping).unwrap());
+        zip.start_file("../evil.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+        let err = plugin.decompress(&escaping, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert!(matches!(&err, PluginError::PathTraversal { entry } if entry == "../evil.txt"));
+        assert!(!dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_malicious_archives_are_refused() {
+        let dir = tempdir().unwrap();
+        let plugin = ZipPlugin::new();
+        let output = dir.path().join("out");
+
+        let absolute = dir.path().join("absolute.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&absolute).unwrap());
+        zip.start_file("/tmp/evil.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+        let err = plugin.decompress(&absolute, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::PathTraversal);
+
+        // A link out of the output directory, then an entry written through it
+        let through_link = dir.path().join("link.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&through_link).unwrap());
+        zip.add_symlink("link", "../..", FileOptions::default()).unwrap();
+        zip.start_file("link/evil.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+        let err = plugin.decompress(&through_link, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::PathTraversal);
+        assert!(!dir.path().join("evil.txt").exists());
+
+        // 64 MiB of zeros deflate to a few dozen KiB
+        let bomb = dir.path().join("bomb.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&bomb).unwrap());
+        zip.start_file("zeros.bin", FileOptions::default()).unwrap();
+        zip.write_all(&vec![0; 64 * 1024 * 1024]).unwrap();
+        zip.finish().unwrap();
+        let err = plugin.decompress(&bomb, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+        assert!(!output.join("zeros.bin").exists());
+
+        let total = ExtractionLimits {
+            max_total_size: Some(1024),
+            ..ExtractionLimits::unlimited()
+        };
+        let err = extract_entries(&plugin, &bomb, &output, true, total).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+
+        let many = dir.path().join("many.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&many).unwrap());
+        for i in 0..5 {
+            zip.start_file(format!("{}.txt", i), FileOptions::default()).unwrap();
+        }
+        zip.finish().unwrap();
+        let entries = ExtractionLimits {
+            max_entries: Some(4),
+            ..ExtractionLimits::unlimited()
+        };
+        let err = extract_entries(&plugin, &many, &output, true, entries).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+        extract_entries(&plugin, &many, &output, true, ExtractionLimits::unlimited()).unwrap();
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let dir = tempdir().unwrap();
+        let plugin = ZipPlugin::new();
+        let options = CompressionOptions {
+            mode: CompressionMode::Fast,
+            ..Default::default()
+        };
+
+        let mut archive = Vec::new();
+        let mut entries = vec![
+            Ok(StreamEntry::new("a.txt", &b"first"[..])),
+            Ok(StreamEntry::new("docs/b.txt", &b"second"[..])),
+        ]
+        .into_iter();
+        plugin.compress_stream(&mut entries, &mut archive, &options).unwrap();
+
+        plugin.decompress_stream(&mut archive.as_slice(), dir.path(), true, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a.txt")).unwrap(), "first");
+        assert_eq!(fs::read_to_string(dir.path().join("docs").join("b.txt")).unwrap(), "second");
+
+        // An entry that fails to open stops the archive
+        let mut entries = vec![Err(PluginError::NotFound("c.txt".to_string()))].into_iter();
+        let err = plugin.compress_stream(&mut entries, &mut Vec::new(), &options).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::NotFound);
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("source went away"))
+        }
+    }
+
+    fn entry_names(archive: &Path) -> Vec<String> {
+        let mut zip = ZipArchive::new(File::open(archive).unwrap()).unwrap();
+        (0..zip.len()).map(|i| zip.by_index(i).unwrap().name().to_string()).collect()
+    }
+
+    fn read_entry(archive: &Path, name: &str) -> String {
+        let mut zip = ZipArchive::new(File::open(archive).unwrap()).unwrap();
+        let mut contents = String::new();
+        zip.by_name(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_update_appends_replaces_and_deletes() {
+        let dir = tempdir().unwrap();
+        let plugin = ZipPlugin::new();
+        let options = CompressionOptions::default();
+        let archive = dir.path().join("files.zip");
+        let mut entries = vec![
+            Ok(StreamEntry::new("a.txt", &b"first"[..])),
+            Ok(StreamEntry::directory("docs")),
+            Ok(StreamEntry::new("docs/b.txt", &b"second"[..])),
+        ]
+        .into_iter();
+        plugin.compress_seekable(&mut entries, &mut File::create(&archive).unwrap(), &options).unwrap();
+        let original = fs::read(&archive).unwrap();
+
+        // Additions only: appended after the untouched entries
+        let mut entries = std::iter::once(Ok(StreamEntry::new("c.txt", &b"third"[..])));
+        plugin.update(&archive, &mut entries, &[], &options).unwrap();
+        assert_eq!(entry_names(&archive), ["a.txt", "docs/", "docs/b.txt", "c.txt"]);
+        let appended = fs::read(&archive).unwrap();
+        let first_entry = 30 + "a.txt".len();
+        assert_eq!(appended[..first_entry], original[..first_entry]);
+
+        // A failed append puts the archive back as it was
+        let mut entries = vec![
+            Ok(StreamEntry::new("d.txt", &b"fourth"[..])),
+            Ok(StreamEntry::new("e.txt", FailingReader)),
+        ]
+        .into_iter();
+        let err = plugin.update(&archive, &mut entries, &[], &options).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::Io);
+        assert_eq!(fs::read(&archive).unwrap(), appended);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&archive, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        let mut entries = std::iter::once(Ok(StreamEntry::new("a.txt", &b"replaced"[..])));
+        plugin.update(&archive, &mut entries, &["docs".to_string()], &options).unwrap();
+        assert_eq!(entry_names(&archive), ["c.txt", "a.txt"]);
+        // The rewritten archive keeps the mode of the one it replaced
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&archive).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        assert_eq!(read_entry(&archive, "a.txt"), "replaced");
+        assert_eq!(read_entry(&archive, "c.txt"), "third");
+
+        let err = plugin.update(&archive, &mut std::iter::empty(), &["missing.txt".to_string()], &options).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert_eq!(entry_names(&archive), ["c.txt", "a.txt"]);
+    }
 }
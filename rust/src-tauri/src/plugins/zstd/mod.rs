@@ -1,14 +1,15 @@
-use std::fs::{self, File};
-use std::path::PathBuf;
-use zstd::stream::{copy_encode, copy_decode};
-use crate::plugin_api::base::{Plugin, PluginConfig, PluginError, PluginType};
-use crate::plugin_api::compression::CompressionPlugin;
-use crate::plugin_api::types::{CompressionOptions, PluginMetadata};
-use log::info;
-use async_trait::async_trait;
+use std::any::Any;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use anyhow::Result;
+use crate::plugin_api::base::{Plugin, PluginError, PluginConfig};
+use crate::plugin_api::compression::{CompressionPlugin, EntryInfo, EntryKind, EntryMetadata, StreamEntry};
+use crate::plugin_api::extract::{extract_entries, ExtractionLimits};
+use crate::plugin_api::settings::PluginSettings;
+use crate::plugin_api::types::{Capability, CompressionMode, CompressionOptions, PluginMetadata, PlatformSupport, PluginType};
 
-#[derive(Clone)]
+// Zstandard compresses a single stream, so archives hold exactly one file
 pub struct ZstdPlugin {
     config: PluginConfig,
     metadata: PluginMetadata,
@@ -16,20 +17,39 @@ pub struct ZstdPlugin {
 
 impl ZstdPlugin {
     pub fn new() -> Self {
-        Self {
+        ZstdPlugin {
             config: PluginConfig {
-                name: "zstd".to_string(),
-                description: "Zstandard compression plugin".to_string(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
+                name: String::from("Zstandard Plugin"),
+                description: String::from("Compresses single files with Zstandard"),
+                version: String::from("1.0.0"),
                 plugin_type: PluginType::Compression,
             },
             metadata: PluginMetadata {
-                name: "Zstandard".to_string(),
-                description: "High-performance compression using Zstandard algorithm".to_string(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
+                name: String::from("Zstandard Plugin"),
+                description: String::from("Compresses single files with Zstandard"),
+                version: String::from("1.0.0"),
+                author: String::from("Smart Transfer Team"),
+                platform_support: PlatformSupport {
+                    windows: true,
+                    linux: true,
+                    macos: true,
+                },
+                plugin_type: PluginType::Compression,
+                core_version: None,
+                extensions: vec![String::from("zst")],
+                provides: vec![Capability::Format(String::from("zstd"))],
+                requires: Vec::new(),
             },
         }
     }
+
+    fn level_for(mode: &CompressionMode) -> i32 {
+        match mode {
+            CompressionMode::Fast => 1,
+            CompressionMode::Normal => zstd::DEFAULT_COMPRESSION_LEVEL,
+            CompressionMode::Best => 19,
+        }
+    }
 }
 
 impl Plugin for ZstdPlugin {
@@ -37,86 +57,193 @@ impl Plugin for ZstdPlugin {
         &self.config
     }
 
-    fn initialize(&mut self) -> Result<(), PluginError> {
+    fn metadata(&self) -> PluginMetadata {
+        self.metadata.clone()
+    }
+
+    fn initialize(&mut self, _settings: &PluginSettings) -> Result<(), PluginError> {
         Ok(())
     }
 
-    fn shutdown(&mut self) -> Result<(), PluginError> {
+    fn cleanup(&mut self) -> Result<(), PluginError> {
         Ok(())
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn as_compression(&self) -> Option<&dyn CompressionPlugin> {
+        Some(self)
+    }
 }
 
-#[async_trait]
 impl CompressionPlugin for ZstdPlugin {
-    fn metadata(&self) -> &PluginMetadata {
-        &self.metadata
+    fn compress(
+        &self,
+        input_files: &[PathBuf],
+        output_file: &Path,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let input_file = match input_files {
+            [input_file] => input_file,
+            [] => return Err(PluginError::InvalidInput("No files to compress".to_string())),
+            _ => {
+                return Err(PluginError::UnsupportedFeature(
+                    "zstd archives hold a single file, use zip or 7z for several".to_string(),
+                ))
+            }
+        };
+
+        let input = File::open(input_file).map_err(|e| PluginError::io(input_file, e))?;
+        let output = File::create(output_file).map_err(|e| PluginError::io(output_file, e))?;
+        zstd::stream::copy_encode(input, output, Self::level_for(&options.mode))
+            .map_err(|e| PluginError::io(output_file, e))?;
+        Ok(())
     }
 
-    async fn compress(
+    fn decompress(
         &self,
-        files: Vec<PathBuf>,
-        output_path: PathBuf,
-        options: CompressionOptions,
+        archive_file: &Path,
+        output_dir: &Path,
+        overwrite: bool,
+        limits: ExtractionLimits,
     ) -> Result<(), PluginError> {
-        if files.is_empty() {
-            return Err(PluginError::ExecutionError("No files to compress".to_string()));
-        }
+        extract_entries(self, archive_file, output_dir, overwrite, limits)
+    }
 
-        // Create parent directory if it doesn't exist
-        if let Some(parent) = output_path.parent() {
-            fs::create_dir_all(parent)
-                .map_err(|e| PluginError::ExecutionError(format!("Failed to create output directory: {}", e)))?;
+    // Encodes as it reads, nothing is buffered
+    fn compress_stream(
+        &self,
+        entries: &mut dyn Iterator<Item = Result<StreamEntry<'_>, PluginError>>,
+        output: &mut dyn Write,
+        options: &CompressionOptions,
+    ) -> Result<(), PluginError> {
+        let entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry?,
+            (None, _) => return Err(PluginError::InvalidInput("No files to compress".to_string())),
+            (Some(_), Some(_)) => {
+                return Err(PluginError::UnsupportedFeature(
+                    "zstd archives hold a single file, use zip or 7z for several".to_string(),
+                ))
+            }
+        };
+        if entry.kind != EntryKind::File {
+            return Err(PluginError::UnsupportedFeature(format!(
+                "zstd archives hold a single file, '{}' is not one",
+                entry.name
+            )));
         }
-
-        let input_file = File::open(&files[0])
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to open input file: {}", e)))?;
-
-        let output_file = File::create(&output_path)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to create output file: {}", e)))?;
-
-        // Use compression level from options or default to 3
-        let level = options.level.unwrap_or(3);
-        copy_encode(input_file, output_file, level)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to compress file: {}", e)))?;
-
-        info!("Successfully created ZSTD file: {}", output_path.display());
+        zstd::stream::copy_encode(entry.reader, &mut *output, Self::level_for(&options.mode))?;
+        output.flush()?;
         Ok(())
     }
 
-    async fn decompress(
+    // `notes.txt.zst` holds `notes.txt`
+    fn read_entries(
         &self,
-        archive_path: PathBuf,
-        output_dir: PathBuf,
-        _options: CompressionOptions,
-    ) -> Result<Vec<String>, PluginError> {
-        if !archive_path.exists() {
-            return Err(PluginError::ExecutionError("Archive file does not exist".to_string()));
-        }
-
-        fs::create_dir_all(&output_dir)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to create output directory: {}", e)))?;
-
-        let file_stem = archive_path.file_stem()
-            .ok_or_else(|| PluginError::ExecutionError("Invalid archive filename".to_string()))?;
-
-        let output_path = output_dir.join(file_stem);
-        let input_file = File::open(&archive_path)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to open archive: {}", e)))?;
-
-        let output_file = File::create(&output_path)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to create output file: {}", e)))?;
+        archive_file: &Path,
+        visit: &mut dyn FnMut(EntryInfo, &mut dyn Read) -> Result<(), PluginError>,
+    ) -> Result<(), PluginError> {
+        let name = archive_file
+            .file_stem()
+            .ok_or_else(|| PluginError::InvalidInput("Invalid archive file name".to_string()))?
+            .to_string_lossy()
+            .into_owned();
+        let input = File::open(archive_file).map_err(|e| PluginError::io(archive_file, e))?;
+        let mut decoder = zstd::stream::read::Decoder::new(input).map_err(|e| PluginError::io(archive_file, e))?;
+        let info = EntryInfo {
+            name,
+            kind: EntryKind::File,
+            metadata: EntryMetadata::default(),
+        };
+        visit(info, &mut decoder)
+    }
 
-        copy_decode(input_file, output_file)
-            .map_err(|e| PluginError::ExecutionError(format!("Failed to decompress file: {}", e)))?;
+    // `decompress` names the output after the archive file; a bare stream has no name to use
+    fn decompress_stream(
+        &self,
+        _input: &mut dyn Read,
+        _output_dir: &Path,
+        _overwrite: bool,
+        _limits: ExtractionLimits,
+    ) -> Result<(), PluginError> {
+        Err(PluginError::UnsupportedFeature(
+            "zstd streams carry no file name, decompress from a .zst file instead".to_string(),
+        ))
+    }
+}
 
-        Ok(vec![output_path.to_string_lossy().into_owned()])
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::plugin_api::error::ErrorCode;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("notes.txt");
+        fs::write(&input, "zstd ".repeat(1000)).unwrap();
+        let archive = dir.path().join("notes.txt.zst");
+
+        let plugin = ZstdPlugin::new();
+        plugin.compress(std::slice::from_ref(&input), &archive, &CompressionOptions::default()).unwrap();
+        let output = dir.path().join("out");
+        plugin.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap();
+        assert_eq!(fs::read_to_string(output.join("notes.txt")).unwrap(), "zstd ".repeat(1000));
+
+        let err = plugin.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::AlreadyExists);
+
+        let mut streamed = Vec::new();
+        let mut entries = std::iter::once(Ok(StreamEntry::new("notes.txt", fs::File::open(&input).unwrap())));
+        plugin.compress_stream(&mut entries, &mut streamed, &CompressionOptions::default()).unwrap();
+        assert_eq!(zstd::decode_all(streamed.as_slice()).unwrap(), fs::read(&input).unwrap());
     }
 
-    fn supported_extensions(&self) -> Vec<&str> {
-        vec!["zst", "zstd"]
+    #[test]
+    fn test_bombs_stop_at_the_limit() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("zeros.bin.zst");
+        fs::write(&archive, zstd::encode_all(&vec![0; 64 * 1024 * 1024][..], 3).unwrap()).unwrap();
+
+        let plugin = ZstdPlugin::new();
+        let output = dir.path().join("out");
+        let err = plugin.decompress(&archive, &output, true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
+        assert!(!output.join("zeros.bin").exists());
+
+        let limits = ExtractionLimits {
+            max_total_size: Some(1024),
+            ..ExtractionLimits::unlimited()
+        };
+        let err = extract_entries(&plugin, &archive, &output, true, limits).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::LimitExceeded);
     }
 
-    fn default_extension(&self) -> &str {
-        "zst"
+    #[test]
+    fn test_errors_have_codes() {
+        let dir = tempdir().unwrap();
+        let plugin = ZstdPlugin::new();
+        let first = dir.path().join("a.txt");
+        let second = dir.path().join("b.txt");
+        fs::write(&first, "a").unwrap();
+        fs::write(&second, "b").unwrap();
+
+        let archive = dir.path().join("both.zst");
+        let err = plugin.compress(&[first, second], &archive, &CompressionOptions::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UnsupportedFeature);
+
+        let garbage = dir.path().join("garbage.txt.zst");
+        fs::write(&garbage, "not zstd data").unwrap();
+        let err = plugin.decompress(&garbage, &dir.path().join("out"), true, ExtractionLimits::default()).unwrap_err();
+        assert_eq!(err.code(), ErrorCode::CorruptArchive);
+        assert_eq!(err.path(), Some(garbage.as_path()));
     }
 }
@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use crate::transfer::TransferListener;
+use crate::transfer::protocol;
+
+const MIN_BLOCK_SIZE: u32 = 2048;
+const MAX_BLOCK_SIZE: u32 = 64 * 1024;
+const MAX_LITERAL_SIZE: usize = 256 * 1024;
+const MAX_BLOCK_COUNT: u32 = 16 * 1024 * 1024;
+
+const OP_END: u8 = 0;
+const OP_COPY: u8 = 1;
+const OP_LITERAL: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: [u8; 16],
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Signature {
+    pub block_size: u32,
+    pub file_size: u64,
+    pub blocks: Vec<BlockSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaOp {
+    // Reuse block `index` of the receiver's old copy
+    Copy(u32),
+    Literal(Vec<u8>),
+}
+
+// The weak checksum from the rsync paper, cheap to slide one byte at a time
+#[derive(Debug, Clone, Copy, Default)]
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(data: &[u8]) -> Self {
+        let len = data.len() as u32;
+        let mut a: u32 = 0;
+        let mut b: u32 = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        Self { a: a & 0xffff, b: b & 0xffff, len }
+    }
+
+    fn roll(&mut self, out: u8, inp: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(inp as u32) & 0xffff;
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a)
+            & 0xffff;
+    }
+
+    fn digest(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+fn strong_hash(data: &[u8]) -> [u8; 16] {
+    let digest = Sha256::digest(data);
+    let mut strong = [0u8; 16];
+    strong.copy_from_slice(&digest[..16]);
+    strong
+}
+
+pub fn block_size_for(file_size: u64) -> u32 {
+    let root = (file_size as f64).sqrt() as u32;
+    root.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE)
+}
+
+pub fn compute_signature<R: Read>(mut reader: R, block_size: u32) -> Result<Signature> {
+    if block_size == 0 {
+        bail!("Block size must not be zero");
+    }
+    let mut signature = Signature {
+        block_size,
+        file_size: 0,
+        blocks: Vec::new(),
+    };
+    let mut block = vec![0u8; block_size as usize];
+    loop {
+        let filled = read_full(&mut reader, &mut block)?;
+        if filled == 0 {
+            break;
+        }
+        signature.file_size += filled as u64;
+        signature.blocks.push(BlockSignature {
+            weak: RollingChecksum::new(&block[..filled]).digest(),
+            strong: strong_hash(&block[..filled]),
+        });
+        if filled < block.len() {
+            break;
+        }
+    }
+    Ok(signature)
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn block_len(signature: &Signature, index: usize) -> usize {
+    let start = index as u64 * signature.block_size as u64;
+    (signature.file_size - start).min(signature.block_size as u64) as usize
+}
+
+pub fn compute_delta<R, F>(signature: &Signature, mut reader: R, mut emit: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(DeltaOp) -> Result<()>,
+{
+    if signature.block_size == 0 {
+        bail!("Block size must not be zero");
+    }
+    let block_size = signature.block_size as usize;
+    let mut lookup: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, block) in signature.blocks.iter().enumerate() {
+        lookup.entry(block.weak).or_default().push(index);
+    }
+
+    let find_block = |window: &[u8], weak: u32| -> Option<usize> {
+        let candidates = lookup.get(&weak)?;
+        let strong = strong_hash(window);
+        candidates
+            .iter()
+            .copied()
+            .find(|&i| block_len(signature, i) == window.len() && signature.blocks[i].strong == strong)
+    };
+
+    let mut buf: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    let mut literal_start = 0;
+    let mut eof = false;
+    let mut rolling: Option<RollingChecksum> = None;
+    let mut chunk = vec![0u8; 256 * 1024];
+
+    loop {
+        // Keep one byte beyond the window available so the checksum can roll
+        while !eof && buf.len() < pos + block_size + 1 {
+            let read = reader.read(&mut chunk)?;
+            if read == 0 {
+                eof = true;
+            } else {
+                buf.extend_from_slice(&chunk[..read]);
+            }
+        }
+
+        let available = buf.len() - pos;
+        if available == 0 {
+            break;
+        }
+
+        if available < block_size {
+            // Only the short final block of the old file can match the tail
+            let window = &buf[pos..];
+            if let Some(index) = find_block(window, RollingChecksum::new(window).digest()) {
+                flush_literal(&buf[literal_start..pos], &mut emit)?;
+                emit(DeltaOp::Copy(index as u32))?;
+                literal_start = buf.len();
+            }
+            break;
+        }
+
+        let checksum = *rolling.get_or_insert_with(|| RollingChecksum::new(&buf[pos..pos + block_size]));
+        if let Some(index) = find_block(&buf[pos..pos + block_size], checksum.digest()) {
+            flush_literal(&buf[literal_start..pos], &mut emit)?;
+            emit(DeltaOp::Copy(index as u32))?;
+            pos += block_size;
+            literal_start = pos;
+            rolling = None;
+        } else {
+            if buf.len() > pos + block_size {
+                if let Some(checksum) = rolling.as_mut() {
+                    checksum.roll(buf[pos], buf[pos + block_size]);
+                }
+            } else {
+                rolling = None;
+            }
+            pos += 1;
+            if pos - literal_start >= MAX_LITERAL_SIZE {
+                flush_literal(&buf[literal_start..pos], &mut emit)?;
+                literal_start = pos;
+            }
+        }
+
+        // Drop data that has already been emitted
+        if literal_start >= MAX_LITERAL_SIZE {
+            buf.drain(..literal_start);
+            pos -= literal_start;
+            literal_start = 0;
+        }
+    }
+
+    flush_literal(&buf[literal_start.min(buf.len())..], &mut emit)?;
+    Ok(())
+}
+
+fn flush_literal<F: FnMut(DeltaOp) -> Result<()>>(data: &[u8], emit: &mut F) -> Result<()> {
+    for part in data.chunks(MAX_LITERAL_SIZE) {
+        emit(DeltaOp::Literal(part.to_vec()))?;
+    }
+    Ok(())
+}
+
+pub fn apply_delta<B, W>(signature: &Signature, basis: &mut B, ops: &[DeltaOp], output: &mut W) -> Result<()>
+where
+    B: Read + Seek,
+    W: Write,
+{
+    let mut block = vec![0u8; signature.block_size as usize];
+    for op in ops {
+        match op {
+            DeltaOp::Copy(index) => {
+                let index = *index as usize;
+                if index >= signature.blocks.len() {
+                    bail!("Delta references unknown block {}", index);
+                }
+                let len = block_len(signature, index);
+                basis.seek(SeekFrom::Start(index as u64 * signature.block_size as u64))?;
+                basis.read_exact(&mut block[..len])?;
+                output.write_all(&block[..len])?;
+            }
+            DeltaOp::Literal(data) => output.write_all(data)?,
+        }
+    }
+    Ok(())
+}
+
+async fn write_signature<W: AsyncWrite + Unpin>(writer: &mut W, signature: &Signature) -> Result<()> {
+    writer.write_u32(signature.block_size).await?;
+    writer.write_u64(signature.file_size).await?;
+    writer.write_u32(signature.blocks.len() as u32).await?;
+    for block in &signature.blocks {
+        writer.write_u32(block.weak).await?;
+        writer.write_all(&block.strong).await?;
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+// `local_size` is the size of the file the delta is computed for. The receiver's copy may be
+// larger, so it does not limit the count, only what is allocated before the blocks arrive.
+async fn read_signature<R: AsyncRead + Unpin>(reader: &mut R, local_size: u64) -> Result<Signature> {
+    let block_size = reader.read_u32().await?;
+    let file_size = reader.read_u64().await?;
+    let count = reader.read_u32().await?;
+    if count > MAX_BLOCK_COUNT
+        || block_size == 0
+        || block_size > MAX_BLOCK_SIZE
+        || count as u64 != file_size.div_ceil(block_size as u64)
+    {
+        bail!("Invalid signature with {} blocks of {} bytes", count, block_size);
+    }
+    let expected = local_size.div_ceil(block_size as u64).min(count as u64);
+    let mut blocks = Vec::with_capacity(expected as usize);
+    for _ in 0..count {
+        let weak = reader.read_u32().await?;
+        let mut strong = [0u8; 16];
+        reader.read_exact(&mut strong).await?;
+        blocks.push(BlockSignature { weak, strong });
+    }
+    Ok(Signature { block_size, file_size, blocks })
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct DeltaRequest {
+    path: String,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeltaStats {
+    pub copied_bytes: u64,
+    pub literal_bytes: u64,
+}
+
+// Sends `source` to `target`, transmitting only the parts that differ from the receiver's copy of `name`
+pub async fn send_file_delta(source: &Path, name: String, target: String) -> Result<DeltaStats> {
+    let size = tokio::fs::metadata(source).await?.len();
+    let mut stream = tokio::io::BufWriter::new(TcpStream::connect(&target).await?);
+    protocol::write_hello(&mut stream, protocol::STREAM_DELTA).await?;
+    protocol::write_message(&mut stream, &DeltaRequest { path: name, size }).await?;
+    let signature = read_signature(stream.get_mut(), size).await?;
+
+    let (tx, mut rx) = mpsc::channel::<DeltaOp>(16);
+    let path = source.to_path_buf();
+    let worker_signature = signature.clone();
+    let worker = tokio::task::spawn_blocking(move || -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        let reader = HashingReader { inner: std::fs::File::open(&path)?, hasher: &mut hasher };
+        compute_delta(&worker_signature, std::io::BufReader::new(reader), |op| {
+            tx.blocking_send(op).map_err(|_| anyhow!("Delta receiver closed"))
+        })?;
+        Ok(hasher.finalize().into())
+    });
+
+    let mut stats = DeltaStats::default();
+    while let Some(op) = rx.recv().await {
+        match op {
+            DeltaOp::Copy(index) => {
+                stats.copied_bytes += block_len(&signature, index as usize) as u64;
+                stream.write_u8(OP_COPY).await?;
+                stream.write_u32(index).await?;
+            }
+            DeltaOp::Literal(data) => {
+                stats.literal_bytes += data.len() as u64;
+                stream.write_u8(OP_LITERAL).await?;
+                stream.write_u32(data.len() as u32).await?;
+                stream.write_all(&data).await?;
+            }
+        }
+    }
+    let digest = worker.await??;
+    stream.write_u8(OP_END).await?;
+    stream.write_all(&digest).await?;
+    stream.flush().await?;
+
+    match stream.read_u8().await? {
+        protocol::STATUS_OK => Ok(stats),
+        status => Err(anyhow!("Receiver rejected delta (status {})", status)),
+    }
+}
+
+struct HashingReader<'a, R> {
+    inner: R,
+    hasher: &'a mut Sha256,
+}
+
+impl<R: Read> Read for HashingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl TransferListener {
+    pub async fn accept_delta(&self, output_dir: &Path) -> Result<PathBuf> {
+        let (stream, peer) = self.listener.accept().await?;
+        let mut stream = tokio::io::BufReader::new(stream);
+        if protocol::read_hello(&mut stream).await? != protocol::STREAM_DELTA {
+            bail!("Expected delta stream from {}", peer);
+        }
+        let request: DeltaRequest = protocol::read_message(&mut stream).await?;
+        let target = output_dir.join(protocol::sanitize_relative_path(&request.path)?);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let basis_path = target.clone();
+        let signature = tokio::task::spawn_blocking(move || match std::fs::File::open(&basis_path) {
+            Ok(file) => compute_signature(std::io::BufReader::new(file), block_size_for(request.size)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Signature {
+                block_size: block_size_for(request.size),
+                ..Signature::default()
+            }),
+            Err(e) => Err(e.into()),
+        })
+        .await??;
+        write_signature(stream.get_mut(), &signature).await?;
+
+        // Rebuild into a temporary file next to the target and swap it in once verified
+        let temp_path = target.with_file_name(format!(
+            ".{}.delta",
+            target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+        ));
+        let result = rebuild(&mut stream, &signature, &target, &temp_path).await;
+        match result {
+            Ok(()) => {
+                tokio::fs::rename(&temp_path, &target).await?;
+                stream.get_mut().write_u8(protocol::STATUS_OK).await?;
+                Ok(target)
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                let _ = stream.get_mut().write_u8(protocol::STATUS_FAILED).await;
+                Err(e)
+            }
+        }
+    }
+}
+
+async fn rebuild<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    signature: &Signature,
+    basis_path: &Path,
+    temp_path: &Path,
+) -> Result<()> {
+    use tokio::io::AsyncSeekExt;
+
+    let mut basis = match signature.blocks.is_empty() {
+        true => None,
+        false => Some(tokio::fs::File::open(basis_path).await?),
+    };
+    let mut output = tokio::io::BufWriter::new(tokio::fs::File::create(temp_path).await?);
+    let mut hasher = Sha256::new();
+    let mut buffer = Vec::new();
+
+    loop {
+        match stream.read_u8().await? {
+            OP_COPY => {
+                let index = stream.read_u32().await? as usize;
+                let basis = basis.as_mut().ok_or_else(|| anyhow!("Delta references a missing basis file"))?;
+                if index >= signature.blocks.len() {
+                    bail!("Delta references unknown block {}", index);
+                }
+                buffer.resize(block_len(signature, index), 0);
+                basis.seek(SeekFrom::Start(index as u64 * signature.block_size as u64)).await?;
+                basis.read_exact(&mut buffer).await?;
+            }
+            OP_LITERAL => {
+                let len = stream.read_u32().await? as usize;
+                if len > MAX_LITERAL_SIZE {
+                    bail!("Literal of {} bytes exceeds the protocol limit", len);
+                }
+                buffer.resize(len, 0);
+                stream.read_exact(&mut buffer).await?;
+            }
+            OP_END => break,
+            op => bail!("Unknown delta operation {}", op),
+        }
+        hasher.update(&buffer);
+        output.write_all(&buffer).await?;
+    }
+
+    let mut expected = [0u8; 32];
+    stream.read_exact(&mut expected).await?;
+    output.flush().await?;
+    if hasher.finalize().as_slice() != expected {
+        bail!("Checksum mismatch after applying delta");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Small xorshift generator so the tests stay deterministic without extra dependencies
+    struct TestRng(u64);
+
+    impl TestRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    fn mutate(rng: &mut TestRng, data: &[u8], edits: usize) -> Vec<u8> {
+        let mut result = data.to_vec();
+        for _ in 0..edits {
+            let at = rng.below(result.len());
+            match rng.below(3) {
+                0 => {
+                    let len = rng.below(200).min(result.len() - at);
+                    let patch = rng.bytes(len);
+                    result[at..at + len].copy_from_slice(&patch);
+                }
+                1 => {
+                    let len = rng.below(300) + 1;
+                    let insert = rng.bytes(len);
+                    result.splice(at..at, insert);
+                }
+                _ => {
+                    let len = rng.below(300).min(result.len() - at);
+                    result.drain(at..at + len);
+                }
+            }
+        }
+        result
+    }
+
+    fn roundtrip(old: &[u8], new: &[u8], block_size: u32) -> u64 {
+        let signature = compute_signature(Cursor::new(old), block_size).unwrap();
+        let mut ops = Vec::new();
+        compute_delta(&signature, Cursor::new(new), |op| {
+            ops.push(op);
+            Ok(())
+        })
+        .unwrap();
+
+        let mut output = Vec::new();
+        apply_delta(&signature, &mut Cursor::new(old), &ops, &mut output).unwrap();
+        assert_eq!(output, new);
+
+        ops.iter()
+            .map(|op| match op {
+                DeltaOp::Literal(data) => data.len() as u64,
+                DeltaOp::Copy(_) => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_rolling_checksum_matches_recomputation() {
+        let mut rng = TestRng(7);
+        let data = rng.bytes(5000);
+        let mut rolling = RollingChecksum::new(&data[..1024]);
+        for start in 1..(data.len() - 1024) {
+            rolling.roll(data[start - 1], data[start + 1023]);
+            assert_eq!(rolling.digest(), RollingChecksum::new(&data[start..start + 1024]).digest());
+        }
+    }
+
+    #[test]
+    fn test_delta_on_randomly_mutated_files() {
+        let mut rng = TestRng(0x5eed);
+        for round in 0..20 {
+            let len = 200_000 + rng.below(100_000);
+            let old = rng.bytes(len);
+            let new = mutate(&mut rng, &old, 1 + round % 8);
+            let literal = roundtrip(&old, &new, 2048);
+            // A handful of small edits should only cost a few blocks each
+            assert!(literal < (old.len() / 4) as u64, "round {}: {} literal bytes", round, literal);
+        }
+    }
+
+    #[test]
+    fn test_delta_edge_cases() {
+        let mut rng = TestRng(42);
+        let data = rng.bytes(10_000);
+        assert_eq!(roundtrip(&data, &data, 2048), 0);
+        assert_eq!(roundtrip(&[], &data, 2048), data.len() as u64);
+        roundtrip(&data, &[], 2048);
+        roundtrip(&data[..100], &data[..150], 2048);
+        roundtrip(&data, &data[..data.len() - 1], 2048);
+    }
+
+    #[tokio::test]
+    async fn test_read_signature_checks_the_header() {
+        let signature = compute_signature(Cursor::new(vec![7u8; 5000]), 2048).unwrap();
+        let mut data = Vec::new();
+        write_signature(&mut data, &signature).await.unwrap();
+        // The receiver's copy may be larger than the file sent
+        assert_eq!(read_signature(&mut data.as_slice(), 10).await.unwrap(), signature);
+
+        // A count that the file size does not account for is refused before anything is allocated
+        let mut forged = Vec::new();
+        forged.extend(2048u32.to_be_bytes());
+        forged.extend(5000u64.to_be_bytes());
+        forged.extend(MAX_BLOCK_COUNT.to_be_bytes());
+        assert!(read_signature(&mut forged.as_slice(), 10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_file_delta_end_to_end() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let mut rng = TestRng(99);
+
+        let old = rng.bytes(1_000_000);
+        let new = mutate(&mut rng, &old, 5);
+        std::fs::write(output_dir.path().join("image.bin"), &old).unwrap();
+        let source = source_dir.path().join("image.bin");
+        std::fs::write(&source, &new).unwrap();
+
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let output = output_dir.path().to_path_buf();
+        let receiver = tokio::spawn(async move { listener.accept_delta(&output).await });
+
+        let stats = send_file_delta(&source, "image.bin".to_string(), target).await.unwrap();
+        receiver.await.unwrap().unwrap();
+
+        assert_eq!(std::fs::read(output_dir.path().join("image.bin")).unwrap(), new);
+        assert!(stats.literal_bytes < 100_000);
+        assert_eq!(stats.copied_bytes + stats.literal_bytes, new.len() as u64);
+    }
+}
@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use crate::transfer::protocol::sanitize_relative_path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ShareContent {
+    Files(Vec<PathBuf>),
+    // Zipped when the download is requested, so the archive reflects the current folder state
+    FolderZip(PathBuf),
+    // Lets browsers upload files into the given directory
+    Upload(PathBuf),
+}
+
+// Uploads larger than this are refused unless the share sets its own limit
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+// Requests are handled by this many threads; further ones wait in the server's queue
+const WORKERS: usize = 8;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShareOptions {
+    pub expires_in_secs: Option<u64>,
+    pub max_downloads: Option<u32>,
+    pub password: Option<String>,
+    // Per uploaded file, in bytes; `DEFAULT_MAX_UPLOAD_SIZE` when unset
+    #[serde(default)]
+    pub max_upload_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareInfo {
+    pub token: String,
+    pub path: String,
+    pub expires_at: Option<u64>,
+}
+
+struct Share {
+    content: ShareContent,
+    options: ShareOptions,
+    expires_at: Option<u64>,
+    downloads: u32,
+    // Downloads being sent, which hold a slot until they are counted or fail
+    pending: u32,
+}
+
+type Shares = Arc<Mutex<HashMap<String, Share>>>;
+
+pub struct HttpShareServer {
+    server: Arc<Server>,
+    shares: Shares,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl HttpShareServer {
+    pub fn start(addr: &str) -> Result<Self> {
+        let server = Arc::new(Server::http(addr).map_err(|e| anyhow!("Failed to start HTTP server: {}", e))?);
+        let shares: Shares = Arc::new(Mutex::new(HashMap::new()));
+
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let server = Arc::clone(&server);
+                let shares = Arc::clone(&shares);
+                std::thread::spawn(move || {
+                    while let Ok(request) = server.recv() {
+                        handle_request(request, &shares);
+                    }
+                })
+            })
+            .collect();
+
+        log::info!("HTTP share server listening on {}", addr);
+        Ok(Self {
+            server,
+            shares,
+            workers,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn add_share(&self, content: ShareContent, options: ShareOptions) -> Result<ShareInfo> {
+        match &content {
+            ShareContent::Files(files) if files.is_empty() => return Err(anyhow!("No files to share")),
+            ShareContent::Files(files) => {
+                if let Some(missing) = files.iter().find(|f| !f.is_file()) {
+                    return Err(anyhow!("Not a file: {}", missing.display()));
+                }
+            }
+            ShareContent::FolderZip(dir) | ShareContent::Upload(dir) => {
+                if !dir.is_dir() {
+                    return Err(anyhow!("Not a directory: {}", dir.display()));
+                }
+            }
+        }
+
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let expires_at = options.expires_in_secs.map(|secs| now_secs() + secs);
+
+        self.shares.lock().map_err(|_| anyhow!("Share table poisoned"))?.insert(
+            token.clone(),
+            Share {
+                content,
+                options,
+                expires_at,
+                downloads: 0,
+                pending: 0,
+            },
+        );
+
+        Ok(ShareInfo {
+            path: format!("/s/{}", token),
+            token,
+            expires_at,
+        })
+    }
+
+    pub fn revoke(&self, token: &str) -> bool {
+        self.shares
+            .lock()
+            .map(|mut shares| shares.remove(token).is_some())
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for HttpShareServer {
+    // Each unblock stops one worker
+    fn drop(&mut self) {
+        for _ in &self.workers {
+            self.server.unblock();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn respond_text(request: Request, status: u16, text: &str) {
+    let _ = request.respond(Response::from_string(text).with_status_code(StatusCode(status)));
+}
+
+fn is_authorized(request: &Request, password: &Option<String>) -> bool {
+    let password = match password {
+        Some(password) => password,
+        None => return true,
+    };
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| h.value.as_str().strip_prefix("Basic ").map(str::to_string))
+        .and_then(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|credentials| credentials.split_once(':').map(|(_, p)| constant_time_eq(p, password)))
+        .unwrap_or(false)
+}
+
+// Compares digests, so neither the contents nor the length of the password leak through timing
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (Sha256::digest(a.as_bytes()), Sha256::digest(b.as_bytes()));
+    a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn handle_request(mut request: Request, shares: &Shares) {
+    let url = request.url().split('?').next().unwrap_or("").to_string();
+    let mut segments = url.trim_start_matches('/').splitn(3, '/');
+    let (token, rest) = match (segments.next(), segments.next(), segments.next()) {
+        (Some("s"), Some(token), rest) => (token.to_string(), rest.map(percent_decode)),
+        _ => return respond_text(request, 404, "Not found"),
+    };
+
+    // Check the share and reserve a download slot while holding the lock
+    let (content, options, is_download) = {
+        let mut shares = match shares.lock() {
+            Ok(shares) => shares,
+            Err(_) => return respond_text(request, 500, "Internal error"),
+        };
+        let share = match shares.get_mut(&token) {
+            Some(share) => share,
+            None => return respond_text(request, 404, "Not found"),
+        };
+        if share.expires_at.is_some_and(|at| now_secs() >= at) {
+            shares.remove(&token);
+            return respond_text(request, 410, "This link has expired");
+        }
+        if !is_authorized(&request, &share.options.password) {
+            let response = Response::from_string("Password required")
+                .with_status_code(StatusCode(401))
+                .with_header(header("WWW-Authenticate", "Basic realm=\"Smart Transfer\""));
+            let _ = request.respond(response);
+            return;
+        }
+
+        let is_download = *request.method() == Method::Get
+            && match &share.content {
+                ShareContent::Files(files) => files.len() == 1 || rest.is_some(),
+                ShareContent::FolderZip(_) => true,
+                ShareContent::Upload(_) => false,
+            };
+        if is_download {
+            if share.options.max_downloads.is_some_and(|max| share.downloads + share.pending >= max) {
+                return respond_text(request, 410, "Download limit reached");
+            }
+            share.pending += 1;
+        }
+        (share.content.clone(), share.options.clone(), is_download)
+    };
+
+    let result = match (&content, request.method().clone()) {
+        (ShareContent::Files(files), Method::Get) => serve_files(request, &token, files, rest.as_deref(), is_download),
+        (ShareContent::FolderZip(dir), Method::Get) => serve_folder_zip(request, dir),
+        (ShareContent::Upload(_), Method::Get) => serve_upload_page(request),
+        (ShareContent::Upload(dir), Method::Put) => {
+            let max_size = options.max_upload_size.unwrap_or(DEFAULT_MAX_UPLOAD_SIZE);
+            match receive_upload(&mut request, dir, rest.as_deref(), max_size) {
+                Ok(()) => request.respond(Response::empty(201)).map_err(Into::into),
+                Err((status, e)) => {
+                    respond_text(request, status, &e.to_string());
+                    Ok(())
+                }
+            }
+        }
+        _ => {
+            respond_text(request, 405, "Method not allowed");
+            Ok(())
+        }
+    };
+    // Only a download that was sent in full counts, a failed one frees its slot again
+    if is_download {
+        if let Some(share) = shares.lock().ok().as_mut().and_then(|shares| shares.get_mut(&token)) {
+            share.pending -= 1;
+            if result.is_ok() {
+                share.downloads += 1;
+            }
+        }
+    }
+    if let Err(e) = result {
+        log::error!("HTTP share request failed: {}", e);
+    }
+}
+
+fn serve_file(request: Request, path: &Path) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().replace('"', "");
+    let response = Response::from_file(File::open(path)?)
+        .with_header(header("Content-Type", "application/octet-stream"))
+        .with_header(header("Content-Disposition", &format!("attachment; filename=\"{}\"", name)));
+    request.respond(response)?;
+    Ok(())
+}
+
+fn serve_files(request: Request, token: &str, files: &[PathBuf], rest: Option<&str>, is_download: bool) -> Result<()> {
+    if !is_download {
+        let mut html = String::from("<!DOCTYPE html><html><body><h1>Shared files</h1><ul>");
+        for (index, file) in files.iter().enumerate() {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            html.push_str(&format!("<li><a href=\"/s/{}/{}\">{}</a></li>", token, index, html_escape(&name)));
+        }
+        html.push_str("</ul></body></html>");
+        request.respond(
+            Response::from_string(html).with_header(header("Content-Type", "text/html; charset=utf-8")),
+        )?;
+        return Ok(());
+    }
+
+    let index = match rest {
+        Some(index) => index.parse::<usize>().ok(),
+        None => Some(0),
+    };
+    match index.and_then(|i| files.get(i)) {
+        Some(path) => serve_file(request, path),
+        None => {
+            respond_text(request, 404, "Not found");
+            Ok(())
+        }
+    }
+}
+
+fn serve_folder_zip(request: Request, dir: &Path) -> Result<()> {
+    let mut archive = tempfile::tempfile()?;
+    {
+        let mut zip = zip::ZipWriter::new(&mut archive);
+        let options = FileOptions::default();
+        for entry in WalkDir::new(dir).follow_links(false) {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .strip_prefix(dir)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            zip.start_file(name, options)?;
+            std::io::copy(&mut File::open(entry.path())?, &mut zip)?;
+        }
+        zip.finish()?;
+    }
+    archive.seek(SeekFrom::Start(0))?;
+
+    let name = dir.file_name().unwrap_or_default().to_string_lossy().replace('"', "");
+    let response = Response::from_file(archive)
+        .with_header(header("Content-Type", "application/zip"))
+        .with_header(header("Content-Disposition", &format!("attachment; filename=\"{}.zip\"", name)));
+    request.respond(response)?;
+    Ok(())
+}
+
+fn serve_upload_page(request: Request) -> Result<()> {
+    let html = r#"<!DOCTYPE html><html><body><h1>Send files</h1>
+<input type="file" id="files" multiple><button onclick="upload()">Upload</button><p id="status"></p>
+<script>
+async function upload() {
+  const status = document.getElementById('status');
+  for (const file of document.getElementById('files').files) {
+    status.textContent = 'Uploading ' + file.name + '...';
+    const res = await fetch(location.pathname.replace(/\/$/, '') + '/' + encodeURIComponent(file.name), { method: 'PUT', body: file });
+    if (!res.ok) { status.textContent = 'Upload failed: ' + res.status; return; }
+  }
+  status.textContent = 'Done';
+}
+</script></body></html>"#;
+    request.respond(Response::from_string(html).with_header(header("Content-Type", "text/html; charset=utf-8")))?;
+    Ok(())
+}
+
+// Errors come with the status to answer with
+fn receive_upload(request: &mut Request, dir: &Path, name: Option<&str>, max_size: u64) -> Result<(), (u16, anyhow::Error)> {
+    let name = name.ok_or_else(|| (400, anyhow!("Upload without file name")))?;
+    let target = dir.join(sanitize_relative_path(name).map_err(|e| (400, e))?);
+    if request.body_length().is_some_and(|len| len as u64 > max_size) {
+        return Err((413, anyhow!("Uploads are limited to {} bytes", max_size)));
+    }
+    // A subdirectory of the share may be a symlink leading out of it. What exists of the
+    // parent is checked before anything is created, and the parent itself once it is.
+    let parent = target.parent().unwrap_or(dir);
+    let existing = parent.ancestors().find(|path| path.exists()).unwrap_or(dir);
+    check_inside(dir, existing)?;
+    fs::create_dir_all(parent).map_err(|e| (500, e.into()))?;
+    check_inside(dir, parent)?;
+    // Uploads never replace what is already there
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(&target) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err((409, anyhow!("A file named '{}' already exists", name)))
+        }
+        Err(e) => return Err((500, e.into())),
+    };
+
+    // One byte past the limit tells an oversized body without a length apart
+    let copied = std::io::copy(&mut request.as_reader().take(max_size + 1), &mut file);
+    let failure = match copied {
+        Ok(len) if len > max_size => Some((413, anyhow!("Uploads are limited to {} bytes", max_size))),
+        Ok(_) => None,
+        Err(e) => Some((400, e.into())),
+    };
+    if let Some(failure) = failure {
+        drop(file);
+        let _ = fs::remove_file(&target);
+        return Err(failure);
+    }
+    log::info!("Received upload {}", target.display());
+    Ok(())
+}
+
+fn check_inside(dir: &Path, path: &Path) -> Result<(), (u16, anyhow::Error)> {
+    let root = dir.canonicalize().map_err(|e| (500, e.into()))?;
+    let path = path.canonicalize().map_err(|e| (500, e.into()))?;
+    if !path.starts_with(&root) {
+        return Err((403, anyhow!("Uploads must stay inside the shared directory")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use tempfile::tempdir;
+
+    fn http(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            body.len()
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status_line = String::from_utf8_lossy(&response[..split]).to_string();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, response[split + 4..].to_vec())
+    }
+
+    fn start() -> (HttpShareServer, SocketAddr) {
+        let server = HttpShareServer::start("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        (server, addr)
+    }
+
+    #[test]
+    fn test_download_limit_and_password() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("report.txt");
+        fs::write(&file, b"quarterly numbers").unwrap();
+
+        let (server, addr) = start();
+        let share = server
+            .add_share(
+                ShareContent::Files(vec![file]),
+                ShareOptions {
+                    max_downloads: Some(1),
+                    password: Some(String::from("secret")),
+                    ..ShareOptions::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(http(addr, "GET", &share.path, &[], b"").0, 401);
+        let wrong = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("any:secrets"));
+        assert_eq!(http(addr, "GET", &share.path, &[("Authorization", &wrong)], b"").0, 401);
+        let auth = format!("Basic {}", base64::engine::general_purpose::STANDARD.encode("any:secret"));
+        let (status, body) = http(addr, "GET", &share.path, &[("Authorization", &auth)], b"");
+        assert_eq!(status, 200);
+        assert_eq!(body, b"quarterly numbers");
+        assert_eq!(http(addr, "GET", &share.path, &[("Authorization", &auth)], b"").0, 410);
+        assert_eq!(http(addr, "GET", "/s/unknown", &[], b"").0, 404);
+    }
+
+    #[test]
+    fn test_failed_downloads_are_not_counted() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("report.txt");
+        fs::write(&file, b"numbers").unwrap();
+
+        let (server, addr) = start();
+        let share = server
+            .add_share(
+                ShareContent::Files(vec![file.clone()]),
+                ShareOptions { max_downloads: Some(1), ..ShareOptions::default() },
+            )
+            .unwrap();
+
+        fs::rename(&file, dir.path().join("moved.txt")).unwrap();
+        assert_eq!(http(addr, "GET", &share.path, &[], b"").0, 500);
+        fs::rename(dir.path().join("moved.txt"), &file).unwrap();
+        assert_eq!(http(addr, "GET", &share.path, &[], b""), (200, b"numbers".to_vec()));
+        assert_eq!(http(addr, "GET", &share.path, &[], b"").0, 410);
+    }
+
+    #[test]
+    fn test_expired_and_revoked_shares() {
+        let dir = tempdir().unwrap();
+        let (server, addr) = start();
+        let expired = server
+            .add_share(
+                ShareContent::FolderZip(dir.path().to_path_buf()),
+                ShareOptions { expires_in_secs: Some(0), ..ShareOptions::default() },
+            )
+            .unwrap();
+        assert_eq!(http(addr, "GET", &expired.path, &[], b"").0, 410);
+
+        let revoked = server
+            .add_share(ShareContent::FolderZip(dir.path().to_path_buf()), ShareOptions::default())
+            .unwrap();
+        assert!(server.revoke(&revoked.token));
+        assert_eq!(http(addr, "GET", &revoked.path, &[], b"").0, 404);
+    }
+
+    #[test]
+    fn test_folder_zip_download() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("a.txt"), b"alpha").unwrap();
+        fs::write(dir.path().join("sub/b.txt"), b"beta").unwrap();
+
+        let (server, addr) = start();
+        let share = server
+            .add_share(ShareContent::FolderZip(dir.path().to_path_buf()), ShareOptions::default())
+            .unwrap();
+        let (status, body) = http(addr, "GET", &share.path, &[], b"");
+        assert_eq!(status, 200);
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(body)).unwrap();
+        let mut content = String::new();
+        archive.by_name("sub/b.txt").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "beta");
+        assert_eq!(archive.len(), 2);
+    }
+
+    #[test]
+    fn test_upload() {
+        let dir = tempdir().unwrap();
+        let (server, addr) = start();
+        let share = server
+            .add_share(ShareContent::Upload(dir.path().to_path_buf()), ShareOptions::default())
+            .unwrap();
+
+        assert_eq!(http(addr, "GET", &share.path, &[], b"").0, 200);
+        let path = format!("{}/photo%20one.jpg", share.path);
+        assert_eq!(http(addr, "PUT", &path, &[], b"jpegdata").0, 201);
+        assert_eq!(fs::read(dir.path().join("photo one.jpg")).unwrap(), b"jpegdata");
+
+        let escape = format!("{}/..%2Fescape.txt", share.path);
+        assert_eq!(http(addr, "PUT", &escape, &[], b"x").0, 400);
+        assert!(!dir.path().parent().unwrap().join("escape.txt").exists());
+
+        // Existing files are kept
+        assert_eq!(http(addr, "PUT", &path, &[], b"other").0, 409);
+        assert_eq!(fs::read(dir.path().join("photo one.jpg")).unwrap(), b"jpegdata");
+
+        let limited = server
+            .add_share(
+                ShareContent::Upload(dir.path().to_path_buf()),
+                ShareOptions { max_upload_size: Some(4), ..ShareOptions::default() },
+            )
+            .unwrap();
+        let path = format!("{}/big.bin", limited.path);
+        assert_eq!(http(addr, "PUT", &path, &[], b"too large").0, 413);
+        assert!(!dir.path().join("big.bin").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_upload_cannot_follow_symlinks_out() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+
+        let (server, addr) = start();
+        let share = server
+            .add_share(ShareContent::Upload(dir.path().to_path_buf()), ShareOptions::default())
+            .unwrap();
+        for name in ["link/escape.txt", "link/new/escape.txt"] {
+            let path = format!("{}/{}", share.path, name.replace('/', "%2F"));
+            assert_eq!(http(addr, "PUT", &path, &[], b"x").0, 403, "{}", name);
+        }
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_listing_escapes_file_names() {
+        let dir = tempdir().unwrap();
+        let (first, second) = (dir.path().join("<script>alert(1)<"), dir.path().join("plain.txt"));
+        fs::write(&first, b"a").unwrap();
+        fs::write(&second, b"b").unwrap();
+
+        let (server, addr) = start();
+        let share = server.add_share(ShareContent::Files(vec![first, second]), ShareOptions::default()).unwrap();
+        let (status, body) = http(addr, "GET", &share.path, &[], b"");
+        assert_eq!(status, 200);
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.contains("&lt;script&gt;alert(1)&lt;"));
+        assert!(!body.contains("<script>"));
+    }
+}
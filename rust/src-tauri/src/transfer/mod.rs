@@ -1,6 +1,192 @@
 pub mod sender;
 pub mod receiver;
+pub mod protocol;
+pub mod sync;
+pub mod delta;
+pub mod http_share;
+
+use anyhow::{anyhow, Result};
+use crate::plugin_api::types::PluginType;
+use crate::plugins::registry::{PluginRef, PluginRegistry};
 
 // Re-export main functionality
-pub use sender::send_files;
-pub use receiver::receive_files;
+pub use sender::{send_files, send_files_with_options, send_entries, TcpSender, TransferEntry};
+pub use receiver::{receive_files, TransferListener};
+pub use delta::{send_file_delta, DeltaStats};
+pub use http_share::{HttpShareServer, ShareContent, ShareInfo, ShareOptions};
+pub use sync::{sync_folder, SyncOptions, SyncPlan, SyncAction, ConflictResolution};
+// Part of the plugin API, as transfer plugins receive them
+pub use crate::plugin_api::transfer::TransferOptions;
+
+// Scheme used for targets written as a bare `host:port`
+pub const DEFAULT_SCHEME: &str = "tcp";
+
+// Splits `scheme://address` into its parts; a bare `host:port` uses `DEFAULT_SCHEME`
+pub fn split_target(target: &str) -> (&str, &str) {
+    target.split_once("://").unwrap_or((DEFAULT_SCHEME, target))
+}
+
+// Picks the registered transfer plugin handling `scheme`, the first in name order if several do
+pub fn transfer_plugin(plugins: &PluginRegistry, scheme: &str) -> Result<PluginRef> {
+    plugins
+        .plugins_of_type(&PluginType::Transfer)
+        .into_iter()
+        .find(|plugin| {
+            plugin
+                .call(|plugin| {
+                    Ok(plugin.as_transfer().is_some_and(|transfer| {
+                        transfer.schemes().iter().any(|s| s.eq_ignore_ascii_case(scheme))
+                    }))
+                })
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| anyhow!("No transfer plugin handles '{}' targets", scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+    use crate::plugin_api::transfer::TransferManifest;
+    use crate::plugins::tcp::TcpTransferPlugin;
+
+    fn plugins() -> PluginRegistry {
+        let plugins = PluginRegistry::new();
+        plugins.register(String::from("TCP Transfer Plugin"), Box::new(TcpTransferPlugin::new())).unwrap();
+        plugins
+    }
+
+    async fn roundtrip(streams: usize) {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+
+        let small = source_dir.path().join("small.txt");
+        fs::write(&small, b"hello").unwrap();
+        let large = source_dir.path().join("large.bin");
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&large, &data).unwrap();
+        let empty = source_dir.path().join("empty");
+        fs::write(&empty, b"").unwrap();
+
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let output = output_dir.path().to_path_buf();
+        let receiver = tokio::spawn(async move { listener.accept_transfer(&output).await });
+
+        let options = TransferOptions { streams, chunk_size: 64 * 1024 };
+        send_files_with_options(&plugins(), vec![small, large, empty], target, &options).await.unwrap();
+        receiver.await.unwrap().unwrap();
+
+        assert_eq!(fs::read(output_dir.path().join("small.txt")).unwrap(), b"hello");
+        assert_eq!(fs::read(output_dir.path().join("large.bin")).unwrap(), data);
+        assert!(fs::read(output_dir.path().join("empty")).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_single_stream_transfer() {
+        roundtrip(1).await;
+    }
+
+    #[tokio::test]
+    async fn test_parallel_stream_transfer() {
+        roundtrip(4).await;
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unrepresentable_transfers() {
+        let source_dir = tempdir().unwrap();
+        for dir in ["a", "b"] {
+            fs::create_dir(source_dir.path().join(dir)).unwrap();
+            fs::write(source_dir.path().join(dir).join("x.txt"), dir).unwrap();
+        }
+        let files = vec![source_dir.path().join("a").join("x.txt"), source_dir.path().join("b").join("x.txt")];
+        let err = send_files(&plugins(), files, String::from("127.0.0.1:1")).await.unwrap_err();
+        assert!(err.to_string().contains("'x.txt'"), "{}", err);
+
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let options = TransferOptions { streams: 1, chunk_size: u32::MAX as usize + 1 };
+        let mut sender = TcpSender::connect(target, &options).await.unwrap();
+        let manifest = TransferManifest { files: Vec::new() };
+        let err = sender.send_manifest(&manifest).await.unwrap_err();
+        assert!(err.to_string().contains("Chunk size too large"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_receiver_does_not_trust_the_peer() {
+        use crate::transfer::protocol::{self, ChunkHeader, FileEntry, Manifest};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let output_dir = tempdir().unwrap();
+        let file = |path: &str| FileEntry { path: path.to_string(), size: 4 };
+        let manifest = |files| Manifest { session_id: 7, streams: 2, chunk_size: 4, files };
+
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap();
+        let output = output_dir.path().to_path_buf();
+        let receiver = tokio::spawn(async move {
+            let duplicates = listener.accept_transfer(&output).await;
+            (duplicates, listener.accept_transfer(&output).await)
+        });
+
+        let mut control = TcpStream::connect(target).await.unwrap();
+        protocol::write_hello(&mut control, protocol::STREAM_CONTROL).await.unwrap();
+        protocol::write_message(&mut control, &manifest(vec![file("a.txt"), file("./a.txt")])).await.unwrap();
+        assert_eq!(control.read_u8().await.unwrap(), protocol::STATUS_FAILED);
+
+        let mut control = TcpStream::connect(target).await.unwrap();
+        protocol::write_hello(&mut control, protocol::STREAM_CONTROL).await.unwrap();
+        protocol::write_message(&mut control, &manifest(vec![file("a.txt"), file("b.txt")])).await.unwrap();
+        assert_eq!(control.read_u8().await.unwrap(), protocol::STATUS_OK);
+
+        // A stray connection is dropped without ending the session
+        let mut stray = TcpStream::connect(target).await.unwrap();
+        stray.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+        // Both streams send the first file, which adds up to the right total
+        for _ in 0..2 {
+            let mut data = TcpStream::connect(target).await.unwrap();
+            protocol::write_hello(&mut data, protocol::STREAM_DATA).await.unwrap();
+            data.write_u64(7).await.unwrap();
+            let header = ChunkHeader { file_index: 0, offset: 0, len: 4 };
+            protocol::write_chunk_header(&mut data, &header).await.unwrap();
+            data.write_all(b"data").await.unwrap();
+            protocol::write_chunk_header(&mut data, &ChunkHeader::end_of_stream()).await.unwrap();
+        }
+        assert_eq!(control.read_u8().await.unwrap(), protocol::STATUS_FAILED);
+
+        let (duplicates, overlapping) = receiver.await.unwrap();
+        assert!(duplicates.unwrap_err().to_string().contains("Duplicate path"));
+        assert!(overlapping.unwrap_err().to_string().contains("overlaps"));
+    }
+
+    #[test]
+    fn test_split_target() {
+        assert_eq!(split_target("192.168.1.5:8000"), ("tcp", "192.168.1.5:8000"));
+        assert_eq!(split_target("quic://example.org:443"), ("quic", "example.org:443"));
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_scheme() {
+        let source_dir = tempdir().unwrap();
+        let output_dir = tempdir().unwrap();
+        let file = source_dir.path().join("note.txt");
+        fs::write(&file, b"routed").unwrap();
+        let plugins = plugins();
+
+        let err = send_files(&plugins, vec![file.clone()], String::from("quic://127.0.0.1:1"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No transfer plugin handles 'quic'"));
+
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = format!("TCP://{}", listener.local_addr().unwrap());
+        let output = output_dir.path().to_path_buf();
+        let receiver = tokio::spawn(async move { listener.accept_transfer(&output).await });
+        send_files(&plugins, vec![file], target).await.unwrap();
+        receiver.await.unwrap().unwrap();
+        assert_eq!(fs::read(output_dir.path().join("note.txt")).unwrap(), b"routed");
+    }
+}
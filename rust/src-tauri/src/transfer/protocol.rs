@@ -0,0 +1,158 @@
+use std::path::{Component, Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const PROTOCOL_VERSION: u8 = 1;
+
+pub const STREAM_CONTROL: u8 = 0;
+pub const STREAM_DATA: u8 = 1;
+pub const STREAM_SYNC: u8 = 2;
+pub const STREAM_DELTA: u8 = 3;
+
+pub const STATUS_OK: u8 = 0;
+pub const STATUS_FAILED: u8 = 1;
+
+// Marks the end of a data stream in place of a file index
+pub const END_OF_STREAM: u32 = u32::MAX;
+
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub session_id: u64,
+    pub streams: u32,
+    pub chunk_size: u32,
+    pub files: Vec<FileEntry>,
+}
+
+impl Manifest {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkHeader {
+    pub file_index: u32,
+    pub offset: u64,
+    pub len: u32,
+}
+
+impl ChunkHeader {
+    pub fn end_of_stream() -> Self {
+        Self {
+            file_index: END_OF_STREAM,
+            offset: 0,
+            len: 0,
+        }
+    }
+
+    pub fn is_end_of_stream(&self) -> bool {
+        self.file_index == END_OF_STREAM
+    }
+}
+
+pub async fn write_hello<W: AsyncWrite + Unpin>(writer: &mut W, stream_kind: u8) -> Result<()> {
+    writer.write_all(&[PROTOCOL_VERSION, stream_kind]).await?;
+    Ok(())
+}
+
+pub async fn read_hello<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u8> {
+    let mut hello = [0u8; 2];
+    reader.read_exact(&mut hello).await?;
+    if hello[0] != PROTOCOL_VERSION {
+        bail!("Unsupported protocol version: {}", hello[0]);
+    }
+    Ok(hello[1])
+}
+
+pub async fn write_message<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let payload = serde_json::to_vec(message)?;
+    writer.write_u32(payload.len() as u32).await?;
+    writer.write_all(&payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn read_message<R, T>(reader: &mut R) -> Result<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let len = reader.read_u32().await?;
+    if len > MAX_MESSAGE_SIZE {
+        bail!("Message too large: {} bytes", len);
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+pub async fn write_chunk_header<W: AsyncWrite + Unpin>(writer: &mut W, header: &ChunkHeader) -> Result<()> {
+    let mut buf = [0u8; 16];
+    buf[0..4].copy_from_slice(&header.file_index.to_be_bytes());
+    buf[4..12].copy_from_slice(&header.offset.to_be_bytes());
+    buf[12..16].copy_from_slice(&header.len.to_be_bytes());
+    writer.write_all(&buf).await?;
+    Ok(())
+}
+
+pub async fn read_chunk_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<ChunkHeader> {
+    let mut buf = [0u8; 16];
+    reader.read_exact(&mut buf).await?;
+    Ok(ChunkHeader {
+        file_index: u32::from_be_bytes(buf[0..4].try_into()?),
+        offset: u64::from_be_bytes(buf[4..12].try_into()?),
+        len: u32::from_be_bytes(buf[12..16].try_into()?),
+    })
+}
+
+// Turns a path received from a peer into a relative path that cannot leave the output directory
+pub fn sanitize_relative_path(path: &str) -> Result<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            _ => return Err(anyhow!("Rejected unsafe path from peer: {}", path)),
+        }
+    }
+    if sanitized.as_os_str().is_empty() {
+        bail!("Rejected empty path from peer");
+    }
+    Ok(sanitized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_relative_path() {
+        assert_eq!(sanitize_relative_path("a/b.txt").unwrap(), PathBuf::from("a/b.txt"));
+        assert!(sanitize_relative_path("../etc/passwd").is_err());
+        assert!(sanitize_relative_path("/etc/passwd").is_err());
+        assert!(sanitize_relative_path("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_header_roundtrip() {
+        let header = ChunkHeader { file_index: 3, offset: 1 << 40, len: 4096 };
+        let mut buf = Vec::new();
+        write_chunk_header(&mut buf, &header).await.unwrap();
+        let decoded = read_chunk_header(&mut buf.as_slice()).await.unwrap();
+        assert_eq!(decoded, header);
+    }
+}
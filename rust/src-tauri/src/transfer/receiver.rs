@@ -1,7 +1,269 @@
-use std::path::PathBuf;
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Result};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use crate::plugins::registry::PluginRegistry;
+use crate::transfer::protocol::{self, Manifest};
+use crate::transfer::{split_target, transfer_plugin};
 
-pub async fn receive_files(source: String, output_dir: PathBuf) -> Result<()> {
-    // TODO: Implement file receiving logic
+const MAX_STREAMS: u32 = 64;
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+// How long a new data connection gets to identify itself
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Receives through the transfer plugin registered for the address's scheme, see `split_target`
+pub async fn receive_files(plugins: &PluginRegistry, source: String, output_dir: PathBuf) -> Result<()> {
+    let (scheme, address) = split_target(&source);
+    let plugin = transfer_plugin(plugins, scheme)?;
+    plugin
+        .call_async(|plugin| async move {
+            let transfer = plugin.as_transfer().expect("transfer_plugin only returns transfer plugins");
+            transfer.receive(address, &output_dir).await
+        })
+        .await?;
+    Ok(())
+}
+
+pub struct TransferListener {
+    pub(super) listener: TcpListener,
+}
+
+impl TransferListener {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub async fn accept_transfer(&self, output_dir: &Path) -> Result<()> {
+        let (mut control, peer) = self.listener.accept().await?;
+        control.set_nodelay(true)?;
+        if protocol::read_hello(&mut control).await? != protocol::STREAM_CONTROL {
+            bail!("Expected control stream from {}", peer);
+        }
+
+        let manifest: Manifest = protocol::read_message(&mut control).await?;
+        let targets = match prepare_files(&manifest, output_dir).await {
+            Ok(targets) => targets,
+            Err(e) => {
+                control.write_u8(protocol::STATUS_FAILED).await?;
+                return Err(e);
+            }
+        };
+        control.write_u8(protocol::STATUS_OK).await?;
+        log::info!(
+            "Receiving {} files ({} bytes) from {} over {} streams",
+            manifest.files.len(),
+            manifest.total_bytes(),
+            peer,
+            manifest.streams
+        );
+
+        let ranges = Arc::new(Mutex::new(ReceivedRanges::new(&manifest)));
+        let result = self.receive_data_streams(&manifest, Arc::new(targets), Arc::clone(&ranges)).await;
+        let (complete, received) = {
+            let ranges = ranges.lock().map_err(|_| anyhow!("Received ranges poisoned"))?;
+            (ranges.is_complete(), ranges.received())
+        };
+        match result {
+            Ok(()) if complete => {
+                control.write_u8(protocol::STATUS_OK).await?;
+                Ok(())
+            }
+            Ok(()) => {
+                control.write_u8(protocol::STATUS_FAILED).await?;
+                Err(anyhow!(
+                    "Incomplete transfer: received {} of {} bytes",
+                    received,
+                    manifest.total_bytes()
+                ))
+            }
+            Err(e) => {
+                let _ = control.write_u8(protocol::STATUS_FAILED).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn receive_data_streams(
+        &self,
+        manifest: &Manifest,
+        targets: Arc<Vec<PathBuf>>,
+        ranges: Arc<Mutex<ReceivedRanges>>,
+    ) -> Result<()> {
+        let mut workers = Vec::with_capacity(manifest.streams as usize);
+        while workers.len() < manifest.streams as usize {
+            let (mut stream, peer) = tokio::time::timeout(ACCEPT_TIMEOUT, self.listener.accept())
+                .await
+                .map_err(|_| anyhow!("Timed out waiting for data streams"))??;
+
+            // Anyone can connect to the port, so a connection that does not belong to this
+            // session is dropped rather than failing it
+            let handshake = async {
+                Ok::<_, anyhow::Error>(
+                    protocol::read_hello(&mut stream).await? == protocol::STREAM_DATA
+                        && stream.read_u64().await? == manifest.session_id,
+                )
+            };
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                Ok(Ok(true)) => {}
+                Ok(Ok(false)) => {
+                    log::warn!("Ignoring unexpected connection from {}", peer);
+                    continue;
+                }
+                Ok(Err(e)) => {
+                    log::warn!("Ignoring connection from {}: {}", peer, e);
+                    continue;
+                }
+                Err(_) => {
+                    log::warn!("Ignoring connection from {} that did not identify itself", peer);
+                    continue;
+                }
+            }
+
+            let targets = Arc::clone(&targets);
+            let ranges = Arc::clone(&ranges);
+            workers.push(tokio::spawn(async move {
+                receive_chunks(stream, targets, ranges).await
+            }));
+        }
+
+        for worker in workers {
+            worker.await.map_err(|e| anyhow!("Data stream task failed: {}", e))??;
+        }
+        Ok(())
+    }
+}
+
+// Which bytes of each file have arrived, as ranges keyed by their start. A chunk may only
+// cover bytes that have not arrived yet, so the transfer is complete once every byte has.
+struct ReceivedRanges {
+    sizes: Vec<u64>,
+    files: Vec<BTreeMap<u64, u64>>,
+}
+
+impl ReceivedRanges {
+    fn new(manifest: &Manifest) -> Self {
+        Self {
+            sizes: manifest.files.iter().map(|f| f.size).collect(),
+            files: manifest.files.iter().map(|_| BTreeMap::new()).collect(),
+        }
+    }
+
+    fn claim(&mut self, header: &protocol::ChunkHeader) -> Result<()> {
+        let index = header.file_index as usize;
+        let size = *self
+            .sizes
+            .get(index)
+            .ok_or_else(|| anyhow!("Chunk for unknown file index {}", header.file_index))?;
+        let (start, end) = match header.offset.checked_add(header.len as u64) {
+            Some(end) if end <= size => (header.offset, end),
+            _ => bail!("Chunk exceeds file bounds for file index {}", header.file_index),
+        };
+        if start == end {
+            return Ok(());
+        }
+
+        let ranges = &mut self.files[index];
+        let before = ranges.range(..=start).next_back().map(|(&s, &e)| (s, e));
+        let after = ranges.range(start..).next().map(|(&s, &e)| (s, e));
+        if before.is_some_and(|(_, e)| e > start) || after.is_some_and(|(s, _)| s < end) {
+            bail!("Chunk overlaps data already received for file index {}", header.file_index);
+        }
+        // Neighbouring ranges are merged, so a complete file ends up as a single one
+        let mut merged = (start, end);
+        if let Some((s, _)) = before.filter(|&(_, e)| e == start) {
+            ranges.remove(&s);
+            merged.0 = s;
+        }
+        if let Some((s, e)) = after.filter(|&(s, _)| s == end) {
+            ranges.remove(&s);
+            merged.1 = e;
+        }
+        ranges.insert(merged.0, merged.1);
+        Ok(())
+    }
+
+    fn received(&self) -> u64 {
+        self.files.iter().flat_map(|ranges| ranges.iter().map(|(s, e)| e - s)).sum()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.files.iter().zip(&self.sizes).all(|(ranges, &size)| {
+            size == 0 || (ranges.len() == 1 && ranges.get(&0) == Some(&size))
+        })
+    }
+}
+
+async fn prepare_files(manifest: &Manifest, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    if manifest.streams == 0 || manifest.streams > MAX_STREAMS {
+        bail!("Invalid stream count: {}", manifest.streams);
+    }
+
+    tokio::fs::create_dir_all(output_dir).await?;
+    let mut targets = Vec::with_capacity(manifest.files.len());
+    // The sender refuses these as well, but the manifest comes from the peer
+    let mut seen = HashSet::new();
+    for entry in &manifest.files {
+        let relative = protocol::sanitize_relative_path(&entry.path)?;
+        if !seen.insert(relative.clone()) {
+            bail!("Duplicate path in manifest: {}", entry.path);
+        }
+        let target = output_dir.join(relative);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Preallocate so that every stream can write its chunks at their final position
+        let file = File::create(&target).await?;
+        file.set_len(entry.size).await?;
+        targets.push(target);
+    }
+    Ok(targets)
+}
+
+async fn receive_chunks(
+    stream: TcpStream,
+    targets: Arc<Vec<PathBuf>>,
+    ranges: Arc<Mutex<ReceivedRanges>>,
+) -> Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut open_files: HashMap<u32, File> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    loop {
+        let header = protocol::read_chunk_header(&mut stream).await?;
+        if header.is_end_of_stream() {
+            break;
+        }
+
+        // Claimed before anything is written, so a repeated chunk cannot overwrite data
+        ranges.lock().map_err(|_| anyhow!("Received ranges poisoned"))?.claim(&header)?;
+
+        buffer.resize(header.len as usize, 0);
+        stream.read_exact(&mut buffer).await?;
+
+        let file = match open_files.entry(header.file_index) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let path = &targets[header.file_index as usize];
+                entry.insert(OpenOptions::new().write(true).open(path).await?)
+            }
+        };
+        file.seek(std::io::SeekFrom::Start(header.offset)).await?;
+        file.write_all(&buffer).await?;
+    }
+
+    for file in open_files.values_mut() {
+        file.flush().await?;
+    }
     Ok(())
 }
@@ -1,7 +1,258 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
-use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, bail, Result};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+use crate::plugin_api::transfer::{TransferFile, TransferManifest};
+use crate::plugins::registry::PluginRegistry;
+use crate::transfer::{split_target, transfer_plugin, TransferOptions};
+use crate::transfer::protocol::{self, ChunkHeader, FileEntry, Manifest};
 
-pub async fn send_files(files: Vec<PathBuf>, target: String) -> Result<()> {
-    // TODO: Implement file sending logic
+#[derive(Debug, Clone)]
+pub struct TransferEntry {
+    pub source: PathBuf,
+    // Path relative to the receiver's output directory
+    pub name: String,
+}
+
+pub async fn send_files(plugins: &PluginRegistry, files: Vec<PathBuf>, target: String) -> Result<()> {
+    send_files_with_options(plugins, files, target, &TransferOptions::default()).await
+}
+
+// Sends through the transfer plugin registered for the target's scheme, see `split_target`
+pub async fn send_files_with_options(
+    plugins: &PluginRegistry,
+    files: Vec<PathBuf>,
+    target: String,
+    options: &TransferOptions,
+) -> Result<()> {
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        let name = file
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file name: {}", file.display()))?
+            .to_string_lossy()
+            .into_owned();
+        entries.push(TransferEntry { source: file, name });
+    }
+    let manifest = build_manifest(&entries).await?;
+    let sources: Vec<PathBuf> = entries.into_iter().map(|e| e.source).collect();
+
+    let (scheme, address) = split_target(&target);
+    let plugin = transfer_plugin(plugins, scheme)?;
+    plugin
+        .call_async(|plugin| async move {
+            let transfer = plugin.as_transfer().expect("transfer_plugin only returns transfer plugins");
+            let mut session = transfer.connect(address, options).await?;
+            session.send_manifest(&manifest).await?;
+            session.send_data(&sources).await?;
+            session.finish().await
+        })
+        .await?;
     Ok(())
 }
+
+// Sends straight over TCP; used by folder sync, which needs the TCP receiver on the other end
+pub async fn send_entries(
+    entries: Vec<TransferEntry>,
+    target: String,
+    options: &TransferOptions,
+) -> Result<()> {
+    let manifest = build_manifest(&entries).await?;
+    let sources: Vec<PathBuf> = entries.into_iter().map(|e| e.source).collect();
+
+    let mut sender = TcpSender::connect(target, options).await?;
+    sender.send_manifest(&manifest).await?;
+    sender.send_data(&sources).await?;
+    sender.finish().await
+}
+
+async fn build_manifest(entries: &[TransferEntry]) -> Result<TransferManifest> {
+    let mut files = Vec::with_capacity(entries.len());
+    let mut names = HashSet::new();
+    for entry in entries {
+        // The receiver would write both to the same path, the last one winning
+        if !names.insert(entry.name.as_str()) {
+            bail!("More than one file would be sent as '{}'", entry.name);
+        }
+        let metadata = tokio::fs::metadata(&entry.source).await?;
+        if !metadata.is_file() {
+            bail!("Not a regular file: {}", entry.source.display());
+        }
+        files.push(TransferFile {
+            path: entry.name.clone(),
+            size: metadata.len(),
+        });
+    }
+    Ok(TransferManifest { files })
+}
+
+// Sending side of the built-in protocol: a control connection carrying the manifest and
+// status, plus `TransferOptions::streams` data connections pulling chunks from a shared queue
+pub struct TcpSender {
+    target: String,
+    options: TransferOptions,
+    control: TcpStream,
+    manifest: Option<Manifest>,
+}
+
+impl TcpSender {
+    pub async fn connect(target: String, options: &TransferOptions) -> Result<Self> {
+        if options.streams == 0 || options.chunk_size == 0 {
+            bail!("Transfer needs at least one stream and a non-zero chunk size");
+        }
+        let mut control = TcpStream::connect(&target).await?;
+        control.set_nodelay(true)?;
+        protocol::write_hello(&mut control, protocol::STREAM_CONTROL).await?;
+        Ok(Self {
+            target,
+            options: options.clone(),
+            control,
+            manifest: None,
+        })
+    }
+
+    pub async fn send_manifest(&mut self, manifest: &TransferManifest) -> Result<()> {
+        let manifest = Manifest {
+            session_id: new_session_id(),
+            streams: u32::try_from(self.options.streams)
+                .map_err(|_| anyhow!("Too many streams: {}", self.options.streams))?,
+            chunk_size: u32::try_from(self.options.chunk_size)
+                .map_err(|_| anyhow!("Chunk size too large: {}", self.options.chunk_size))?,
+            files: manifest
+                .files
+                .iter()
+                .map(|file| FileEntry {
+                    path: file.path.clone(),
+                    size: file.size,
+                })
+                .collect(),
+        };
+        log::info!(
+            "Sending {} files ({} bytes) to {} over {} streams",
+            manifest.files.len(),
+            manifest.total_bytes(),
+            self.target,
+            manifest.streams
+        );
+
+        protocol::write_message(&mut self.control, &manifest).await?;
+        expect_ok(&mut self.control, "manifest").await?;
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    pub async fn send_data(&mut self, sources: &[PathBuf]) -> Result<()> {
+        let manifest = self
+            .manifest
+            .as_ref()
+            .ok_or_else(|| anyhow!("The manifest must be sent before any data"))?;
+        if sources.len() != manifest.files.len() {
+            bail!("Expected {} source files, got {}", manifest.files.len(), sources.len());
+        }
+
+        let queue = Arc::new(Mutex::new(build_work_queue(manifest)));
+        let files: Arc<Vec<PathBuf>> = Arc::new(sources.to_vec());
+
+        let mut workers = Vec::with_capacity(self.options.streams);
+        for _ in 0..self.options.streams {
+            let target = self.target.clone();
+            let queue = Arc::clone(&queue);
+            let files = Arc::clone(&files);
+            let session_id = manifest.session_id;
+            workers.push(tokio::spawn(async move {
+                run_data_stream(target, session_id, files, queue).await
+            }));
+        }
+
+        for worker in workers {
+            worker.await.map_err(|e| anyhow!("Data stream task failed: {}", e))??;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<()> {
+        expect_ok(&mut self.control, "transfer").await?;
+        log::info!("Transfer to {} completed", self.target);
+        Ok(())
+    }
+}
+
+fn build_work_queue(manifest: &Manifest) -> VecDeque<ChunkHeader> {
+    let chunk_size = manifest.chunk_size as u64;
+    let mut queue = VecDeque::new();
+    for (index, file) in manifest.files.iter().enumerate() {
+        let mut offset = 0;
+        while offset < file.size {
+            let len = chunk_size.min(file.size - offset);
+            queue.push_back(ChunkHeader {
+                file_index: index as u32,
+                offset,
+                len: len as u32,
+            });
+            offset += len;
+        }
+    }
+    queue
+}
+
+async fn run_data_stream(
+    target: String,
+    session_id: u64,
+    files: Arc<Vec<PathBuf>>,
+    queue: Arc<Mutex<VecDeque<ChunkHeader>>>,
+) -> Result<()> {
+    let stream = TcpStream::connect(&target).await?;
+    stream.set_nodelay(true)?;
+    let mut stream = BufWriter::new(stream);
+    protocol::write_hello(&mut stream, protocol::STREAM_DATA).await?;
+    stream.write_u64(session_id).await?;
+
+    let mut open_files: HashMap<u32, File> = HashMap::new();
+    let mut buffer = Vec::new();
+
+    loop {
+        // The lock must not be held across an await point
+        let next = queue.lock().map_err(|_| anyhow!("Work queue poisoned"))?.pop_front();
+        let header = match next {
+            Some(header) => header,
+            None => break,
+        };
+
+        let file = match open_files.entry(header.file_index) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(File::open(&files[header.file_index as usize]).await?)
+            }
+        };
+        file.seek(std::io::SeekFrom::Start(header.offset)).await?;
+        buffer.resize(header.len as usize, 0);
+        file.read_exact(&mut buffer).await?;
+
+        protocol::write_chunk_header(&mut stream, &header).await?;
+        stream.write_all(&buffer).await?;
+    }
+
+    protocol::write_chunk_header(&mut stream, &ChunkHeader::end_of_stream()).await?;
+    stream.flush().await?;
+    stream.into_inner().shutdown().await?;
+    Ok(())
+}
+
+async fn expect_ok(control: &mut TcpStream, stage: &str) -> Result<()> {
+    match control.read_u8().await? {
+        protocol::STATUS_OK => Ok(()),
+        status => Err(anyhow!("Receiver rejected {} (status {})", stage, status)),
+    }
+}
+
+fn new_session_id() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    nanos ^ ((std::process::id() as u64) << 32)
+}
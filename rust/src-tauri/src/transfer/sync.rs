@@ -0,0 +1,522 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use walkdir::WalkDir;
+use crate::transfer::{TransferListener, TransferOptions};
+use crate::transfer::protocol;
+use crate::transfer::sender::{send_entries, TransferEntry};
+
+// Last synchronised state, kept in the local root to detect changes on both sides
+pub const SYNC_STATE_FILE: &str = ".smart_transfer_sync.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    pub modified: i64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncManifest {
+    // Keyed by path relative to the sync root, always with '/' separators
+    pub files: BTreeMap<String, FileState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictResolution {
+    Skip,
+    PreferLocal,
+    KeepBoth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncOptions {
+    pub propagate_deletions: bool,
+    pub dry_run: bool,
+    pub conflict_resolution: ConflictResolution,
+    pub transfer: TransferOptions,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            propagate_deletions: false,
+            dry_run: false,
+            conflict_resolution: ConflictResolution::Skip,
+            transfer: TransferOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyncAction {
+    Create { path: String, size: u64 },
+    Update { path: String, size: u64 },
+    Delete { path: String },
+    // The remote copy changed since the last sync as well, so pushing the local copy, or
+    // propagating its deletion, would overwrite that change
+    Conflict { path: String, resolution: ConflictResolution },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncPlan {
+    pub actions: Vec<SyncAction>,
+    pub bytes_to_send: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum SyncRequest {
+    GetManifest,
+    BeginTransfer,
+    Delete { paths: Vec<String> },
+    Done,
+}
+
+pub fn build_manifest(root: &Path, cache: Option<&SyncManifest>) -> Result<SyncManifest> {
+    let mut manifest = SyncManifest::default();
+    for entry in WalkDir::new(root).follow_links(false) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root)?;
+        let key = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if key == SYNC_STATE_FILE {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        // Skip hashing when size and mtime still match the last known state
+        let hash = match cache.and_then(|c| c.files.get(&key)) {
+            Some(state) if state.size == metadata.len() && state.modified == modified => state.hash.clone(),
+            _ => hash_file(entry.path())?,
+        };
+
+        manifest.files.insert(key, FileState {
+            size: metadata.len(),
+            modified,
+            hash,
+        });
+    }
+    Ok(manifest)
+}
+
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+pub fn plan_sync(
+    local: &SyncManifest,
+    remote: &SyncManifest,
+    base: Option<&SyncManifest>,
+    options: &SyncOptions,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+
+    for (path, state) in &local.files {
+        let remote_state = match remote.files.get(path) {
+            Some(remote_state) => remote_state,
+            None => {
+                plan.bytes_to_send += state.size;
+                plan.actions.push(SyncAction::Create { path: path.clone(), size: state.size });
+                continue;
+            }
+        };
+        if remote_state.hash == state.hash {
+            continue;
+        }
+
+        let (local_changed, remote_changed) = match base {
+            Some(base) => match base.files.get(path) {
+                Some(base_state) => (base_state.hash != state.hash, base_state.hash != remote_state.hash),
+                // Added on both sides since the last sync
+                None => (true, true),
+            },
+            // Without a last sync only the timestamps tell which copy is newer
+            None => (true, remote_state.modified > state.modified),
+        };
+        match (local_changed, remote_changed) {
+            (true, true) => {
+                if options.conflict_resolution != ConflictResolution::Skip {
+                    plan.bytes_to_send += state.size;
+                }
+                plan.actions.push(SyncAction::Conflict {
+                    path: path.clone(),
+                    resolution: options.conflict_resolution,
+                });
+            }
+            (true, false) => {
+                plan.bytes_to_send += state.size;
+                plan.actions.push(SyncAction::Update { path: path.clone(), size: state.size });
+            }
+            // Only the remote copy changed, which a push leaves as it is
+            (false, _) => {}
+        }
+    }
+
+    if options.propagate_deletions {
+        for (path, remote_state) in &remote.files {
+            if local.files.contains_key(path) {
+                continue;
+            }
+            // Files added on the remote side after the last sync are left alone, and without a
+            // last sync there is no telling a local deletion from a remote addition
+            let Some(base_state) = base.and_then(|b| b.files.get(path)) else {
+                continue;
+            };
+            if base_state.hash == remote_state.hash {
+                plan.actions.push(SyncAction::Delete { path: path.clone() });
+            } else {
+                plan.actions.push(SyncAction::Conflict {
+                    path: path.clone(),
+                    resolution: options.conflict_resolution,
+                });
+            }
+        }
+    }
+
+    plan
+}
+
+// The base for the next sync: what both sides hold after `plan` ran. Skipped conflicts keep
+// their old entry so they are reported again, files only the remote changed keep theirs so a
+// later local edit is still a conflict, and a conflict kept as both copies takes the local
+// state so it is not raised again until the local copy changes.
+fn synced_state(
+    local: &SyncManifest,
+    remote: &SyncManifest,
+    base: Option<&SyncManifest>,
+    plan: &SyncPlan,
+) -> SyncManifest {
+    let base_entry = |path: &String| base.and_then(|b| b.files.get(path)).cloned();
+    let mut state = SyncManifest::default();
+    for (path, local_state) in &local.files {
+        let entry = match remote.files.get(path) {
+            Some(remote_state) if remote_state.hash == local_state.hash => Some(local_state.clone()),
+            _ => base_entry(path),
+        };
+        if let Some(entry) = entry {
+            state.files.insert(path.clone(), entry);
+        }
+    }
+    for action in &plan.actions {
+        match action {
+            SyncAction::Create { path, .. } | SyncAction::Update { path, .. } => {
+                state.files.insert(path.clone(), local.files[path].clone());
+            }
+            SyncAction::Conflict { path, resolution } => match (resolution, local.files.get(path)) {
+                (ConflictResolution::Skip, None) => {
+                    if let Some(entry) = base_entry(path) {
+                        state.files.insert(path.clone(), entry);
+                    }
+                }
+                (ConflictResolution::Skip, Some(_)) | (_, None) => {}
+                (_, Some(local_state)) => {
+                    state.files.insert(path.clone(), local_state.clone());
+                }
+            },
+            SyncAction::Delete { .. } => {}
+        }
+    }
+    state
+}
+
+pub fn conflict_copy_name(path: &str) -> String {
+    let (dir, file) = match path.rfind('/') {
+        Some(pos) => (&path[..=pos], &path[pos + 1..]),
+        None => ("", path),
+    };
+    match file.rfind('.') {
+        Some(pos) if pos > 0 => format!("{}{} (conflict){}", dir, &file[..pos], &file[pos..]),
+        _ => format!("{}{} (conflict)", dir, file),
+    }
+}
+
+fn load_state(root: &Path) -> Option<SyncManifest> {
+    let data = std::fs::read(root.join(SYNC_STATE_FILE)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+fn save_state(root: &Path, state: &SyncManifest) -> Result<()> {
+    std::fs::write(root.join(SYNC_STATE_FILE), serde_json::to_vec_pretty(state)?)?;
+    Ok(())
+}
+
+pub async fn sync_folder(local_root: PathBuf, target: String, options: &SyncOptions) -> Result<SyncPlan> {
+    let base = load_state(&local_root);
+
+    let mut control = TcpStream::connect(&target).await?;
+    protocol::write_hello(&mut control, protocol::STREAM_SYNC).await?;
+    protocol::write_message(&mut control, &SyncRequest::GetManifest).await?;
+    let remote: SyncManifest = protocol::read_message(&mut control).await?;
+
+    let root = local_root.clone();
+    let cache = base.clone();
+    let local = tokio::task::spawn_blocking(move || build_manifest(&root, cache.as_ref())).await??;
+
+    let plan = plan_sync(&local, &remote, base.as_ref(), options);
+    if options.dry_run {
+        protocol::write_message(&mut control, &SyncRequest::Done).await?;
+        return Ok(plan);
+    }
+
+    let mut entries = Vec::new();
+    let mut deletions = Vec::new();
+    for action in &plan.actions {
+        match action {
+            SyncAction::Create { path, .. } | SyncAction::Update { path, .. } => {
+                entries.push(TransferEntry { source: local_root.join(path), name: path.clone() });
+            }
+            SyncAction::Conflict { path, resolution } => match (resolution, local.files.contains_key(path)) {
+                (ConflictResolution::Skip, _) => {}
+                (ConflictResolution::PreferLocal, true) => {
+                    entries.push(TransferEntry { source: local_root.join(path), name: path.clone() });
+                }
+                (ConflictResolution::KeepBoth, true) => {
+                    entries.push(TransferEntry {
+                        source: local_root.join(path),
+                        name: conflict_copy_name(path),
+                    });
+                }
+                // Deleted here but changed on the remote; keeping both keeps the remote copy
+                (ConflictResolution::PreferLocal, false) => deletions.push(path.clone()),
+                (ConflictResolution::KeepBoth, false) => {}
+            },
+            SyncAction::Delete { path } => deletions.push(path.clone()),
+        }
+    }
+
+    if !entries.is_empty() {
+        protocol::write_message(&mut control, &SyncRequest::BeginTransfer).await?;
+        expect_ok(&mut control, "transfer request").await?;
+        send_entries(entries, target.clone(), &options.transfer).await?;
+        expect_ok(&mut control, "transfer").await?;
+    }
+
+    if !deletions.is_empty() {
+        protocol::write_message(&mut control, &SyncRequest::Delete { paths: deletions }).await?;
+        expect_ok(&mut control, "deletion").await?;
+    }
+
+    protocol::write_message(&mut control, &SyncRequest::Done).await?;
+
+    save_state(&local_root, &synced_state(&local, &remote, base.as_ref(), &plan))?;
+
+    log::info!("Synchronised {} with {} ({} actions)", local_root.display(), target, plan.actions.len());
+    Ok(plan)
+}
+
+async fn expect_ok(control: &mut TcpStream, stage: &str) -> Result<()> {
+    match control.read_u8().await? {
+        protocol::STATUS_OK => Ok(()),
+        status => Err(anyhow!("Peer rejected {} (status {})", stage, status)),
+    }
+}
+
+impl TransferListener {
+    pub async fn serve_sync(&self, root: &Path) -> Result<()> {
+        let (mut control, peer) = self.listener.accept().await?;
+        if protocol::read_hello(&mut control).await? != protocol::STREAM_SYNC {
+            bail!("Expected sync stream from {}", peer);
+        }
+
+        loop {
+            match protocol::read_message::<_, SyncRequest>(&mut control).await? {
+                SyncRequest::GetManifest => {
+                    let root = root.to_path_buf();
+                    let manifest = tokio::task::spawn_blocking(move || build_manifest(&root, None)).await??;
+                    protocol::write_message(&mut control, &manifest).await?;
+                }
+                SyncRequest::BeginTransfer => {
+                    control.write_u8(protocol::STATUS_OK).await?;
+                    let status = match self.accept_transfer(root).await {
+                        Ok(()) => protocol::STATUS_OK,
+                        Err(e) => {
+                            log::error!("Sync transfer from {} failed: {}", peer, e);
+                            protocol::STATUS_FAILED
+                        }
+                    };
+                    control.write_u8(status).await?;
+                }
+                SyncRequest::Delete { paths } => {
+                    let status = match delete_files(root, &paths).await {
+                        Ok(()) => protocol::STATUS_OK,
+                        Err(e) => {
+                            log::error!("Sync deletion from {} failed: {}", peer, e);
+                            protocol::STATUS_FAILED
+                        }
+                    };
+                    control.write_u8(status).await?;
+                }
+                SyncRequest::Done => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn delete_files(root: &Path, paths: &[String]) -> Result<()> {
+    for path in paths {
+        let target = root.join(protocol::sanitize_relative_path(path)?);
+        match tokio::fs::remove_file(&target).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn state(hash: &str, modified: i64) -> FileState {
+        FileState { size: 1, modified, hash: hash.to_string() }
+    }
+
+    fn manifest(entries: &[(&str, FileState)]) -> SyncManifest {
+        SyncManifest {
+            files: entries.iter().map(|(p, s)| (p.to_string(), s.clone())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_plan_detects_changes_and_conflicts() {
+        let base = manifest(&[("same", state("a", 1)), ("local", state("b", 1)), ("both", state("c", 1)), ("gone", state("d", 1))]);
+        let local = manifest(&[("same", state("a", 1)), ("local", state("b2", 2)), ("both", state("c2", 2)), ("new", state("e", 2))]);
+        let remote = manifest(&[("same", state("a", 1)), ("local", state("b", 1)), ("both", state("c3", 3)), ("gone", state("d", 1)), ("remote_new", state("f", 3))]);
+        let options = SyncOptions { propagate_deletions: true, ..SyncOptions::default() };
+
+        let plan = plan_sync(&local, &remote, Some(&base), &options);
+        assert_eq!(plan.actions, vec![
+            SyncAction::Conflict { path: "both".into(), resolution: ConflictResolution::Skip },
+            SyncAction::Update { path: "local".into(), size: 1 },
+            SyncAction::Create { path: "new".into(), size: 1 },
+            SyncAction::Delete { path: "gone".into() },
+        ]);
+        assert_eq!(plan.bytes_to_send, 2);
+    }
+
+    #[test]
+    fn test_plan_leaves_remote_changes_alone() {
+        let base = manifest(&[("edited", state("a", 1)), ("deleted", state("b", 1))]);
+        let local = manifest(&[("edited", state("a", 1))]);
+        let remote = manifest(&[("edited", state("a2", 2)), ("deleted", state("b2", 2))]);
+        let options = SyncOptions {
+            propagate_deletions: true,
+            conflict_resolution: ConflictResolution::PreferLocal,
+            ..SyncOptions::default()
+        };
+
+        // The stale local copy is not pushed over the remote edit, and the file deleted here
+        // but edited there is a conflict rather than a deletion
+        let plan = plan_sync(&local, &remote, Some(&base), &options);
+        assert_eq!(plan.actions, vec![
+            SyncAction::Conflict { path: "deleted".into(), resolution: ConflictResolution::PreferLocal },
+        ]);
+        assert_eq!(plan.bytes_to_send, 0);
+    }
+
+    #[test]
+    fn test_conflicts_kept_as_both_are_not_raised_again() {
+        let base = manifest(&[("both", state("a", 1)), ("skipped", state("b", 1))]);
+        let local = manifest(&[("both", state("a2", 2)), ("skipped", state("b2", 2))]);
+        let remote = manifest(&[("both", state("a3", 3)), ("skipped", state("b3", 3))]);
+        let keep_both = SyncOptions { conflict_resolution: ConflictResolution::KeepBoth, ..SyncOptions::default() };
+
+        let plan = plan_sync(&local, &remote, Some(&base), &keep_both);
+        assert_eq!(plan.actions.len(), 2);
+        let state = synced_state(&local, &remote, Some(&base), &plan);
+        assert!(plan_sync(&local, &remote, Some(&state), &keep_both).actions.is_empty());
+
+        // Skipped ones are
+        let plan = plan_sync(&local, &remote, Some(&base), &SyncOptions::default());
+        let state = synced_state(&local, &remote, Some(&base), &plan);
+        assert_eq!(plan_sync(&local, &remote, Some(&state), &SyncOptions::default()).actions, plan.actions);
+    }
+
+    #[test]
+    fn test_first_sync_deletes_nothing() {
+        let local = manifest(&[("same", state("a", 1))]);
+        let remote = manifest(&[("same", state("a", 1)), ("remote_only", state("b", 1))]);
+        let options = SyncOptions { propagate_deletions: true, ..SyncOptions::default() };
+
+        assert!(plan_sync(&local, &remote, None, &options).actions.is_empty());
+    }
+
+    #[test]
+    fn test_conflict_copy_name() {
+        assert_eq!(conflict_copy_name("dir/report.pdf"), "dir/report (conflict).pdf");
+        assert_eq!(conflict_copy_name("README"), "README (conflict)");
+    }
+
+    #[tokio::test]
+    async fn test_sync_folder_end_to_end() {
+        let local = tempdir().unwrap();
+        let remote = tempdir().unwrap();
+        fs::create_dir_all(local.path().join("sub")).unwrap();
+        fs::write(local.path().join("sub/a.txt"), b"alpha").unwrap();
+        fs::write(local.path().join("b.txt"), b"beta").unwrap();
+        fs::write(remote.path().join("b.txt"), b"beta").unwrap();
+        fs::write(remote.path().join("stale.txt"), b"old").unwrap();
+
+        let options = SyncOptions { propagate_deletions: true, ..SyncOptions::default() };
+
+        // A dry run only reports the plan
+        let listener = TransferListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let remote_root = remote.path().to_path_buf();
+        let server = tokio::spawn(async move {
+            listener.serve_sync(&remote_root).await.unwrap();
+            listener.serve_sync(&remote_root).await.unwrap();
+            listener.serve_sync(&remote_root).await
+        });
+
+        let dry_run = SyncOptions { dry_run: true, ..options.clone() };
+        let plan = sync_folder(local.path().to_path_buf(), target.clone(), &dry_run).await.unwrap();
+        assert_eq!(plan.actions.len(), 1);
+        assert!(!remote.path().join("sub/a.txt").exists());
+
+        // The first sync has no base to tell deletions from, so the remote-only file stays
+        sync_folder(local.path().to_path_buf(), target.clone(), &options).await.unwrap();
+        assert_eq!(fs::read(remote.path().join("sub/a.txt")).unwrap(), b"alpha");
+        assert!(remote.path().join("stale.txt").exists());
+        assert!(local.path().join(SYNC_STATE_FILE).exists());
+
+        fs::remove_file(local.path().join("b.txt")).unwrap();
+        sync_folder(local.path().to_path_buf(), target, &options).await.unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!remote.path().join("b.txt").exists());
+        assert!(remote.path().join("stale.txt").exists());
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::Path;
+use smart_transfer::core::plugin_manager::PluginManager;
+use smart_transfer::plugin_api::types::{CompressionOptions, PluginType};
+use smart_transfer::plugin_api::{ErrorCode, ErrorPayload, ExtractionLimits};
+
+fn manager() -> PluginManager {
+    let mut manager = PluginManager::new();
+    manager.register_default_plugins().unwrap();
+    manager
+}
+
+// Same path as the `compress_files` and `decompress_file` commands
+fn roundtrip(manager: &PluginManager, plugin_name: &str, archive_name: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("report.txt");
+    std::fs::write(&input, "quarterly numbers").unwrap();
+    let archive = dir.path().join(archive_name);
+
+    manager.compress(plugin_name, &[input], &archive, &CompressionOptions::default()).unwrap();
+    assert!(archive.is_file());
+
+    let output = dir.path().join("extracted");
+    manager.decompress(plugin_name, &archive, &output, true).unwrap();
+    assert_eq!(std::fs::read_to_string(output.join("report.txt")).unwrap(), "quarterly numbers");
+}
+
+#[test]
+fn test_builtin_plugins_compress_and_decompress() {
+    let manager = manager();
+    roundtrip(&manager, "ZIP Plugin", "files.zip");
+    roundtrip(&manager, "7-Zip Plugin", "files.7z");
+    roundtrip(&manager, "Zstandard Plugin", "report.txt.zst");
+}
+
+// Same path as the async commands: sync plugins run on the blocking pool
+#[tokio::test]
+async fn test_async_jobs_do_not_block_the_runtime() {
+    let manager = manager();
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("report.txt");
+    std::fs::write(&input, "quarterly numbers").unwrap();
+    let (zip, sevenz) = (dir.path().join("files.zip"), dir.path().join("files.7z"));
+
+    let (zipped, sevenzipped) = tokio::join!(
+        manager.compress_async("ZIP Plugin", vec![input.clone()], zip.clone(), CompressionOptions::default()),
+        manager.compress_async("7-Zip Plugin", vec![input.clone()], sevenz.clone(), CompressionOptions::default()),
+    );
+    zipped.unwrap();
+    sevenzipped.unwrap();
+
+    let output = dir.path().join("extracted");
+    manager.decompress_async("7-Zip Plugin", sevenz, output.clone(), true).await.unwrap();
+    assert_eq!(std::fs::read_to_string(output.join("report.txt")).unwrap(), "quarterly numbers");
+
+    let err = manager.compress_async("Missing", Vec::new(), zip, CompressionOptions::default()).await.unwrap_err();
+    assert_eq!(err.to_string(), "Plugin 'Missing' not found");
+}
+
+#[test]
+fn test_builtin_plugins_expose_compression() {
+    let manager = manager();
+    for plugin in manager.plugins_of_type(&PluginType::Compression) {
+        assert!(plugin.call(|plugin| Ok(plugin.as_compression().is_some())).unwrap(), "{}", plugin.metadata().name);
+    }
+    let zip = manager.plugin_for_path(Path::new("files.zip")).unwrap();
+    assert_eq!(zip.metadata().name, "ZIP Plugin");
+}
+
+#[test]
+fn test_unknown_plugin_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = manager()
+        .compress("Missing", &[], &dir.path().join("out.zip"), &CompressionOptions::default())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Plugin 'Missing' not found");
+}
+
+// What the frontend receives when a command fails
+#[test]
+fn test_failures_carry_an_error_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("broken.zip");
+    std::fs::write(&archive, "not a zip file").unwrap();
+
+    let err = manager().decompress("ZIP Plugin", &archive, dir.path(), true).unwrap_err();
+    let payload = ErrorPayload::from(err);
+    assert_eq!(payload.code, ErrorCode::CorruptArchive);
+    assert_eq!(payload.path.as_deref(), Some(archive.as_path()));
+    assert!(payload.message.starts_with(&format!("Archive {} is corrupt: invalid Zip archive", archive.display())));
+
+    let json = serde_json::to_value(&payload).unwrap();
+    assert_eq!(json["code"], "corrupt_archive");
+}
+
+// Embedders build archives from generated content with any registered plugin
+#[test]
+fn test_archive_builder_with_registered_plugins() {
+    let manager = manager();
+    let dir = tempfile::tempdir().unwrap();
+
+    for (plugin_name, archive_name) in [("ZIP Plugin", "built.zip"), ("7-Zip Plugin", "built.7z")] {
+        let archive = dir.path().join(archive_name);
+        let mut builder = manager.archive_builder(plugin_name).unwrap();
+        builder.add_bytes("summary.txt", "generated").add_dir("attachments");
+        builder.write_to_file(&archive, &CompressionOptions::default()).unwrap();
+
+        let output = dir.path().join(format!("{}-out", archive_name));
+        manager.decompress(plugin_name, &archive, &output, true).unwrap();
+        assert_eq!(std::fs::read_to_string(output.join("summary.txt")).unwrap(), "generated");
+        assert!(output.join("attachments").is_dir());
+    }
+
+    let mut builder = manager.archive_builder("Zstandard Plugin").unwrap();
+    builder.add_bytes("report.txt", "quarterly numbers");
+    let archive = builder.to_vec(&CompressionOptions::default()).unwrap();
+    assert_eq!(zstd::decode_all(archive.as_slice()).unwrap(), b"quarterly numbers");
+
+    assert!(manager.archive_builder("Missing").is_err());
+}
+
+// Same path as the `update_archive` command
+#[tokio::test]
+async fn test_update_archive_adds_and_removes_entries() {
+    let manager = manager();
+    let dir = tempfile::tempdir().unwrap();
+    let (first, second) = (dir.path().join("first.txt"), dir.path().join("second.txt"));
+    std::fs::write(&first, "one").unwrap();
+    std::fs::write(&second, "two").unwrap();
+
+    for (plugin_name, archive_name) in [("ZIP Plugin", "files.zip"), ("7-Zip Plugin", "files.7z")] {
+        let archive = dir.path().join(archive_name);
+        manager.compress(plugin_name, std::slice::from_ref(&first), &archive, &CompressionOptions::default()).unwrap();
+
+        manager
+            .update_archive_async(plugin_name, archive.clone(), vec![second.clone()], Vec::new(), CompressionOptions::default())
+            .await
+            .unwrap();
+        manager
+            .update_archive_async(plugin_name, archive.clone(), Vec::new(), vec!["first.txt".to_string()], CompressionOptions::default())
+            .await
+            .unwrap();
+
+        let output = dir.path().join(format!("{}-out", archive_name));
+        manager.decompress(plugin_name, &archive, &output, true).unwrap();
+        assert_eq!(std::fs::read_to_string(output.join("second.txt")).unwrap(), "two");
+        assert!(!output.join("first.txt").exists());
+    }
+
+    let archive = dir.path().join("first.txt.zst");
+    manager.compress("Zstandard Plugin", std::slice::from_ref(&first), &archive, &CompressionOptions::default()).unwrap();
+    let err = manager
+        .update_archive_async("Zstandard Plugin", archive, vec![second], Vec::new(), CompressionOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorPayload::from(err).code, ErrorCode::NotImplemented);
+}
+
+// Same path as the `convert_archive` command
+#[tokio::test]
+async fn test_convert_archive_between_plugins() {
+    let manager = manager();
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("report.txt");
+    std::fs::write(&input, "quarterly numbers").unwrap();
+    let sevenz = dir.path().join("files.7z");
+    manager.compress("7-Zip Plugin", std::slice::from_ref(&input), &sevenz, &CompressionOptions::default()).unwrap();
+
+    let zip = dir.path().join("files.zip");
+    manager
+        .convert_archive_async("7-Zip Plugin", sevenz.clone(), "ZIP Plugin", zip.clone(), CompressionOptions::default())
+        .await
+        .unwrap();
+    let output = dir.path().join("extracted");
+    manager.decompress("ZIP Plugin", &zip, &output, true).unwrap();
+    assert_eq!(std::fs::read_to_string(output.join("report.txt")).unwrap(), "quarterly numbers");
+
+    // A failed conversion leaves no output behind
+    let garbage = dir.path().join("garbage.7z");
+    std::fs::write(&garbage, "not a 7z archive").unwrap();
+    let failed = dir.path().join("failed.zip");
+    let err = manager
+        .convert_archive_async("7-Zip Plugin", garbage, "ZIP Plugin", failed.clone(), CompressionOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorPayload::from(err).code, ErrorCode::CorruptArchive);
+    assert!(!failed.exists());
+}
+
+// Limits set on the manager apply to the `decompress_file` command's path
+#[tokio::test]
+async fn test_extraction_limits_are_configurable() {
+    let manager = manager();
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("report.txt");
+    std::fs::write(&input, "quarterly numbers ".repeat(100)).unwrap();
+
+    let archives = [
+        ("ZIP Plugin", "report.zip"),
+        ("7-Zip Plugin", "report.7z"),
+        ("Zstandard Plugin", "report.txt.zst"),
+    ];
+    for (plugin_name, archive_name) in archives {
+        let archive = dir.path().join(archive_name);
+        manager.compress(plugin_name, std::slice::from_ref(&input), &archive, &CompressionOptions::default()).unwrap();
+
+        manager.set_extraction_limits(ExtractionLimits {
+            max_total_size: Some(100),
+            ..ExtractionLimits::unlimited()
+        })
+        .unwrap();
+        let output = dir.path().join(format!("{}-out", archive_name));
+        let err = manager
+            .decompress_async(plugin_name, archive.clone(), output.clone(), true)
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorPayload::from(err).code, ErrorCode::LimitExceeded);
+        assert!(!output.join("report.txt").exists());
+
+        manager.set_extraction_limits(ExtractionLimits::default()).unwrap();
+        manager.decompress(plugin_name, &archive, &output, true).unwrap();
+        assert_eq!(std::fs::read(output.join("report.txt")).unwrap(), std::fs::read(&input).unwrap());
+    }
+}
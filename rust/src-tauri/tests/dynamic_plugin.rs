@@ -0,0 +1,365 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use smart_transfer::core::plugin_manager::PluginManager;
+use smart_transfer::plugin_api::types::{Capability, CompressionOptions};
+use smart_transfer::plugins::signature::{sign_plugin, TrustedKey};
+use smart_transfer::plugins::host::{self as plugin_host, HostedPlugin, PluginHostCommand};
+use smart_transfer::plugins::{PluginLoader, PluginTrustConfig, SignaturePolicy};
+use smart_transfer::plugin_api::{ErrorCode, ErrorPayload, ExtractionLimits, PluginSettings};
+
+// Builds `plugin-example` as a cdylib and returns the path of the produced library
+fn build_example_plugin() -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let example = manifest_dir.join("..").join("plugin-example");
+    let target_dir = manifest_dir.join("target").join("plugin-fixtures");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| String::from("cargo"));
+
+    let status = Command::new(cargo)
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(example.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "building plugin-example failed");
+
+    target_dir.join("debug").join(format!(
+        "{}plugin_example{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ))
+}
+
+#[test]
+fn test_load_and_call_dynamic_plugin() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "example", "Example Plugin", ">=0.1.0");
+    let manifest = plugin_dir.path().join("example").join("plugin.toml");
+    let mut plugin = PluginLoader::load_from_manifest(&manifest, &allow_unsigned()).unwrap().plugin;
+    assert_eq!(plugin.metadata().name, "Example Plugin");
+    assert_eq!(plugin.get_config().name, "Example Plugin");
+
+    // The schema and settings cross the boundary as JSON
+    let schema = plugin.settings_schema();
+    assert_eq!(schema.settings[0].key, "pretty");
+    let mut settings = PluginSettings::new();
+    settings.insert("pretty".to_string(), serde_json::json!(true));
+    plugin.initialize(&schema.resolve(&settings).unwrap()).unwrap();
+    let compression = plugin.as_compression().unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("hello.txt");
+    std::fs::write(&input, "hello plugin").unwrap();
+    let archive = dir.path().join("out.example");
+    compression.compress(&[input], &archive, &CompressionOptions::default()).unwrap();
+    assert!(std::fs::read_to_string(&archive).unwrap().contains('\n'));
+
+    let output = dir.path().join("extracted");
+    compression.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap();
+    assert_eq!(std::fs::read_to_string(output.join("hello.txt")).unwrap(), "hello plugin");
+
+    // Errors raised inside the library come back as values instead of unwinding across the boundary
+    let err = compression.decompress(&archive, &output, false, ExtractionLimits::default()).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::AlreadyExists);
+    assert!(err.to_string().contains("hello.txt"));
+
+    let escaping = dir.path().join("escaping.example");
+    std::fs::write(&escaping, r#"{"../escaped.txt": "escaped"}"#).unwrap();
+    let err = compression.decompress(&escaping, &output, true, ExtractionLimits::default()).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::PathTraversal);
+    assert!(!dir.path().join("escaped.txt").exists());
+
+    plugin.cleanup().unwrap();
+}
+
+fn write_manifest(dir: &Path, name: &str, version: &str, core_version: &str) {
+    std::fs::write(
+        dir.join("plugin.toml"),
+        format!(
+            "name = \"{}\"\nversion = \"{}\"\ncore_version = \"{}\"\ntype = \"compression\"\n",
+            name, version, core_version
+        ),
+    )
+    .unwrap();
+}
+
+fn install_plugin(plugin_dir: &Path, library: &Path, dir_name: &str, name: &str, core_version: &str) {
+    let dir = plugin_dir.join(dir_name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(library, dir.join(library.file_name().unwrap())).unwrap();
+    write_manifest(&dir, name, "0.2.0", core_version);
+}
+
+// The example plugin is not signed; these tests are about loading, not signatures
+fn allow_unsigned() -> PluginTrustConfig {
+    PluginTrustConfig {
+        policy: SignaturePolicy::Warn,
+        keys: Vec::new(),
+    }
+}
+
+fn unsigned_manager() -> PluginManager {
+    let mut manager = PluginManager::new();
+    manager.set_trust(allow_unsigned());
+    manager
+}
+
+#[test]
+fn test_discover_checks_manifests() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "compatible", "compatible", ">=0.1.0");
+    install_plugin(plugin_dir.path(), &library, "too-new", "too-new", ">=99.0.0");
+    std::fs::copy(&library, plugin_dir.path().join(library.file_name().unwrap())).unwrap();
+
+    let discovery = PluginLoader::discover(plugin_dir.path().to_path_buf(), &allow_unsigned()).unwrap();
+    assert_eq!(discovery.plugins.len(), 1);
+    let metadata = discovery.plugins[0].plugin.metadata();
+    assert_eq!(metadata.name, "compatible");
+    assert_eq!(metadata.version, "0.2.0");
+    assert_eq!(metadata.core_version.as_deref(), Some(">=0.1.0"));
+
+    let reasons: Vec<&str> = discovery.rejected.iter().map(|r| r.reason.as_str()).collect();
+    assert_eq!(reasons.len(), 2);
+    assert!(reasons.iter().any(|r| r.contains("has no plugin.toml")));
+    assert!(reasons.iter().any(|r| r.contains("requires core version >=99.0.0")));
+}
+
+#[test]
+fn test_manager_registers_external_plugins() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "a-example", "Example", ">=0.1.0");
+    install_plugin(plugin_dir.path(), &library, "b-example", "Example", ">=0.1.0");
+    install_plugin(plugin_dir.path(), &library, "zip", "ZIP Plugin", ">=0.1.0");
+
+    let mut manager = unsigned_manager();
+    manager.register_default_plugins().unwrap();
+    assert_eq!(manager.load_external_plugins(plugin_dir.path().to_path_buf()), 1);
+
+    let example = manager.get_plugin("Example").unwrap();
+    assert_eq!(example.metadata().core_version.as_deref(), Some(">=0.1.0"));
+    let work = tempfile::tempdir().unwrap();
+    let input = work.path().join("notes.txt");
+    std::fs::write(&input, "external").unwrap();
+    let archive = work.path().join("notes.example");
+    manager.compress("Example", &[input], &archive, &CompressionOptions::default()).unwrap();
+    manager.decompress("Example", &archive, &work.path().join("out"), false).unwrap();
+    assert_eq!(std::fs::read_to_string(work.path().join("out").join("notes.txt")).unwrap(), "external");
+    // Built-ins keep their name, and the first external plugin in path order wins
+    assert_eq!(manager.get_plugin("ZIP Plugin").unwrap().metadata().author, "Smart Transfer Team");
+
+    let failures = manager.load_failures();
+    assert_eq!(failures.len(), 2);
+    assert!(failures.iter().all(|f| f.reason.contains("is already registered")));
+    assert!(failures.iter().any(|f| f.path.starts_with(plugin_dir.path().join("b-example"))));
+    assert!(failures.iter().any(|f| f.path.starts_with(plugin_dir.path().join("zip"))));
+}
+
+#[test]
+fn test_manager_resolves_plugin_requirements() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    let install = |dir_name: &str, capabilities: &str| {
+        install_plugin(plugin_dir.path(), &library, dir_name, dir_name, ">=0.1.0");
+        let manifest = plugin_dir.path().join(dir_name).join("plugin.toml");
+        let source = std::fs::read_to_string(&manifest).unwrap();
+        std::fs::write(&manifest, source + capabilities).unwrap();
+    };
+    // Installed in path order before the plugins they build on
+    install("a-tar-zst", "extensions = [\"tar.zst\"]\nrequires = [\"format:tar\", \"format:zip\"]\n");
+    install("b-cycle", "provides = [\"format:left\"]\nrequires = [\"format:right\"]\n");
+    install("c-cycle", "provides = [\"format:right\"]\nrequires = [\"format:left\"]\n");
+    install("d-xz", "requires = [\"format:xz\"]\n");
+    install("e-tar", "provides = [\"format:tar\", \"feature:encryption\"]\n");
+
+    let mut manager = unsigned_manager();
+    manager.register_default_plugins().unwrap();
+    assert_eq!(manager.load_external_plugins(plugin_dir.path().to_path_buf()), 2);
+    assert!(manager.get_plugin("a-tar-zst").is_some());
+    assert!(manager.get_plugin("e-tar").is_some());
+
+    let failures = manager.load_failures();
+    assert_eq!(failures.len(), 3);
+    assert!(failures.iter().all(|f| f.reason.contains("which no loaded plugin provides")));
+    assert!(failures.iter().any(|f| f.reason.contains("Requires format:xz")));
+
+    let query: Vec<Capability> = vec!["extension:.tar.zst".parse().unwrap()];
+    let found = manager.find_plugins(&query);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].metadata().name, "a-tar-zst");
+    let encrypted: Vec<Capability> = vec!["format:tar".parse().unwrap(), "feature:encryption".parse().unwrap()];
+    assert_eq!(manager.find_plugins(&encrypted)[0].metadata().name, "e-tar");
+
+    // e-tar provides format:tar to a-tar-zst, so it can neither go nor drop it first
+    let err = manager.unload_plugin("e-tar").unwrap_err();
+    assert!(err.to_string().contains("is required by a-tar-zst"));
+    write_manifest(&plugin_dir.path().join("e-tar"), "e-tar", "0.3.0", ">=0.1.0");
+    let err = manager.reload_plugin("e-tar").unwrap_err();
+    assert!(err.to_string().contains("is required by a-tar-zst"));
+    assert_eq!(manager.get_plugin("e-tar").unwrap().metadata().version, "0.2.0");
+    manager.unload_plugin("a-tar-zst").unwrap();
+    manager.reload_plugin("e-tar").unwrap();
+    manager.unload_plugin("e-tar").unwrap();
+}
+
+#[test]
+fn test_reload_keeps_in_flight_instance() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "example", "Example", ">=0.1.0");
+
+    let manager = unsigned_manager();
+    assert_eq!(manager.load_external_plugins(plugin_dir.path().to_path_buf()), 1);
+    let in_flight = manager.get_plugin("Example").unwrap();
+
+    write_manifest(&plugin_dir.path().join("example"), "Example", "0.3.0", ">=0.1.0");
+    manager.reload_plugin("Example").unwrap();
+    let reloaded = manager.get_plugin("Example").unwrap();
+    assert!(!Arc::ptr_eq(&in_flight, &reloaded));
+    assert_eq!(reloaded.metadata().version, "0.3.0");
+
+    manager.unload_plugin("Example").unwrap();
+    assert!(manager.get_plugin("Example").is_none());
+    drop(reloaded);
+
+    // The old instance and its library stay usable until the job lets go of them
+    assert_eq!(in_flight.metadata().version, "0.2.0");
+    let work = tempfile::tempdir().unwrap();
+    let input = work.path().join("job.txt");
+    std::fs::write(&input, "still running").unwrap();
+    let output = work.path().join("job.example");
+    in_flight
+        .call(|plugin| plugin.as_compression().unwrap().compress(&[input], &output, &CompressionOptions::default()))
+        .unwrap();
+}
+
+#[test]
+fn test_signed_plugins() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    let key = ed25519_dalek::SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+    let installed = |dir_name: &str| {
+        install_plugin(plugin_dir.path(), &library, dir_name, dir_name, ">=0.1.0");
+        let dir = plugin_dir.path().join(dir_name);
+        (dir.join("plugin.toml"), dir.join(library.file_name().unwrap()))
+    };
+
+    let (manifest, signed_library) = installed("signed");
+    sign_plugin(&key, &manifest, &signed_library).unwrap();
+    let (manifest, tampered_library) = installed("tampered");
+    sign_plugin(&key, &manifest, &tampered_library).unwrap();
+    write_manifest(manifest.parent().unwrap(), "tampered", "9.9.9", ">=0.1.0");
+    installed("unsigned");
+
+    let trust = PluginTrustConfig {
+        policy: SignaturePolicy::Require,
+        keys: vec![TrustedKey::new("test", &key.verifying_key())],
+    };
+    let discovery = PluginLoader::discover(plugin_dir.path().to_path_buf(), &trust).unwrap();
+    assert_eq!(discovery.plugins.len(), 1);
+    assert_eq!(discovery.plugins[0].plugin.metadata().name, "signed");
+
+    let reasons: Vec<&str> = discovery.rejected.iter().map(|r| r.reason.as_str()).collect();
+    assert_eq!(reasons.len(), 2);
+    assert!(reasons.iter().any(|r| r.contains("does not match any trusted key")));
+    assert!(reasons.iter().any(|r| r.contains("not signed")));
+}
+
+fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_watcher_reloads_changed_plugins() {
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "example", "Example", ">=0.1.0");
+
+    let manager = Arc::new(unsigned_manager());
+    manager.load_external_plugins(plugin_dir.path().to_path_buf());
+    let _reloader = manager.watch_plugin_dir(plugin_dir.path().to_path_buf()).unwrap();
+
+    write_manifest(&plugin_dir.path().join("example"), "Example", "0.3.0", ">=0.1.0");
+    assert!(wait_for(|| manager
+        .get_plugin("Example")
+        .is_some_and(|p| p.metadata().version == "0.3.0")));
+
+    install_plugin(plugin_dir.path(), &library, "added", "Added", ">=0.1.0");
+    assert!(wait_for(|| manager.get_plugin("Added").is_some()));
+
+    std::fs::remove_dir_all(plugin_dir.path().join("example")).unwrap();
+    assert!(wait_for(|| manager.get_plugin("Example").is_none()));
+}
+
+const HOST_ENV: &str = "SMART_TRANSFER_TEST_PLUGIN_HOST";
+
+// Lets this test binary stand in for the app as plugin host; a no-op in normal test runs
+#[test]
+fn plugin_host_entry() {
+    if std::env::var_os(HOST_ENV).is_some() {
+        let code = if plugin_host::serve_stdio().is_ok() { 0 } else { 1 };
+        std::process::exit(code);
+    }
+}
+
+#[test]
+fn test_isolated_plugin_survives_host_crash() {
+    plugin_host::set_host_command(
+        PluginHostCommand::new(std::env::current_exe().unwrap())
+            .arg("plugin_host_entry")
+            .arg("--exact")
+            .arg("--nocapture")
+            .arg("--quiet")
+            .env(HOST_ENV, "1"),
+    );
+    let library = build_example_plugin();
+    let plugin_dir = tempfile::tempdir().unwrap();
+    install_plugin(plugin_dir.path(), &library, "isolated", "Isolated", ">=0.1.0");
+    let manifest = plugin_dir.path().join("isolated").join("plugin.toml");
+    let source = std::fs::read_to_string(&manifest).unwrap();
+    std::fs::write(&manifest, source + "isolation = \"process\"\n").unwrap();
+
+    let manager = unsigned_manager();
+    assert_eq!(manager.load_external_plugins(plugin_dir.path().to_path_buf()), 1);
+    let plugin = manager.get_plugin("Isolated").unwrap();
+    let host_id = || plugin.call(|plugin| Ok(plugin.as_any().downcast_ref::<HostedPlugin>().unwrap().host_id())).unwrap();
+    assert_eq!(plugin.settings_schema().settings[0].key, "pretty");
+
+    let work = tempfile::tempdir().unwrap();
+    let input = work.path().join("notes.txt");
+    std::fs::write(&input, "isolated").unwrap();
+    let archive = work.path().join("notes.example");
+    let compress = || manager.compress("Isolated", std::slice::from_ref(&input), &archive, &CompressionOptions::default());
+    compress().unwrap();
+    manager.decompress("Isolated", &archive, &work.path().join("out"), false).unwrap();
+    assert_eq!(std::fs::read_to_string(work.path().join("out").join("notes.txt")).unwrap(), "isolated");
+    // Plugin errors come back as values, not as a crashed host
+    let err = manager.decompress("Isolated", &archive, &work.path().join("out"), false).unwrap_err();
+    assert!(err.to_string().contains("notes.txt"));
+    assert_eq!(ErrorPayload::from(err).code, ErrorCode::AlreadyExists);
+
+    // Simulate a crash; the app keeps running and the next call gets a fresh host
+    let crashed = host_id().unwrap();
+    #[cfg(unix)]
+    let killed = Command::new("kill").arg("-9").arg(crashed.to_string()).status().unwrap();
+    #[cfg(windows)]
+    let killed = Command::new("taskkill").args(["/F", "/PID", &crashed.to_string()]).status().unwrap();
+    assert!(killed.success());
+    if let Err(e) = compress() {
+        assert!(e.to_string().contains("crashed"), "{}", e);
+        compress().unwrap();
+    }
+    assert_ne!(host_id().unwrap(), crashed);
+}
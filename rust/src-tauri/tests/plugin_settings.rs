@@ -0,0 +1,76 @@
+use smart_transfer::core::plugin_manager::PluginManager;
+use smart_transfer::core::plugin_settings::PluginSettingsStore;
+use smart_transfer::plugin_api::{ExtractionLimits, PluginSettings};
+use serde_json::json;
+
+fn manager_with_settings(settings_file: &std::path::Path) -> PluginManager {
+    let mut manager = PluginManager::new();
+    let store = PluginSettingsStore::load_from(settings_file).unwrap();
+    manager.set_settings(store, settings_file.to_path_buf());
+    manager.register_default_plugins().unwrap();
+    manager
+}
+
+fn level(value: serde_json::Value) -> PluginSettings {
+    let mut settings = PluginSettings::new();
+    settings.insert("level".to_string(), value);
+    settings
+}
+
+// Same path as the `get_plugin_settings` and `set_plugin_settings` commands
+#[test]
+fn test_settings_are_validated_and_persisted() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings_file = dir.path().join("plugin_settings.json");
+
+    let manager = manager_with_settings(&settings_file);
+    let view = manager.plugin_settings("ZIP Plugin").unwrap();
+    assert_eq!(view.schema.settings[0].key, "level");
+    assert_eq!(view.values["level"], json!(6));
+
+    let err = manager.set_plugin_settings("ZIP Plugin", level(json!(12))).unwrap_err();
+    assert!(err.to_string().contains("out of range"));
+    let err = manager.set_plugin_settings("ZIP Plugin", level(json!(true))).unwrap_err();
+    assert!(err.to_string().contains("expected an integer"));
+    assert!(!settings_file.exists());
+
+    let in_use = manager.get_plugin("ZIP Plugin").unwrap();
+    let view = manager.set_plugin_settings("ZIP Plugin", level(json!(9))).unwrap();
+    assert_eq!(view.values["level"], json!(9));
+    // Running jobs keep the instance they started with
+    assert_eq!(in_use.settings()["level"], json!(6));
+    assert_eq!(manager.get_plugin("ZIP Plugin").unwrap().settings()["level"], json!(9));
+    drop(manager);
+
+    let restarted = manager_with_settings(&settings_file);
+    assert_eq!(restarted.plugin_settings("ZIP Plugin").unwrap().values["level"], json!(9));
+    assert!(restarted.set_plugin_settings("missing", PluginSettings::new()).is_err());
+}
+
+#[test]
+fn test_plugins_without_settings_have_an_empty_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let manager = manager_with_settings(&dir.path().join("plugin_settings.json"));
+    let view = manager.plugin_settings("7-Zip Plugin").unwrap();
+    assert!(view.schema.is_empty());
+    assert!(view.values.is_empty());
+}
+
+// Same path as the `set_extraction_limits` command
+#[test]
+fn test_extraction_limits_are_persisted() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings_file = dir.path().join("plugin_settings.json");
+    let limits = ExtractionLimits {
+        max_entries: Some(10),
+        ..ExtractionLimits::unlimited()
+    };
+
+    let manager = manager_with_settings(&settings_file);
+    assert_eq!(manager.extraction_limits(), ExtractionLimits::default());
+    manager.set_extraction_limits(limits).unwrap();
+    drop(manager);
+
+    let restarted = manager_with_settings(&settings_file);
+    assert_eq!(restarted.extraction_limits(), limits);
+}